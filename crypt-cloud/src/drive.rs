@@ -3,10 +3,14 @@ use async_recursion::async_recursion;
 use crypt_core::{
     common::DirInfo,
     common::{FileInfo, FsNode},
+    config,
+    error::TokenError,
+    events::{emit, Event},
     token::UserToken,
 };
+use futures_util::{stream, StreamExt};
 use reqwest::{
-    header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_RANGE, LOCATION},
+    header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_RANGE, LOCATION, RANGE},
     Client, Response,
 };
 use serde_json::Value;
@@ -14,11 +18,24 @@ use std::{
     collections::HashMap,
     path::{Path, PathBuf},
 };
-use tokio::{fs::File, io::AsyncReadExt, runtime::Runtime};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt},
+    runtime::Runtime,
+};
 
 const GOOGLE_FOLDER: &str = "Crypt";
 const CHUNK_SIZE: usize = 5_242_880; //5MB
 
+/// Pulls the Drive file/folder `id` out of a Drive API JSON response body,
+/// erroring if the field is absent (e.g. the body is an error payload).
+fn extract_id(response: &Value) -> Result<String> {
+    response["id"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or(Error::FileIdError)
+}
+
 /// <b>Asynchronously</b> sends an HTTP GET request to the specified URL with the provided user credentials.
 ///
 /// # Arguments
@@ -34,12 +51,33 @@ const CHUNK_SIZE: usize = 5_242_880; //5MB
 ///
 /// This function could panic if `reqwest` crate fails to create a new `Client`
 pub async fn request_url(url: &str, creds: &UserToken) -> Result<Response> {
-    let client = reqwest::Client::new();
+    request_url_with_client(&reqwest::Client::new(), url, creds).await
+}
+
+/// Same as [`request_url`], but issues the request through a caller-supplied
+/// client instead of constructing a fresh one. Callers that fire off many
+/// requests at once (e.g. `walk_cloud`'s concurrent sibling-folder fetches)
+/// should share one client -- building a fresh `reqwest::Client` per call adds
+/// enough connector setup overhead to serialize otherwise-concurrent requests.
+async fn request_url_with_client(
+    client: &reqwest::Client,
+    url: &str,
+    creds: &UserToken,
+) -> Result<Response> {
     let response = client
         .get(url)
         .bearer_auth(&creds.access_token)
         .send()
         .await?;
+
+    // A 401 here means the token was rejected right now, regardless of what our
+    // locally-cached (and possibly clock-skewed) expiration said -- surface it
+    // as the same expired-token error the caller already knows to react to by
+    // re-authenticating, rather than a generic response error.
+    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        return Err(crypt_core::prelude::Error::TokenError(TokenError::ExpiredToken).into());
+    }
+
     Ok(response)
 }
 
@@ -67,11 +105,97 @@ pub async fn g_id_exists(user_token: &UserToken, id: &str) -> Result<bool> {
     }
 }
 
+/// Checks that a Drive file id exists, isn't trashed, and its remote name matches
+/// `expected_name` -- used to make sure we don't re-associate a local crypt with
+/// an unrelated cloud file just because the id happens to still resolve.
+pub async fn g_id_matches_name(
+    user_token: &UserToken,
+    id: &str,
+    expected_name: &str,
+) -> Result<bool> {
+    //Create the URL, we don't care about trashed items
+    let url = format!(
+        "https://www.googleapis.com/drive/v3/files/{}?fields=trashed,name",
+        id,
+    );
+
+    //Send the url and get the response
+    let response = request_url(&url, user_token).await?;
+
+    match response.status() {
+        reqwest::StatusCode::OK => {
+            let file = response.json::<Value>().await?;
+            let trashed = file["trashed"].as_bool().unwrap_or(true);
+            let name = file["name"].as_str().unwrap_or_default();
+            return Ok(!trashed && name == expected_name);
+        }
+        reqwest::StatusCode::NOT_FOUND => return Ok(false),
+        _ => {
+            let error = response.json::<Value>().await?;
+            return Err(Error::GeneralQueryError(error));
+        }
+    }
+}
+
+/// An account's Drive storage quota, as reported by `about?fields=storageQuota`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quota {
+    /// Total storage allotted to the account, in bytes. `None` if the account
+    /// has unlimited storage, in which case Drive omits `limit` entirely.
+    pub limit: Option<u64>,
+    /// Total storage used across all Google services, in bytes.
+    pub usage: u64,
+    /// Storage used by Drive specifically (a subset of `usage`), in bytes.
+    pub usage_in_drive: u64,
+}
+
+/// Fetches the account's storage quota via `about?fields=storageQuota`, so
+/// callers can check remaining space before a big upload or report it to the user.
+pub async fn g_quota(user_token: &UserToken) -> Result<Quota> {
+    g_quota_from_url(
+        "https://www.googleapis.com/drive/v3/about?fields=storageQuota",
+        user_token,
+    )
+    .await
+}
+
+/// Implementation behind [`g_quota`], taking the request URL directly so
+/// tests can point it at a mock server instead of Google's API.
+async fn g_quota_from_url(url: &str, user_token: &UserToken) -> Result<Quota> {
+    //Send the url and get the response
+    let response = request_url(url, user_token).await?;
+
+    if !response.status().is_success() {
+        let error = response.json::<Value>().await?;
+        return Err(Error::GeneralQueryError(error));
+    }
+
+    let body = response.json::<Value>().await?;
+    let quota = &body["storageQuota"];
+
+    let parse_u64 = |value: &Value| value.as_str().and_then(|s| s.parse::<u64>().ok());
+
+    Ok(Quota {
+        limit: parse_u64(&quota["limit"]),
+        usage: parse_u64(&quota["usage"]).unwrap_or(0),
+        usage_in_drive: parse_u64(&quota["usageInDrive"]).unwrap_or(0),
+    })
+}
+
 ///Parse the drive and create the folder if it doesn't exist
 pub async fn g_create_folder(
     user_token: &UserToken,
     path: Option<&PathBuf>,
     parent: &str,
+) -> Result<String> {
+    g_create_folder_from_url(user_token, path, parent, "https://www.googleapis.com").await
+}
+
+async fn g_create_folder_from_url(
+    user_token: &UserToken,
+    path: Option<&PathBuf>,
+    parent: &str,
+    base_url: &str,
 ) -> Result<String> {
     let save_path = match path {
         Some(p) => p.to_str().unwrap(),
@@ -95,7 +219,7 @@ pub async fn g_create_folder(
         }
     };
 
-    let url = format!("https://www.googleapis.com/drive/v3/files?q={}", query);
+    let url = format!("{}/drive/v3/files?q={}", base_url, query);
 
     //Send the url and get the response
     let response = request_url(&url, user_token).await?;
@@ -110,9 +234,9 @@ pub async fn g_create_folder(
     for item in folders["files"].as_array().unwrap_or(&vec![]) {
         // dbg!(&item);
         if item["name"].as_str() == Some(save_path) {
-            if let Some(id) = item["id"].as_str() {
-                // dbg!(&path, &parent, &id.to_string());
-                return Ok(id.to_string());
+            if let Ok(id) = extract_id(item) {
+                // dbg!(&path, &parent, &id);
+                return Ok(id);
             }
         }
     }
@@ -129,14 +253,50 @@ pub async fn g_create_folder(
         }),
     };
     //If folder doesn't exist, create new folder
-    return Ok(Client::new()
-        .post("https://www.googleapis.com/drive/v3/files")
+    let response = Client::new()
+        .post(format!("{}/drive/v3/files", base_url))
         .bearer_auth(&user_token.access_token)
         .json(&json)
         .send()
-        .await?
-        .text()
-        .await?);
+        .await?;
+
+    if !response.status().is_success() {
+        let error = response.json::<Value>().await?;
+        return Err(Error::GeneralQueryError(error));
+    }
+
+    let created = response.json::<Value>().await?;
+    extract_id(&created)
+}
+
+/// Resolves (creating any missing intermediate folders) a `/`-separated
+/// nested folder path under `root`, returning the id of the innermost folder.
+/// An empty `path` returns `root` unchanged.
+pub async fn g_create_folder_path(
+    user_token: &UserToken,
+    root: &str,
+    path: &Path,
+) -> Result<String> {
+    g_create_folder_path_from_url(user_token, root, path, "https://www.googleapis.com").await
+}
+
+async fn g_create_folder_path_from_url(
+    user_token: &UserToken,
+    root: &str,
+    path: &Path,
+    base_url: &str,
+) -> Result<String> {
+    let mut parent = root.to_string();
+    for component in path.iter() {
+        parent = g_create_folder_from_url(
+            user_token,
+            Some(&PathBuf::from(component)),
+            &parent,
+            base_url,
+        )
+        .await?;
+    }
+    Ok(parent)
 }
 
 ///Updates a file that already exists on google drive
@@ -167,11 +327,30 @@ pub async fn g_update(user_token: &UserToken, id: &str, path: &str) -> Result<St
         .to_str()?
         .to_owned();
 
-    return upload_chunks(&session_uri, &mut file, file_size).await;
+    return upload_chunks(&session_uri, &mut file, file_size, path).await;
 }
 
 ///Uploads a file to google drive
 pub async fn g_upload(user_token: &UserToken, path: &str, parent: &str) -> Result<String> {
+    g_upload_with_content_type(user_token, path, parent, "application/x-crypt").await
+}
+
+/// Uploads a file to google drive as-is, with no `FileCrypt`/keeper involvement --
+/// for backing up already-encrypted or non-sensitive files without running them
+/// through ByteCrypt's encryption. The content type is guessed from the file's
+/// extension rather than the fixed `application/x-crypt` [`g_upload`] uses.
+pub async fn g_upload_raw(user_token: &UserToken, path: &str, parent: &str) -> Result<String> {
+    let content_type = mime_guess::from_path(path).first_or_octet_stream();
+    g_upload_with_content_type(user_token, path, parent, content_type.essence_str()).await
+}
+
+///Shared resumable-upload implementation behind [`g_upload`] and [`g_upload_raw`].
+async fn g_upload_with_content_type(
+    user_token: &UserToken,
+    path: &str,
+    parent: &str,
+    content_type: &str,
+) -> Result<String> {
     //Get file content
     let mut file = File::open(path).await?;
     // let mut tmp; // to appease the compiler gods
@@ -187,8 +366,7 @@ pub async fn g_upload(user_token: &UserToken, path: &str, parent: &str) -> Resul
             "name": file_name,
             "parents": [parent]
         }))
-        //application/octet-stream for unknown file types
-        .header("X-Upload-Content-Type", "application/x-crypt")
+        .header("X-Upload-Content-Type", content_type)
         .send()
         .await?;
 
@@ -199,11 +377,16 @@ pub async fn g_upload(user_token: &UserToken, path: &str, parent: &str) -> Resul
         .to_str()?
         .to_string();
 
-    return upload_chunks(&session_uri, &mut file, file_size).await;
+    return upload_chunks(&session_uri, &mut file, file_size, path).await;
 }
 
 ///Helper function that performs the upload of file information
-async fn upload_chunks(session_uri: &str, file: &mut File, file_size: u64) -> Result<String> {
+async fn upload_chunks(
+    session_uri: &str,
+    file: &mut File,
+    file_size: u64,
+    path: &str,
+) -> Result<String> {
     let client = reqwest::Client::new();
 
     let mut start = 0;
@@ -225,10 +408,18 @@ async fn upload_chunks(session_uri: &str, file: &mut File, file_size: u64) -> Re
 
         match inner_response.status().as_u16() {
             308 => {
-                //Incomplete continue
-                //if log, place log here
+                emit(Event::UploadProgress {
+                    path: PathBuf::from(path),
+                    bytes_done: start + bytes_read as u64,
+                    total_bytes: file_size,
+                });
             }
             200 | 201 => {
+                emit(Event::UploadProgress {
+                    path: PathBuf::from(path),
+                    bytes_done: file_size,
+                    total_bytes: file_size,
+                });
                 let body = inner_response.json::<Value>().await?;
                 if let Some(id) = body["id"].as_str() {
                     return Ok(id.to_string());
@@ -236,7 +427,9 @@ async fn upload_chunks(session_uri: &str, file: &mut File, file_size: u64) -> Re
                     return Err(Error::FileIdError);
                 }
             }
-            //TODO: Deal with HTTP 401 Unauthorized Error
+            401 => {
+                return Err(crypt_core::prelude::Error::TokenError(TokenError::ExpiredToken).into());
+            }
             status => {
                 return Err(Error::ResponseError(status));
             }
@@ -347,7 +540,10 @@ pub async fn g_walk(user_token: &UserToken, name: &str) -> Result<DirInfo> {
         "name = '{}' and mimeType = 'application/vnd.google-apps.folder' and trashed = false",
         name
     );
-    let url = format!("https://www.googleapis.com/drive/v3/files?q={}", query);
+    let url = format!(
+        "https://www.googleapis.com/drive/v3/files?q={}&supportsAllDrives=true&includeItemsFromAllDrives=true",
+        query
+    );
 
     //Send the url and get the response
     let response = request_url(&url, user_token).await?;
@@ -370,6 +566,14 @@ pub async fn g_walk(user_token: &UserToken, name: &str) -> Result<DirInfo> {
     return Err(Error::FolderNotFoundError);
 }
 
+///Walks the google drive folder identified directly by `folder_id`, rather than
+///searching for it by name -- for a folder resolved via [`g_create_folder_path`],
+///which may not be uniquely identifiable by name alone.
+pub async fn g_walk_by_id(user_token: &UserToken, folder_id: &str) -> Result<DirInfo> {
+    let client = reqwest::Client::new();
+    walk_cloud(user_token, &client, folder_id).await
+}
+
 ///
 pub async fn google_query_folders(
     user_token: &UserToken,
@@ -487,20 +691,162 @@ pub async fn google_query_file(user_token: &UserToken, file_id: &str) -> Result<
     Ok(text)
 }
 
+/// How many range requests [`download_to_file`] will attempt before giving up.
+const MAX_DOWNLOAD_RETRIES: u32 = 5;
+
+/// Streams file `file_id` to `dest` on disk, resuming from wherever a prior
+/// attempt left off instead of restarting from byte zero. Downloads to a
+/// `.part` sibling of `dest` and renames it into place once complete, so a
+/// crash mid-download can't be mistaken for a finished file.
+///
+/// Makes up to [`MAX_DOWNLOAD_RETRIES`] attempts in all on a dropped connection,
+/// resuming each attempt with a `Range: bytes={downloaded}-` request.
+pub async fn download_to_file(user_token: &UserToken, file_id: &str, dest: &Path) -> Result<()> {
+    let url = format!(
+        "https://www.googleapis.com/drive/v3/files/{}?alt=media&source=downloadUrl",
+        file_id
+    );
+    download_to_file_from_url(&url, user_token, file_id, dest).await
+}
+
+/// Retry/resume loop behind [`download_to_file`], taking the download URL
+/// directly so tests can point it at a mock server instead of Google's API.
+async fn download_to_file_from_url(
+    url: &str,
+    user_token: &UserToken,
+    file_id: &str,
+    dest: &Path,
+) -> Result<()> {
+    let tmp_path = dest.with_extension("part");
+    let mut last_error = None;
+
+    for _attempt in 0..MAX_DOWNLOAD_RETRIES {
+        let downloaded = tokio::fs::metadata(&tmp_path)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+
+        match download_range(url, user_token, &tmp_path, downloaded).await {
+            Ok(total_size) => {
+                let downloaded = tokio::fs::metadata(&tmp_path).await?.len();
+                if downloaded >= total_size {
+                    tokio::fs::rename(&tmp_path, dest).await?;
+                    return Ok(());
+                }
+                // server closed the stream early without erroring -- treat as a
+                // dropped connection and let the loop retry from the new offset.
+                last_error = Some(format!(
+                    "connection closed after {} of {} bytes",
+                    downloaded, total_size
+                ));
+            }
+            Err(e) => last_error = Some(e.to_string()),
+        }
+    }
+
+    Err(Error::DownloadRetriesExhausted(
+        file_id.to_string(),
+        MAX_DOWNLOAD_RETRIES,
+        last_error.unwrap_or_default(),
+    ))
+}
+
+/// Issues a single `Range: bytes={start}-` request against `url` and appends
+/// whatever bytes arrive to `tmp_path`, returning the file's total size (parsed
+/// from `Content-Range`). A response without `Content-Range` means the server
+/// ignored the range and sent the whole file back: `tmp_path` is then rewritten
+/// from byte zero and `Content-Length` is the total size. A connection drop mid-stream leaves
+/// the bytes received so far written to `tmp_path` and surfaces as an `Err`,
+/// letting the caller retry from the new file length.
+async fn download_range(
+    url: &str,
+    user_token: &UserToken,
+    tmp_path: &Path,
+    start: u64,
+) -> Result<u64> {
+    let client = reqwest::Client::new();
+    let response = client
+        .get(url)
+        .bearer_auth(&user_token.access_token)
+        .header(RANGE, format!("bytes={}-", start))
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let error = response.json::<Value>().await?;
+        return Err(Error::GeneralQueryError(error));
+    }
+
+    let ranged_total = response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.rsplit('/').next())
+        .and_then(|s| s.parse::<u64>().ok());
+    let resumed = ranged_total.is_some();
+    let total_size = ranged_total
+        .or_else(|| {
+            response
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|s| s.parse::<u64>().ok())
+        })
+        .ok_or(Error::HeaderError("CONTENT_LENGTH"))?;
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(tmp_path)
+        .await?;
+
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        file.write_all(&chunk).await?;
+    }
+
+    Ok(total_size)
+}
+
 ///Walks google drive to get all of the files within their respective folders
-#[async_recursion]
 async fn walk_cloud(
     user_token: &UserToken,
     client: &reqwest::Client,
     folder_id: &str,
+) -> Result<DirInfo> {
+    walk_cloud_from_url(user_token, client, folder_id, "https://www.googleapis.com").await
+}
+
+///Walks google drive to get all of the files within their respective folders.
+///
+///Resolves shortcut items (`application/vnd.google-apps.shortcut`) to the folder
+///or file they point at via `shortcutDetails`, so a shortcut to another folder is
+///traversed instead of being treated as an opaque file. Requests
+///`supportsAllDrives`/`includeItemsFromAllDrives` so a Crypt folder living in (or
+///shortcut-linked from) a shared drive isn't silently treated as empty.
+///
+///Sibling subfolders are fetched concurrently, up to `cloud_walk_concurrency`
+///requests at a time (see [`config::Config::get_cloud_walk_concurrency`]), rather
+///than one at a time depth-first. `contents` is sorted by name afterward so the
+///rendered tree is stable regardless of which subfolder's request finishes first.
+#[async_recursion]
+async fn walk_cloud_from_url(
+    user_token: &UserToken,
+    client: &reqwest::Client,
+    folder_id: &str,
+    base_url: &str,
 ) -> Result<DirInfo> {
     let mut contents = Vec::new();
+    let mut subfolder_ids = Vec::new();
     let url = format!(
-        "https://www.googleapis.com/drive/v3/files?q='{}' in parents and trashed = false",
-        folder_id
+        "{}/drive/v3/files?q='{}' in parents and trashed = false&supportsAllDrives=true&includeItemsFromAllDrives=true&fields=files(id,name,mimeType,shortcutDetails)",
+        base_url, folder_id
     );
     //Send the url and get the response
-    let response = request_url(&url, user_token).await?;
+    let response = request_url_with_client(client, &url, user_token).await?;
 
     if !response.status().is_success() {
         return Err(Error::DirectoryQueryError);
@@ -512,17 +858,46 @@ async fn walk_cloud(
         for item in array {
             let name = item["name"].as_str().unwrap_or_default().to_string();
             let id = item["id"].as_str().unwrap_or_default().to_string();
+            let mime_type = item["mimeType"].as_str().unwrap_or_default();
+
+            let (id, mime_type) = if mime_type == "application/vnd.google-apps.shortcut" {
+                (
+                    item["shortcutDetails"]["targetId"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    item["shortcutDetails"]["targetMimeType"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                )
+            } else {
+                (id, mime_type.to_string())
+            };
 
-            if item["mimeType"] == "application/vnd.google-apps.folder" {
-                let dir_info = walk_cloud(user_token, client, &id).await?;
-                contents.push(FsNode::Directory(dir_info));
+            if mime_type == "application/vnd.google-apps.folder" {
+                subfolder_ids.push(id);
             } else {
                 contents.push(FsNode::File(FileInfo::new(name, id)));
             }
         }
     }
 
-    let url = format!("https://www.googleapis.com/drive/v3/files/{}", folder_id);
+    let concurrency = config::get_config().get_cloud_walk_concurrency();
+    let dir_infos: Vec<Result<DirInfo>> = stream::iter(subfolder_ids)
+        .map(|id| async move { walk_cloud_from_url(user_token, client, &id, base_url).await })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+    for dir_info in dir_infos {
+        contents.push(FsNode::Directory(dir_info?));
+    }
+    contents.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+
+    let url = format!(
+        "{}/drive/v3/files/{}?supportsAllDrives=true",
+        base_url, folder_id
+    );
 
     let dir_name = client
         .get(&url)
@@ -635,3 +1010,378 @@ pub fn google_startup() -> Result<(Runtime, UserToken, String)> {
 
     std::result::Result::Ok((runtime, user_token, crypt_folder))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_extract_id_from_creation_response() {
+        // a full Drive `files.create` response body -- only `id` should come back,
+        // not the whole JSON blob.
+        let response = serde_json::json!({
+            "kind": "drive#file",
+            "id": "1a2b3c4d5e",
+            "name": "Crypt",
+            "mimeType": "application/vnd.google-apps.folder"
+        });
+
+        assert_eq!(extract_id(&response).unwrap(), "1a2b3c4d5e");
+    }
+
+    #[test]
+    fn test_extract_id_missing_field_errors() {
+        let response = serde_json::json!({ "error": { "message": "not found" } });
+        assert!(matches!(extract_id(&response), Err(Error::FileIdError)));
+    }
+
+    fn dummy_user_token() -> UserToken {
+        UserToken {
+            service: crypt_core::token::CloudService::Google,
+            key_seed: [0u8; crypt_core::encryption::KEY_SIZE],
+            nonce_seed: [0u8; crypt_core::encryption::NONCE_SIZE],
+            expiration: u64::MAX,
+            access_token: "dummy-token".to_string(),
+        }
+    }
+
+    /// A server that drops the connection mid-stream -- it advertises 20 bytes
+    /// via `Content-Range` but only ever sends the first 10 -- should be picked
+    /// back up by a resumed `Range` request instead of failing the download.
+    #[tokio::test]
+    async fn test_download_to_file_resumes_after_dropped_connection() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let full_body = b"0123456789abcdefghij";
+        assert_eq!(full_body.len(), 20);
+
+        let mock_server = MockServer::start().await;
+
+        // First request: claims 20 bytes total but only sends the first 10,
+        // simulating a connection dropped mid-stream.
+        Mock::given(method("GET"))
+            .and(path("/file"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Range", "bytes 0-9/20")
+                    .set_body_bytes(full_body[..10].to_vec()),
+            )
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Retry: resumes from byte 10 and completes.
+        Mock::given(method("GET"))
+            .and(path("/file"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Range", "bytes 10-19/20")
+                    .set_body_bytes(full_body[10..].to_vec()),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/file", mock_server.uri());
+        let dest = std::env::temp_dir().join(format!(
+            "crypt-cloud-test-{}.download",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&dest);
+        let _ = std::fs::remove_file(dest.with_extension("part"));
+
+        download_to_file_from_url(&url, &dummy_user_token(), "file", &dest)
+            .await
+            .unwrap();
+
+        let downloaded = std::fs::read(&dest).unwrap();
+        assert_eq!(downloaded, full_body);
+
+        let _ = std::fs::remove_file(&dest);
+    }
+
+    #[tokio::test]
+    async fn test_g_quota_parses_storage_quota_response() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/about"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "storageQuota": {
+                    "limit": "16106127360",
+                    "usage": "8053063680",
+                    "usageInDrive": "4026531840",
+                    "usageInDriveTrash": "0"
+                }
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/about", mock_server.uri());
+        let quota = g_quota_from_url(&url, &dummy_user_token()).await.unwrap();
+
+        assert_eq!(quota.limit, Some(16106127360));
+        assert_eq!(quota.usage, 8053063680);
+        assert_eq!(quota.usage_in_drive, 4026531840);
+    }
+
+    #[tokio::test]
+    async fn test_g_quota_unlimited_storage_has_no_limit() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/about"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "storageQuota": {
+                    "usage": "8053063680",
+                    "usageInDrive": "4026531840"
+                }
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/about", mock_server.uri());
+        let quota = g_quota_from_url(&url, &dummy_user_token()).await.unwrap();
+
+        assert_eq!(quota.limit, None);
+        assert_eq!(quota.usage, 8053063680);
+    }
+
+    #[tokio::test]
+    async fn test_walk_cloud_resolves_shortcut_to_target_folder() {
+        use wiremock::matchers::{method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+
+        // The root folder contains only a shortcut pointing at another folder.
+        Mock::given(method("GET"))
+            .and(path("/drive/v3/files"))
+            .and(query_param(
+                "q",
+                "'root-folder-id' in parents and trashed = false",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "files": [{
+                    "id": "shortcut-id",
+                    "name": "linked",
+                    "mimeType": "application/vnd.google-apps.shortcut",
+                    "shortcutDetails": {
+                        "targetId": "target-folder-id",
+                        "targetMimeType": "application/vnd.google-apps.folder"
+                    }
+                }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        // The shortcut's target folder contains one ordinary file.
+        Mock::given(method("GET"))
+            .and(path("/drive/v3/files"))
+            .and(query_param(
+                "q",
+                "'target-folder-id' in parents and trashed = false",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "files": [{
+                    "id": "file-id",
+                    "name": "inside.txt",
+                    "mimeType": "text/plain"
+                }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/drive/v3/files/root-folder-id"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "name": "Root"
+            })))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/drive/v3/files/target-folder-id"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "name": "Linked"
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let dir = walk_cloud_from_url(
+            &dummy_user_token(),
+            &client,
+            "root-folder-id",
+            &mock_server.uri(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(dir.contents.len(), 1);
+        let FsNode::Directory(linked) = &dir.contents[0] else {
+            panic!("expected the shortcut to resolve to a Directory node");
+        };
+        assert_eq!(linked.name, "Linked");
+        assert_eq!(linked.path, "target-folder-id");
+        assert_eq!(linked.contents.len(), 1);
+        assert_eq!(linked.contents[0].get_name(), "inside.txt");
+    }
+
+    #[tokio::test]
+    async fn test_walk_cloud_fetches_sibling_folders_concurrently_and_sorts_by_name() {
+        use std::time::{Duration, Instant};
+        use wiremock::matchers::{method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+        let delay = Duration::from_millis(150);
+
+        // root contains three sibling folders, listed out of alphabetical order.
+        Mock::given(method("GET"))
+            .and(path("/drive/v3/files"))
+            .and(query_param("q", "'root-folder-id' in parents and trashed = false"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "files": [
+                    {"id": "c-id", "name": "c", "mimeType": "application/vnd.google-apps.folder"},
+                    {"id": "a-id", "name": "a", "mimeType": "application/vnd.google-apps.folder"},
+                    {"id": "b-id", "name": "b", "mimeType": "application/vnd.google-apps.folder"}
+                ]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        // each sibling is empty and childless, but takes `delay` to respond --
+        // if they were fetched sequentially this test would take ~3x as long.
+        for id in ["a-id", "b-id", "c-id"] {
+            Mock::given(method("GET"))
+                .and(path("/drive/v3/files"))
+                .and(query_param("q", format!("'{id}' in parents and trashed = false")))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_delay(delay)
+                        .set_body_json(serde_json::json!({ "files": [] })),
+                )
+                .mount(&mock_server)
+                .await;
+        }
+
+        Mock::given(method("GET"))
+            .and(path("/drive/v3/files/root-folder-id"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "name": "Root" })))
+            .mount(&mock_server)
+            .await;
+        for (id, name) in [("a-id", "a"), ("b-id", "b"), ("c-id", "c")] {
+            Mock::given(method("GET"))
+                .and(path(format!("/drive/v3/files/{id}")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "name": name })))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let client = reqwest::Client::new();
+        let started = Instant::now();
+        let dir = walk_cloud_from_url(
+            &dummy_user_token(),
+            &client,
+            "root-folder-id",
+            &mock_server.uri(),
+        )
+        .await
+        .unwrap();
+
+        // sequential would take ~3 delays; allow generous headroom above one
+        // delay for scheduling jitter under a loaded test runner while still
+        // being well short of what a sequential fetch would take.
+        assert!(
+            started.elapsed() < delay * 3 - Duration::from_millis(50),
+            "siblings do not appear to have been fetched concurrently"
+        );
+
+        let names: Vec<&str> = dir.contents.iter().map(FsNode::get_name).collect();
+        assert_eq!(names, vec!["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_g_create_folder_path_creates_missing_intermediate_folders() {
+        use wiremock::matchers::{body_partial_json, method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+
+        // "a" doesn't exist under root yet -- must be created.
+        Mock::given(method("GET"))
+            .and(path("/drive/v3/files"))
+            .and(query_param(
+                "q",
+                "name = 'a' and mimeType = 'application/vnd.google-apps.folder' and trashed = false and 'root-folder-id' in parents",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "files": [] })))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/drive/v3/files"))
+            .and(body_partial_json(serde_json::json!({
+                "name": "a",
+                "parents": ["root-folder-id"]
+            })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "id": "a-id" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // "b" already exists under "a" -- must be reused, not recreated.
+        Mock::given(method("GET"))
+            .and(path("/drive/v3/files"))
+            .and(query_param(
+                "q",
+                "name = 'b' and mimeType = 'application/vnd.google-apps.folder' and trashed = false and 'a-id' in parents",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "files": [{
+                    "id": "b-id",
+                    "name": "b",
+                    "mimeType": "application/vnd.google-apps.folder"
+                }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let id = g_create_folder_path_from_url(
+            &dummy_user_token(),
+            "root-folder-id",
+            Path::new("a/b"),
+            &mock_server.uri(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(id, "b-id");
+    }
+
+    #[tokio::test]
+    async fn test_g_create_folder_path_empty_path_returns_root_unchanged() {
+        let id = g_create_folder_path_from_url(
+            &dummy_user_token(),
+            "root-folder-id",
+            Path::new(""),
+            "https://unused.invalid",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(id, "root-folder-id");
+    }
+}
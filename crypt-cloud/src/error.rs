@@ -34,6 +34,9 @@ pub enum Error {
     #[error("Failed to upload.")]
     UploadError,
 
+    #[error("download of file {0} did not complete after {1} attempts: {2}")]
+    DownloadRetriesExhausted(String, u32, String),
+
     #[error("Error acessing root 'crypt' directory.")]
     RootDirectoryError,
 
@@ -38,7 +38,7 @@ static SHAKESPEARE_DECRYPT: &str = "benches\\files\\Shakespeare-decrypted.txt";
 // encrypt test with 850kb file
 pub fn enc_benchmark(c: &mut Criterion) {
     c.bench_function("full file encryption (dracula.txt)", |b| {
-        b.iter(|| encrypt_file(DRACULA, &None))
+        b.iter(|| encrypt_file(DRACULA, &None, false, false, false, false))
     });
 }
 
@@ -73,7 +73,7 @@ pub fn shakespeare_content_encryption(c: &mut Criterion) {
 // encrypt test with 5mb file
 pub fn enc_benchmark_large(c: &mut Criterion) {
     c.bench_function("full file encryption (shakespeare)", |b| {
-        b.iter(|| encrypt_file(SHAKESPEARE, &None))
+        b.iter(|| encrypt_file(SHAKESPEARE, &None, false, false, false, false))
     });
 }
 
@@ -105,14 +105,14 @@ pub fn enc_benchmark_large(c: &mut Criterion) {
 // decrypt test with 850kb file
 pub fn dec_benchmark(c: &mut Criterion) {
     c.bench_function("decrypt dracula", |b| {
-        b.iter(|| crate::filecrypt::decrypt_file(DRACULA_CRYPT, String::from("")))
+        b.iter(|| crate::filecrypt::decrypt_file(DRACULA_CRYPT, String::from(""), false, false))
     });
 }
 
 // decrypt test with 5mb file
 pub fn dec_benchmark_large(c: &mut Criterion) {
     c.bench_function("decrypt Shakespeare", |b| {
-        b.iter(|| crate::filecrypt::decrypt_file(SHAKESPEARE_CRYPT, String::from("")))
+        b.iter(|| crate::filecrypt::decrypt_file(SHAKESPEARE_CRYPT, String::from(""), false, false))
     });
 }
 
@@ -125,6 +125,21 @@ pub fn test_compute_hash(c: &mut Criterion) {
     });
 }
 
+// compare Blake2s against Blake3 throughput on a larger file, to sanity-check
+// where `hash_parallel_threshold` is worth setting.
+pub fn hash_algorithm_comparison(c: &mut Criterion) {
+    let contents: Vec<u8> = std::fs::read(SHAKESPEARE).unwrap();
+
+    let mut group = c.benchmark_group("hash algorithm comparison (Shakespeare.txt)");
+    group.bench_function("blake2s", |b| {
+        b.iter(|| crate::encryption::compute_hash_with_algorithm(&contents, encryption::HashAlgorithm::Blake2s))
+    });
+    group.bench_function("blake3", |b| {
+        b.iter(|| crate::encryption::compute_hash_with_algorithm(&contents, encryption::HashAlgorithm::Blake3))
+    });
+    group.finish();
+}
+
 // test generation of a 26 digit uuid
 pub fn test_generate_uuid(c: &mut Criterion) {
     c.bench_function("generate 26 digit uuid", |b| {
@@ -179,6 +194,7 @@ criterion_group!(
     dec_benchmark,
     dec_benchmark_large,
     test_compute_hash,
+    hash_algorithm_comparison,
     test_generate_uuid,
     cleanup
 );
@@ -0,0 +1,138 @@
+//! Packing the crypt folder and keeper database into a single portable
+//! archive for offsite backup, and restoring one back onto disk.
+//!
+//! The bundle is a plain tarball of the crypt folder plus the keeper
+//! database, optionally wrapped with passphrase encryption reusing the same
+//! `derive_key_from_passphrase`/`encrypt_bytes_with_key` primitives as the
+//! private-metadata feature.
+
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::Path,
+};
+
+use logfather::info;
+use tar::{Archive, Builder};
+
+use crate::{
+    encryption::{decrypt_bytes_with_key, derive_key_from_passphrase, encrypt_bytes_with_key},
+    prelude::*,
+};
+
+/// Name the crypt folder is stored under inside the archive.
+const CRYPT_DIR_IN_BUNDLE: &str = "crypt";
+/// Name the keeper database is stored under inside the archive.
+const DATABASE_IN_BUNDLE: &str = "crypt_keeper.db";
+
+/// Tars `crypt_folder` and `database_path` into `out_path`, optionally
+/// encrypting the resulting archive with `passphrase`.
+pub fn export_bundle(
+    crypt_folder: &Path,
+    database_path: &Path,
+    out_path: &Path,
+    passphrase: Option<&str>,
+) -> Result<()> {
+    info!("exporting crypt bundle to {}", out_path.display());
+
+    let mut tar_bytes = Vec::new();
+    {
+        let mut builder = Builder::new(&mut tar_bytes);
+        if crypt_folder.is_dir() {
+            builder.append_dir_all(CRYPT_DIR_IN_BUNDLE, crypt_folder)?;
+        }
+        if database_path.is_file() {
+            builder.append_path_with_name(database_path, DATABASE_IN_BUNDLE)?;
+        }
+        builder.finish()?;
+    }
+
+    let out_bytes = match passphrase {
+        Some(pass) => encrypt_bytes_with_key(&derive_key_from_passphrase(pass), &tar_bytes)?,
+        None => tar_bytes,
+    };
+
+    File::create(out_path)?.write_all(&out_bytes)?;
+    Ok(())
+}
+
+/// Restores a bundle produced by `export_bundle` into `target_dir`, placing
+/// the crypt folder at `target_dir/crypt` and the keeper database at
+/// `target_dir/crypt_keeper.db`.
+pub fn import_bundle(bundle_path: &Path, target_dir: &Path, passphrase: Option<&str>) -> Result<()> {
+    info!("importing crypt bundle from {}", bundle_path.display());
+
+    let mut in_bytes = Vec::new();
+    File::open(bundle_path)?.read_to_end(&mut in_bytes)?;
+
+    let tar_bytes = match passphrase {
+        Some(pass) => decrypt_bytes_with_key(&derive_key_from_passphrase(pass), &in_bytes)?,
+        None => in_bytes,
+    };
+
+    fs::create_dir_all(target_dir)?;
+    Archive::new(tar_bytes.as_slice()).unpack(target_dir)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_export_import_bundle_round_trip() {
+        let temp = std::env::temp_dir().join("crypt_bundle_round_trip_test");
+        let crypt_folder = temp.join("crypt");
+        let database_path = temp.join("crypt_keeper.db");
+        let bundle_path = temp.join("backup.cryptbundle");
+        let restore_dir = temp.join("restored");
+        _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(&crypt_folder).unwrap();
+        fs::write(crypt_folder.join("secret.crypt"), b"encrypted contents").unwrap();
+        fs::write(&database_path, b"pretend sqlite database").unwrap();
+
+        export_bundle(
+            &crypt_folder,
+            &database_path,
+            &bundle_path,
+            Some("correct horse battery staple"),
+        )
+        .unwrap();
+
+        import_bundle(
+            &bundle_path,
+            &restore_dir,
+            Some("correct horse battery staple"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read(restore_dir.join("crypt").join("secret.crypt")).unwrap(),
+            b"encrypted contents"
+        );
+        assert_eq!(
+            fs::read(restore_dir.join("crypt_keeper.db")).unwrap(),
+            b"pretend sqlite database"
+        );
+
+        _ = fs::remove_dir_all(&temp);
+    }
+
+    #[test]
+    fn test_import_bundle_wrong_passphrase_fails() {
+        let temp = std::env::temp_dir().join("crypt_bundle_wrong_passphrase_test");
+        let crypt_folder = temp.join("crypt");
+        let database_path = temp.join("crypt_keeper.db");
+        let bundle_path = temp.join("backup.cryptbundle");
+        _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(&crypt_folder).unwrap();
+        fs::write(crypt_folder.join("secret.crypt"), b"encrypted contents").unwrap();
+        fs::write(&database_path, b"pretend sqlite database").unwrap();
+
+        export_bundle(&crypt_folder, &database_path, &bundle_path, Some("right")).unwrap();
+
+        assert!(import_bundle(&bundle_path, &temp.join("restored"), Some("wrong")).is_err());
+
+        _ = fs::remove_dir_all(&temp);
+    }
+}
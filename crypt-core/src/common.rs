@@ -1,15 +1,20 @@
 use std::{
     fmt::Display,
     fs::{File, OpenOptions},
-    io::{self, BufReader, Write},
+    io::{self, BufReader, BufWriter, Write},
     path::{Path, PathBuf},
     process::Command,
     time::SystemTime,
 };
 use walkdir::WalkDir;
 
-use crate::{config, error, prelude::*};
+use crate::{
+    config, error,
+    events::{emit, Event},
+    prelude::*,
+};
 use ansi_term::Color;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 /// given a path, dissect and return a struct containing the full path, is_dir, parent path, and name.
@@ -25,7 +30,7 @@ impl PathInfo {
     pub fn new(path: &str) -> Self {
         let full_path = match path.is_empty() {
             true => std::env::current_dir().unwrap(),
-            false => get_full_file_path(path),
+            false => get_full_file_path(expand_tilde(path)),
         };
 
         Self {
@@ -257,6 +262,69 @@ where
     Ok(relative_path.to_owned())
 }
 
+/// Character set `build_tree`/`tree_recursion` (and [`crate::filetree::treeprint::print_tree`])
+/// draw box-drawing connectors with. `Ascii` degrades gracefully on terminals/fonts that
+/// can't render Unicode box-drawing characters; `Plain` drops connectors entirely in favor
+/// of simple indentation.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TreeCharset {
+    #[default]
+    Unicode,
+    Ascii,
+    Plain,
+}
+
+impl TreeCharset {
+    fn joint(&self) -> &'static str {
+        match self {
+            Self::Unicode => " ├──",
+            Self::Ascii => " |--",
+            Self::Plain => "    ",
+        }
+    }
+
+    fn node(&self) -> &'static str {
+        match self {
+            Self::Unicode => " ╰──",
+            Self::Ascii => " `--",
+            Self::Plain => "    ",
+        }
+    }
+
+    fn vline(&self) -> &'static str {
+        match self {
+            Self::Unicode => " │  ",
+            Self::Ascii => " |  ",
+            Self::Plain => "    ",
+        }
+    }
+}
+
+impl std::fmt::Display for TreeCharset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unicode => write!(f, "unicode"),
+            Self::Ascii => write!(f, "ascii"),
+            Self::Plain => write!(f, "plain"),
+        }
+    }
+}
+
+impl std::str::FromStr for TreeCharset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "unicode" => Ok(Self::Unicode),
+            "ascii" => Ok(Self::Ascii),
+            "plain" => Ok(Self::Plain),
+            _ => Err(Error::CommonError(error::CommonError::InvalidTreeCharset(
+                s.to_string(),
+            ))),
+        }
+    }
+}
+
 ///Builds a file tree with given DirInfo struct
 ///
 /// # Arguments
@@ -276,7 +344,8 @@ pub fn build_tree(dir_info: &DirInfo) -> Vec<String> {
         bracket_color.paint("]").to_string().as_str(),
         dir_color.paint(&dir_info.name).to_string().as_str()
     ));
-    tree_recursion(dir_info, String::new(), &mut tree);
+    let charset = config::get_config().get_tree_charset();
+    tree_recursion(dir_info, String::new(), &mut tree, charset);
     tree
 }
 
@@ -302,7 +371,6 @@ pub fn build_tree(dir_info: &DirInfo) -> Vec<String> {
 /// # Configuration Options
 ///
 /// - TODO: Consider adding a configuration choice for ordering folders or files first.
-/// - TODO: Implement a more flexible configuration system for character sets and colors.
 /// - TODO: Improve the handling of UI-related configurations.
 ///
 /// # Notes
@@ -313,9 +381,8 @@ pub fn build_tree(dir_info: &DirInfo) -> Vec<String> {
 /// # TODO
 ///
 /// - Consider adding a configuration choice for ordering folders or files first.
-/// - Implement a more flexible configuration system for character sets and colors.
 /// - Improve the handling of UI-related configurations.
-fn tree_recursion(dir_info: &DirInfo, path: String, tree: &mut Vec<String>) {
+fn tree_recursion(dir_info: &DirInfo, path: String, tree: &mut Vec<String>, charset: TreeCharset) {
     //Force files first
     //TODO: make a config choice if folders or files first
     let (mut contents, other_content): (Vec<_>, Vec<_>) = dir_info
@@ -325,17 +392,14 @@ fn tree_recursion(dir_info: &DirInfo, path: String, tree: &mut Vec<String>) {
     contents.extend(other_content);
 
     //Character set and color
-    //TODO: make a part of config and implement properly with UI
     let dir_color = Color::Blue.bold();
     let expanded_color = Color::Green.bold();
     let bracket_color = Color::White.bold();
 
     //Set up the formatted values
-    let joint = format!(" {}{}{}", '├', '─', '─');
-
-    let node = format!(" {}{}{}", '╰', '─', '─');
-
-    let vline = format!(" {}  ", '│');
+    let joint = charset.joint();
+    let node = charset.node();
+    let vline = charset.vline();
 
     //Iterate through contents and add them to the tree
     let contents_len = contents.len();
@@ -343,7 +407,7 @@ fn tree_recursion(dir_info: &DirInfo, path: String, tree: &mut Vec<String>) {
         //Determine if the current entity is last
         let is_last = index == contents_len - 1;
         //Create the prefix
-        let prefix = format!("{}{}", path, if is_last { &node } else { &joint });
+        let prefix = format!("{}{}", path, if is_last { node } else { joint });
 
         match entity {
             FsNode::File(file) => tree.push(prefix.clone() + " " + &file.name),
@@ -364,10 +428,10 @@ fn tree_recursion(dir_info: &DirInfo, path: String, tree: &mut Vec<String>) {
                 let sub_path = if is_last {
                     path.clone() + "    "
                 } else {
-                    path.clone() + &vline
+                    path.clone() + vline
                 };
                 if subdir.expanded {
-                    tree_recursion(subdir, sub_path, tree);
+                    tree_recursion(subdir, sub_path, tree, charset);
                 }
             }
         }
@@ -395,22 +459,80 @@ pub fn get_file_contents<T: AsRef<Path>>(path: T) -> Result<Vec<u8>> {
 ///
 /// Returns a `Result` indicating whether the write operation was successful.
 ///
+/// Size of each chunk [`write_contents_to_file`] writes through its `BufWriter`,
+/// so a large `contents` buffer doesn't need to be copied into the OS in one shot.
+const WRITE_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Writes `contents` to `file` in bounded `WRITE_CHUNK_SIZE` chunks through a
+/// `BufWriter`, then `fsync`s both the file and its parent directory so the
+/// write and the directory entry it creates are durable on disk -- interim
+/// hardening for large writes ahead of real streaming encryption.
 pub fn write_contents_to_file<T: AsRef<Path>>(file: T, contents: Vec<u8>) -> Result<()> {
-    let mut f = OpenOptions::new()
+    let file = file.as_ref();
+    let f = OpenOptions::new()
         .write(true)
         .create(true)
         .read(true)
         .truncate(true)
-        .open(file.as_ref())?;
-    f.write_all(contents.as_slice())?;
-    f.flush()?;
+        .open(file)?;
+
+    let mut writer = BufWriter::with_capacity(WRITE_CHUNK_SIZE, f);
+    for chunk in contents.chunks(WRITE_CHUNK_SIZE) {
+        writer.write_all(chunk)?;
+    }
+    writer.flush()?;
+
+    let f = writer
+        .into_inner()
+        .map_err(|e| Error::IoError(e.into_error()))?;
+    f.sync_all()?;
+
+    sync_parent_dir(file)?;
+
     Ok(())
 }
 
-/// Performs a system command to get user home path.
+/// `fsync`s `path`'s parent directory, so the directory entry created/updated
+/// by a write to `path` is durable and not just the file's own data. No-op on
+/// platforms (like Windows) where directories can't be opened as a `File`.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            File::open(parent)?.sync_all()?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Verifies that `dir` is writable by test-creating and immediately removing
+/// a throwaway file in it. Meant to be called before an expensive operation
+/// (like decrypting/decompressing a large file) so a read-only target or a
+/// full disk fails fast instead of after the work is already done.
+pub fn check_dir_writable(dir: &Path) -> Result<()> {
+    let probe = dir.join(format!(".crypt_write_check_{}", std::process::id()));
+    match File::create(&probe) {
+        Ok(_) => {
+            _ = std::fs::remove_file(&probe);
+            Ok(())
+        }
+        Err(e) => Err(Error::CommonError(error::CommonError::OutputNotWritable(
+            dir.display().to_string(),
+            e.to_string(),
+        ))),
+    }
+}
+
+/// Performs a system command to get the user's home directory.
 /// if system is a windows machine, performs a powershell call. Otherwise, we assume it is linux
 /// and
-pub fn get_config_folder() -> PathBuf {
+fn home_dir() -> PathBuf {
     let output = if cfg!(target_os = "windows") {
         Command::new("cmd")
             .args(["/C", "echo %userprofile%"])
@@ -425,7 +547,14 @@ pub fn get_config_folder() -> PathBuf {
     };
 
     let stdout = output.stdout;
-    let mut path = PathBuf::from(String::from_utf8(stdout).expect("ERROR").trim());
+    PathBuf::from(String::from_utf8(stdout).expect("ERROR").trim())
+}
+
+/// Performs a system command to get user home path.
+/// if system is a windows machine, performs a powershell call. Otherwise, we assume it is linux
+/// and
+pub fn get_config_folder() -> PathBuf {
+    let mut path = home_dir();
     path.push("crypt_config");
 
     if !path.exists() {
@@ -454,21 +583,7 @@ pub fn get_config_folder() -> PathBuf {
 /// function can panic if either the process fails,
 /// or the conversion from `Vec<u8>` to String fails.
 pub fn get_crypt_folder() -> PathBuf {
-    let output = if cfg!(target_os = "windows") {
-        Command::new("cmd")
-            .args(["/C", "echo %userprofile%"])
-            .output()
-            .expect("failed to execute process")
-    } else {
-        Command::new("sh")
-            .arg("-c")
-            .arg("echo $HOME")
-            .output()
-            .expect("failed to execute process")
-    };
-
-    let stdout = output.stdout;
-    let mut path = PathBuf::from(String::from_utf8(stdout).expect("ERROR").trim());
+    let mut path = home_dir();
     path.push("crypt");
 
     if !path.exists() {
@@ -478,6 +593,56 @@ pub fn get_crypt_folder() -> PathBuf {
     path
 }
 
+/// Directory staging operations (stdout decrypt, staged download, streaming)
+/// should write temp files into -- [`config::Config::temp_path`] if set,
+/// otherwise [`std::env::temp_dir`].
+pub fn get_temp_dir() -> PathBuf {
+    match config::get_config().temp_path {
+        Some(path) => expand_tilde(&path),
+        None => std::env::temp_dir(),
+    }
+}
+
+/// A staging file under [`get_temp_dir`] that's created with restrictive
+/// permissions and removed automatically when dropped, even if the caller
+/// bails out early with `?`.
+pub struct TempFileGuard {
+    path: PathBuf,
+}
+
+impl TempFileGuard {
+    /// Creates (or truncates) `name` inside the configured temp directory,
+    /// restricted to the owner on Unix, and returns a guard that deletes it on drop.
+    pub fn new(name: &str) -> Result<Self> {
+        let path = get_temp_dir().join(name);
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+        #[cfg(not(unix))]
+        let _ = file;
+
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFileGuard {
+    fn drop(&mut self) {
+        _ = std::fs::remove_file(&self.path);
+    }
+}
+
 /// Performs a command to query the device hostname.
 ///
 /// If the target operating system is Windows, the function uses the command prompt (`cmd`).
@@ -781,8 +946,9 @@ where
 }
 
 pub fn send_information(info: Vec<String>) {
-    //CLI
-    print_information(info);
+    for item in info {
+        emit(Event::Info(item));
+    }
 }
 
 /// Takes in a path, and recursively walks the subdirectories and returns a `Vec<PathBuf>`
@@ -808,12 +974,28 @@ pub fn walk_directory<T: AsRef<Path>>(
         false => get_full_file_path(path_in),
     };
 
-    let walker = WalkDir::new(path).into_iter();
+    let conf = config::get_config();
+    let mut walker = ignore::WalkBuilder::new(&path);
+    walker
+        .hidden(false)
+        .git_ignore(false)
+        .git_global(false)
+        .git_exclude(false)
+        .ignore(false)
+        .add_custom_ignore_filename(CRYPTIGNORE_FILENAME);
+
     let mut pathlist: Vec<PathBuf> = Vec::new();
 
-    for entry in walker.filter_entry(|e| !is_hidden(e)) {
+    for entry in walker.build() {
         let entry = entry?;
 
+        let Some(name) = entry.file_name().to_str() else {
+            continue;
+        };
+        if is_ignored_name(name, &conf.ignore_items) {
+            continue;
+        }
+
         if !filter_directories || entry.path().display().to_string().find('.').is_some() {
             pathlist.push(PathBuf::from(entry.path().display().to_string()));
         }
@@ -822,6 +1004,13 @@ pub fn walk_directory<T: AsRef<Path>>(
     Ok(pathlist)
 }
 
+/// Filename [`walk_directory`] treats as a per-directory ignore file, parsed
+/// with gitignore syntax (including `!negation` and directory anchoring).
+/// A `.cryptignore` in a subdirectory takes precedence over patterns declared
+/// by its parents, exactly like nested `.gitignore` files, and combines with
+/// the global [`config::Config::ignore_items`] list rather than replacing it.
+const CRYPTIGNORE_FILENAME: &str = ".cryptignore";
+
 /// Recursively walks the subdirectories of the crypt folder and returns a `Vec<PathBuf>`.
 ///
 /// # Returns
@@ -904,13 +1093,98 @@ pub fn walk_paths<T: AsRef<str>>(path_in: T) -> Vec<PathInfo> {
     pathlist
 }
 
+/// Expands a leading `~` or `~user` component to the relevant home directory.
+/// A `~` that doesn't appear at the very start of the path (e.g. `foo~bar`) is
+/// left untouched, since it isn't a home-directory reference.
+///
+/// # Example
+/// ```
+/// # use crypt_core::common::expand_tilde;
+/// # use std::path::PathBuf;
+/// let expanded = expand_tilde("~/docs/secret.txt");
+/// assert!(!expanded.to_string_lossy().starts_with('~'));
+/// assert_eq!(expand_tilde("foo~bar"), PathBuf::from("foo~bar"));
+/// ```
+pub fn expand_tilde<T: AsRef<Path>>(path: T) -> PathBuf {
+    let path = path.as_ref();
+    let path_str = path.to_string_lossy();
+
+    if !path_str.starts_with('~') {
+        return path.to_owned();
+    }
+
+    let mut components = path.components();
+    // `~` and `~user` both land in the first component; this system only ever
+    // resolves the current user's home, so `~user` falls back to it too rather
+    // than pretending to look up other users' home directories.
+    match components.next() {
+        Some(std::path::Component::Normal(_)) => (),
+        _ => return path.to_owned(),
+    }
+
+    let rest: PathBuf = components.collect();
+    let home = home_dir();
+
+    if rest.as_os_str().is_empty() {
+        home
+    } else {
+        home.join(rest)
+    }
+}
+
 /// get full path from a relative path
 pub fn get_full_file_path<T: AsRef<Path>>(path: T) -> PathBuf {
-    let canonicalize = dunce::canonicalize(path.as_ref());
+    let path = expand_tilde(path);
+    let canonicalize = dunce::canonicalize(&path);
     match canonicalize {
         Ok(c) => c,
-        Err(_) => PathBuf::from(path.as_ref()),
+        Err(_) => path,
+    }
+}
+
+/// Resolves `output` against `root`, guaranteeing the result stays within `root`
+/// unless `allow_absolute` is set and `output` is itself an absolute path.
+///
+/// `output` may not exist on disk yet (we're often about to create it), so `..`
+/// components are resolved lexically against `root` rather than via `fs::canonicalize`.
+///
+/// # Errors
+///
+/// Returns `Error::CommonError(CommonError::PathTraversal)` if `output` is an
+/// unpermitted absolute path, or if a `..` sequence resolves outside of `root`.
+pub fn resolve_within_root(root: &Path, output: &str, allow_absolute: bool) -> Result<PathBuf> {
+    let candidate = PathBuf::from(output);
+
+    if candidate.is_absolute() {
+        if allow_absolute {
+            return Ok(candidate);
+        }
+        return Err(Error::CommonError(error::CommonError::PathTraversal(
+            output.to_string(),
+            root.display().to_string(),
+        )));
     }
+
+    let root = get_full_file_path(root);
+    let mut resolved = root.clone();
+    for component in candidate.components() {
+        match component {
+            std::path::Component::ParentDir => {
+                resolved.pop();
+            }
+            std::path::Component::Normal(part) => resolved.push(part),
+            _ => (),
+        }
+    }
+
+    if !resolved.starts_with(&root) {
+        return Err(Error::CommonError(error::CommonError::PathTraversal(
+            output.to_string(),
+            root.display().to_string(),
+        )));
+    }
+
+    Ok(resolved)
 }
 
 /// Checks whether a `DirEntry` should be considered hidden based on the configured
@@ -953,21 +1227,75 @@ pub fn get_full_file_path<T: AsRef<Path>>(path: T) -> PathBuf {
 pub fn is_hidden(entry: &walkdir::DirEntry) -> bool {
     let conf = config::get_config();
 
-    if let Some(s) = entry.file_name().to_str() {
-        // Early return if the file name is not a valid UTF-8 string
-        if s.is_empty() {
-            return true;
-        }
+    match entry.file_name().to_str() {
+        Some(s) => is_ignored_name(s, &conf.ignore_items),
+        None => true, // not a valid UTF-8 string, treat as hidden
+    }
+}
 
-        // TODO: change to support including hidden files?
-        // Use the `any` method for a more concise check
-        return conf
-            .ignore_items
-            .iter()
-            .any(|item| s.contains(item) || s.starts_with('.'));
+/// Shared name check behind [`is_hidden`] and [`walk_directory`]'s `.cryptignore`
+/// filtering: a name is ignored if it's empty, contains one of the global
+/// `ignore_items`, or starts with a dot.
+fn is_ignored_name(name: &str, ignore_items: &[String]) -> bool {
+    if name.is_empty() {
+        return true;
     }
 
-    true // Return true if the file name is not a valid UTF-8 string
+    // TODO: change to support including hidden files?
+    ignore_items.iter().any(|item| name.contains(item)) || name.starts_with('.')
+}
+
+/// A file type sniffed from magic bytes rather than trusted from its extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileType {
+    pub mime: String,
+    pub extension: String,
+}
+
+impl FileType {
+    /// `true` if this type is worth running through zstd -- i.e. it isn't
+    /// already compressed. Images, audio, video, and archive formats pack
+    /// their own entropy coding, so re-compressing them wastes CPU for
+    /// little to no size reduction.
+    pub fn is_compressible(&self) -> bool {
+        let category = self.mime.split('/').next().unwrap_or("");
+        !matches!(category, "image" | "audio" | "video")
+            && !["zip", "gzip", "x-7z-compressed", "x-rar-compressed", "x-tar", "zstd"]
+                .iter()
+                .any(|needle| self.mime.contains(needle))
+    }
+}
+
+/// Sniffs `bytes` for a known file type via magic-byte matching. Returns `None`
+/// if the content is empty or doesn't match any known signature.
+pub fn detect_file_type(bytes: &[u8]) -> Option<FileType> {
+    infer::get(bytes).map(|t| FileType {
+        mime: t.mime_type().to_string(),
+        extension: t.extension().to_string(),
+    })
+}
+
+/// Longest edge, in pixels, of a thumbnail produced by [`make_thumbnail`].
+#[cfg(feature = "thumbnails")]
+const THUMBNAIL_MAX_DIMENSION: u32 = 64;
+
+/// Decodes `bytes` as an image and returns a small blurred/downscaled JPEG
+/// thumbnail, for callers that want a preview of an encrypted image without
+/// decrypting the whole file. Returns `None` if `bytes` isn't a decodable
+/// image, or if re-encoding the thumbnail fails.
+#[cfg(feature = "thumbnails")]
+pub fn make_thumbnail(bytes: &[u8]) -> Option<Vec<u8>> {
+    let img = image::load_from_memory(bytes).ok()?;
+    let thumbnail = img
+        .thumbnail(THUMBNAIL_MAX_DIMENSION, THUMBNAIL_MAX_DIMENSION)
+        .blur(1.0);
+
+    let mut out = Vec::new();
+    thumbnail
+        .write_to(&mut io::Cursor::new(&mut out), image::ImageFormat::Jpeg)
+        .ok()?;
+
+    Some(out)
 }
 
 #[cfg(test)]
@@ -985,4 +1313,266 @@ mod tests {
             "encryption_benchmark.rs"
         );
     }
+
+    // Unlike the DB/Config-touching tests elsewhere in this crate, this test only
+    // reads/writes its own temp directory tree -- it never touches the shared
+    // KEEPER pool and only reads Config (walk_directory never writes it), so it
+    // doesn't need test_support::db_test_guard().
+    #[test]
+    fn test_walk_directory_respects_nested_cryptignore_overrides() {
+        let root = std::env::temp_dir().join("crypt_core_cryptignore_test");
+        let nested = root.join("nested");
+        if root.exists() {
+            std::fs::remove_dir_all(&root).unwrap();
+        }
+        std::fs::create_dir_all(&nested).unwrap();
+
+        std::fs::write(root.join("keep.txt"), b"keep").unwrap();
+        std::fs::write(root.join("skip_root.txt"), b"skip").unwrap();
+        std::fs::write(root.join("other.log"), b"log").unwrap();
+        std::fs::write(
+            root.join(CRYPTIGNORE_FILENAME),
+            "skip_root.txt\n*.log\nnested/skip_nested.txt\n",
+        )
+        .unwrap();
+
+        std::fs::write(nested.join("skip_nested.txt"), b"nested skip").unwrap();
+        std::fs::write(nested.join("allowed.log"), b"nested log").unwrap();
+        std::fs::write(
+            nested.join(CRYPTIGNORE_FILENAME),
+            "!skip_nested.txt\n!allowed.log\n",
+        )
+        .unwrap();
+
+        let found: Vec<String> = walk_directory(root.to_str().unwrap(), false)
+            .unwrap()
+            .into_iter()
+            .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+            .collect();
+
+        // negated by the nested .cryptignore, so they survive despite the parent's rules.
+        assert!(found.contains(&"keep.txt".to_string()));
+        assert!(found.contains(&"skip_nested.txt".to_string()));
+        assert!(found.contains(&"allowed.log".to_string()));
+        // not overridden by anything nested, so the root .cryptignore still applies.
+        assert!(!found.contains(&"skip_root.txt".to_string()));
+        assert!(!found.contains(&"other.log".to_string()));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_expand_tilde_home() {
+        let home = home_dir();
+        assert_eq!(expand_tilde("~"), home);
+    }
+
+    #[test]
+    fn test_expand_tilde_subpath() {
+        let mut expected = home_dir();
+        expected.push("docs");
+        expected.push("secret.txt");
+        assert_eq!(expand_tilde("~/docs/secret.txt"), expected);
+    }
+
+    #[test]
+    fn test_expand_tilde_leaves_mid_string_tilde_alone() {
+        assert_eq!(expand_tilde("foo~bar"), PathBuf::from("foo~bar"));
+    }
+
+    #[test]
+    fn test_detect_file_type_png() {
+        let mut bytes = vec![0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+        bytes.extend_from_slice(&[0u8; 16]);
+        let ft = detect_file_type(&bytes).unwrap();
+        assert_eq!(ft.mime, "image/png");
+        assert!(!ft.is_compressible());
+    }
+
+    #[test]
+    fn test_detect_file_type_zip() {
+        let mut bytes = vec![0x50, 0x4B, 0x03, 0x04];
+        bytes.extend_from_slice(&[0u8; 16]);
+        let ft = detect_file_type(&bytes).unwrap();
+        assert_eq!(ft.mime, "application/zip");
+        assert!(!ft.is_compressible());
+    }
+
+    #[test]
+    fn test_detect_file_type_plain_text_is_undetected_and_compressible() {
+        let bytes = b"just some plain ascii text, nothing magic about it";
+        assert!(detect_file_type(bytes).is_none());
+    }
+
+    #[test]
+    fn test_resolve_within_root_allows_normal_subpath() {
+        let root = std::env::temp_dir().join("crypt_resolve_within_root_ok_test");
+        let resolved = resolve_within_root(&root, "sub/dir/file.txt", false).unwrap();
+        assert_eq!(resolved, get_full_file_path(&root).join("sub/dir/file.txt"));
+    }
+
+    #[test]
+    fn test_resolve_within_root_rejects_parent_dir_traversal() {
+        let root = std::env::temp_dir().join("crypt_resolve_within_root_traversal_test");
+        let err = resolve_within_root(&root, "../../etc/passwd", false).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::CommonError(error::CommonError::PathTraversal(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_within_root_rejects_absolute_path_by_default() {
+        let root = std::env::temp_dir().join("crypt_resolve_within_root_absolute_test");
+        let err = resolve_within_root(&root, "/etc/passwd", false).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::CommonError(error::CommonError::PathTraversal(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_within_root_allows_absolute_path_when_permitted() {
+        let root = std::env::temp_dir().join("crypt_resolve_within_root_absolute_ok_test");
+        let resolved = resolve_within_root(&root, "/etc/passwd", true).unwrap();
+        assert_eq!(resolved, PathBuf::from("/etc/passwd"));
+    }
+
+    #[test]
+    fn test_check_dir_writable_allows_writable_dir() {
+        let dir = std::env::temp_dir().join("crypt_check_dir_writable_ok_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(check_dir_writable(&dir).is_ok());
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    #[ignore = "works locally, fails in CI: root ignores directory permission bits, so this needs a non-root runner"]
+    fn test_check_dir_writable_rejects_read_only_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join("crypt_check_dir_writable_readonly_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o500)).unwrap();
+
+        let err = check_dir_writable(&dir).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::CommonError(error::CommonError::OutputNotWritable(_, _))
+        ));
+
+        _ = std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700));
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_temp_file_guard_lands_in_configured_dir_and_cleans_up() {
+        let custom_dir = std::env::temp_dir().join("crypt_temp_path_config_test");
+        std::fs::create_dir_all(&custom_dir).unwrap();
+        config::get_config_write().set_temp_path(Some(custom_dir.to_string_lossy().to_string()));
+
+        let guard_path = {
+            let guard = TempFileGuard::new("temp_guard_test_file").unwrap();
+            let guard_path = guard.path().to_path_buf();
+            assert_eq!(guard_path.parent().unwrap(), custom_dir);
+            assert!(guard_path.exists());
+            guard_path
+        };
+
+        assert!(!guard_path.exists());
+
+        config::get_config_write().set_temp_path(None);
+        _ = std::fs::remove_dir_all(&custom_dir);
+    }
+
+    #[test]
+    fn test_write_contents_to_file_flushes_and_syncs_a_large_buffer() {
+        let mut path = std::env::temp_dir();
+        path.push("crypt_write_contents_large_test.bin");
+
+        // several times WRITE_CHUNK_SIZE, to exercise more than one chunk.
+        let contents: Vec<u8> = (0..WRITE_CHUNK_SIZE * 3 + 1)
+            .map(|i| (i % 256) as u8)
+            .collect();
+
+        write_contents_to_file(&path, contents.clone()).unwrap();
+
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written, contents);
+
+        _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    #[cfg(feature = "thumbnails")]
+    fn test_make_thumbnail_downscales_a_sample_image() {
+        let sample = image::RgbImage::from_fn(200, 100, |x, y| {
+            image::Rgb([(x % 256) as u8, (y % 256) as u8, 128])
+        });
+        let mut sample_bytes = Vec::new();
+        image::DynamicImage::ImageRgb8(sample)
+            .write_to(&mut io::Cursor::new(&mut sample_bytes), image::ImageFormat::Png)
+            .unwrap();
+
+        let thumbnail = make_thumbnail(&sample_bytes).unwrap();
+        let decoded = image::load_from_memory(&thumbnail).unwrap();
+
+        assert!(decoded.width() <= THUMBNAIL_MAX_DIMENSION);
+        assert!(decoded.height() <= THUMBNAIL_MAX_DIMENSION);
+    }
+
+    #[test]
+    #[cfg(feature = "thumbnails")]
+    fn test_make_thumbnail_returns_none_for_non_image_bytes() {
+        assert!(make_thumbnail(b"not an image").is_none());
+    }
+
+    fn sample_dir_info() -> DirInfo {
+        DirInfo {
+            name: "root".to_string(),
+            path: "root".to_string(),
+            expanded: true,
+            contents: vec![
+                FsNode::File(FileInfo::new("a.txt".to_string(), "a.txt".to_string())),
+                FsNode::Directory(DirInfo {
+                    name: "sub".to_string(),
+                    path: "sub".to_string(),
+                    expanded: true,
+                    contents: vec![FsNode::File(FileInfo::new(
+                        "b.txt".to_string(),
+                        "b.txt".to_string(),
+                    ))],
+                }),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_build_tree_renders_each_charset() {
+        let dir_info = sample_dir_info();
+
+        config::get_config_write().set_tree_charset(TreeCharset::Unicode);
+        let unicode_tree = build_tree(&dir_info).join("\n");
+        assert!(unicode_tree.contains('├'));
+        assert!(unicode_tree.contains('╰'));
+        assert!(unicode_tree.contains("a.txt"));
+        assert!(unicode_tree.contains("b.txt"));
+
+        config::get_config_write().set_tree_charset(TreeCharset::Ascii);
+        let ascii_tree = build_tree(&dir_info).join("\n");
+        assert!(ascii_tree.contains("|--"));
+        assert!(ascii_tree.contains("`--"));
+        assert!(!ascii_tree.contains('├'));
+        assert!(!ascii_tree.contains('╰'));
+
+        config::get_config_write().set_tree_charset(TreeCharset::Plain);
+        let plain_tree = build_tree(&dir_info).join("\n");
+        assert!(!plain_tree.contains("|--"));
+        assert!(!plain_tree.contains('├'));
+        assert!(plain_tree.contains("a.txt"));
+        assert!(plain_tree.contains("b.txt"));
+
+        config::get_config_write().set_tree_charset(TreeCharset::default());
+    }
 }
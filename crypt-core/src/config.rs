@@ -1,7 +1,10 @@
 use crate::{
-    common::{self, get_machine_name, send_information},
+    common::{self, get_machine_name, send_information, TreeCharset},
     db::{self},
+    filecrypt::{DecryptNaming, EncryptCollision},
+    keystore::KeyStoreBackend,
     prelude::*,
+    token::CloudScope,
 };
 use chrono::prelude::*;
 use lazy_static::lazy_static;
@@ -32,11 +35,17 @@ lazy_static! {
         format!("{}", path.display())
     };
 
-    ///Loads and holds config for session
+    ///Loads and holds config for session. Falls back to the default config
+    ///(with a logged warning) instead of panicking if the on-disk config
+    ///can't be read/parsed, so a corrupt config.toml doesn't take down the
+    ///whole program at first access.
     static ref CONFIG: RwLock<Config> = RwLock::new({
         match load_config() {
             Ok(config) => config,
-            Err(err) => panic!("Failed to load config: {}", err),
+            Err(err) => {
+                error!("Failed to load config, falling back to defaults: {}", err);
+                Config::default()
+            }
         }
     });
 
@@ -59,11 +68,17 @@ lazy_static! {
     };
 }
 
-pub fn init(interface: Interface) {
+pub fn init(interface: Interface) -> Result<()> {
     set_interface(&interface);
     load_logger(&interface);
-    _ = get_config();
-    _ = db::get_keeper();
+    let config = get_config();
+    db::get_keeper()?;
+
+    if config.private_metadata {
+        db::prompt_metadata_passphrase();
+    }
+
+    Ok(())
 }
 
 fn load_logger(interface: &Interface) {
@@ -141,6 +156,62 @@ pub struct Config {
     /// zstd level is for file compression, from [fastest, least compression]
     /// to [slowest, highest compression] `-7 to 22`. Default compression level is 3.
     pub zstd_level: i32,
+
+    /// maximum size (in bytes) of a file `encrypt_file` will read into memory.
+    /// `0` means unlimited. Guards against accidentally pointing encryption
+    /// at something huge (e.g. a disk image) until streaming encryption lands.
+    pub max_file_size: u64,
+
+    /// when `true`, the `filename`, `extension`, and `full_path` columns of the
+    /// `crypt` table are stored encrypted at rest, keyed by a passphrase-derived
+    /// master key entered at startup, instead of as plaintext.
+    pub private_metadata: bool,
+
+    /// OAuth scope requested from Google Drive when authorizing a new token.
+    /// Narrower scopes are safer and less scary in the consent screen, but
+    /// `CloudScope::ReadOnly` cannot upload.
+    pub cloud_scope: CloudScope,
+
+    /// Naming scheme used to pick a decrypted file's output path.
+    pub decrypt_naming: DecryptNaming,
+
+    /// Which [`crate::keystore::KeyStore`] backend `filecrypt`'s encrypt/decrypt
+    /// go through to persist and retrieve per-file keys.
+    pub key_store: KeyStoreBackend,
+
+    /// File size (in bytes) at or above which `encrypt_file` hashes content with
+    /// [`crate::encryption::HashAlgorithm::Blake3`] instead of `Blake2s`. `0`
+    /// disables Blake3 and always hashes with Blake2s.
+    pub hash_parallel_threshold: u64,
+
+    /// Directory staging operations (stdout decrypt, staged download, streaming)
+    /// write temp files into. `None` falls back to [`std::env::temp_dir`] --
+    /// important to override when `/tmp` is small or unencrypted.
+    pub temp_path: Option<String>,
+
+    /// Policy `encrypt_file` follows when the target `<filename>.crypt` already
+    /// exists but belongs to a different source file than the one being encrypted.
+    pub encrypt_collision: EncryptCollision,
+
+    /// When `true`, `encrypt_file` decrypts the `.crypt` it just wrote back in
+    /// memory and compares the result against the source hash before returning,
+    /// erroring out instead of reporting success if they don't match. Overridden
+    /// per-call by `encrypt_file`'s own `verify` argument (e.g. a `--verify` flag).
+    pub verify_on_encrypt: bool,
+
+    /// When `true` and the `thumbnails` feature is compiled in, `encrypt_file`
+    /// generates a small encrypted preview thumbnail alongside the `.crypt` for
+    /// source files that decode as images. Has no effect in builds without the
+    /// `thumbnails` feature.
+    pub generate_thumbnails: bool,
+
+    /// Character set `build_tree`/`ls` draw box-drawing connectors with.
+    pub tree_charset: TreeCharset,
+
+    /// Maximum number of sibling Drive folders `walk_cloud`/`g_walk` fetches
+    /// concurrently while building a `DirInfo` tree. Higher values finish a
+    /// wide tree faster at the cost of more simultaneous requests to Drive.
+    pub cloud_walk_concurrency: usize,
 }
 
 ///Enum for storing each item in the config struct
@@ -159,6 +230,18 @@ pub enum ConfigOptions {
     IgnoreItems,
     Hwid,
     ZstdLevel,
+    MaxFileSize,
+    PrivateMetadata,
+    CloudScope,
+    DecryptNaming,
+    KeyStore,
+    HashParallelThreshold,
+    TempPath,
+    EncryptCollision,
+    VerifyOnEncrypt,
+    GenerateThumbnails,
+    TreeCharset,
+    CloudWalkConcurrency,
 }
 
 impl ToString for ConfigOptions {
@@ -170,6 +253,18 @@ impl ToString for ConfigOptions {
             Self::Hwid => "hwid".to_string(),
             Self::ZstdLevel => "zstd_level".to_string(),
             Self::CryptPath => "crypt_path".to_string(),
+            Self::MaxFileSize => "max_file_size".to_string(),
+            Self::PrivateMetadata => "private_metadata".to_string(),
+            Self::CloudScope => "cloud_scope".to_string(),
+            Self::DecryptNaming => "decrypt_naming".to_string(),
+            Self::KeyStore => "key_store".to_string(),
+            Self::HashParallelThreshold => "hash_parallel_threshold".to_string(),
+            Self::TempPath => "temp_path".to_string(),
+            Self::EncryptCollision => "encrypt_collision".to_string(),
+            Self::VerifyOnEncrypt => "verify_on_encrypt".to_string(),
+            Self::GenerateThumbnails => "generate_thumbnails".to_string(),
+            Self::TreeCharset => "tree_charset".to_string(),
+            Self::CloudWalkConcurrency => "cloud_walk_concurrency".to_string(),
         }
     }
 }
@@ -180,18 +275,37 @@ impl ToString for ConfigOptions {
 ///```ignore
 /// # use crypt_lib::util::directive::ConfigTask;
 /// ConfigTask::DatabasePath
-/// ConfigTask::CryptPath
+/// ConfigTask::CryptPath(bool)
 /// ConfigTask::IgnoreItems(ItemTask, String)
 /// ConfigTask::ZstdLevel(i32)
+/// ConfigTask::AutoZstdLevel
 /// ConfigTask::LoadDefault
 ///```
 pub enum ConfigTask {
     DatabasePath,
-    CryptPath,
+    /// `true` moves existing `.crypt` files (and re-points their DB entries)
+    /// from the current crypt path to the new one before switching.
+    CryptPath(bool),
     IgnoreHidden(bool),
     IgnoreItems(ItemsTask, String),
     Hwid,
     ZstdLevel(i32),
+    AutoZstdLevel,
+    MaxFileSize(u64),
+    /// `(enabled, password_stdin)` -- `password_stdin` reads the passphrase for
+    /// enabling metadata encryption from stdin (falling back to `CRYPT_PASSWORD`,
+    /// then an interactive prompt) instead of always prompting the terminal.
+    PrivateMetadata(bool, bool),
+    CloudScope(CloudScope),
+    DecryptNaming(DecryptNaming),
+    KeyStore(KeyStoreBackend),
+    HashParallelThreshold(u64),
+    TempPath,
+    EncryptCollision(EncryptCollision),
+    VerifyOnEncrypt(bool),
+    GenerateThumbnails(bool),
+    TreeCharset(TreeCharset),
+    CloudWalkConcurrency(usize),
     LoadDefault,
 }
 
@@ -221,6 +335,22 @@ impl std::fmt::Display for Config {
         _ = writeln!(f, "  ignore_item: {:?}", self.ignore_items);
         _ = writeln!(f, "  hwid: {:?}", self.hwid);
         _ = writeln!(f, "  zstd_level: {}", self.zstd_level);
+        _ = writeln!(f, "  max_file_size: {}", self.max_file_size);
+        _ = writeln!(f, "  private_metadata: {}", self.private_metadata);
+        _ = writeln!(f, "  cloud_scope: {}", self.cloud_scope);
+        _ = writeln!(f, "  decrypt_naming: {}", self.decrypt_naming);
+        _ = writeln!(f, "  key_store: {}", self.key_store);
+        _ = writeln!(f, "  hash_parallel_threshold: {}", self.hash_parallel_threshold);
+        _ = writeln!(
+            f,
+            "  temp_path: {}",
+            self.temp_path.as_deref().unwrap_or("<system default>")
+        );
+        _ = writeln!(f, "  encrypt_collision: {}", self.encrypt_collision);
+        _ = writeln!(f, "  verify_on_encrypt: {}", self.verify_on_encrypt);
+        _ = writeln!(f, "  generate_thumbnails: {}", self.generate_thumbnails);
+        _ = writeln!(f, "  tree_charset: {}", self.tree_charset);
+        _ = writeln!(f, "  cloud_walk_concurrency: {}", self.cloud_walk_concurrency);
         std::fmt::Result::Ok(())
     }
 }
@@ -239,11 +369,24 @@ impl Default for Config {
             ignore_items: vec!["target".to_string()],
             hwid,
             zstd_level: 3,
+            max_file_size: 0,
+            private_metadata: false,
+            cloud_scope: CloudScope::default(),
+            decrypt_naming: DecryptNaming::default(),
+            key_store: KeyStoreBackend::default(),
+            hash_parallel_threshold: 0,
+            temp_path: None,
+            encrypt_collision: EncryptCollision::default(),
+            verify_on_encrypt: false,
+            generate_thumbnails: false,
+            tree_charset: TreeCharset::default(),
+            cloud_walk_concurrency: 4,
         }
     }
 }
 
 impl Config {
+    #[allow(clippy::too_many_arguments)]
     fn _new(
         database_path: String,
         crypt_path: String,
@@ -251,6 +394,18 @@ impl Config {
         ignore_items: Vec<String>,
         hwid: String,
         zstd_level: i32,
+        max_file_size: u64,
+        private_metadata: bool,
+        cloud_scope: CloudScope,
+        decrypt_naming: DecryptNaming,
+        key_store: KeyStoreBackend,
+        hash_parallel_threshold: u64,
+        temp_path: Option<String>,
+        encrypt_collision: EncryptCollision,
+        verify_on_encrypt: bool,
+        generate_thumbnails: bool,
+        tree_charset: TreeCharset,
+        cloud_walk_concurrency: usize,
     ) -> Self {
         Self {
             database_path,
@@ -259,6 +414,18 @@ impl Config {
             ignore_items,
             hwid,
             zstd_level,
+            max_file_size,
+            private_metadata,
+            cloud_scope,
+            decrypt_naming,
+            key_store,
+            hash_parallel_threshold,
+            temp_path,
+            encrypt_collision,
+            verify_on_encrypt,
+            generate_thumbnails,
+            tree_charset,
+            cloud_walk_concurrency,
         }
     }
 
@@ -280,7 +447,7 @@ impl Config {
         self.database_path.as_ref()
     }
     pub fn set_database_path(&mut self, path: &str) {
-        self.database_path = path.to_owned();
+        self.database_path = common::expand_tilde(path).to_string_lossy().to_string();
         _ = save_config(self);
     }
 
@@ -288,7 +455,7 @@ impl Config {
         self.crypt_path.as_ref()
     }
     pub fn set_crypt_path(&mut self, path: &str) {
-        self.crypt_path = path.to_owned();
+        self.crypt_path = common::expand_tilde(path).to_string_lossy().to_string();
         _ = save_config(self);
     }
 
@@ -343,6 +510,135 @@ impl Config {
             }
         }
     }
+
+    /// maximum size (in bytes) a file may be for `encrypt_file` to read it into memory.
+    /// `0` means unlimited.
+    pub fn get_max_file_size(&self) -> u64 {
+        self.max_file_size
+    }
+
+    pub fn set_max_file_size(&mut self, bytes: u64) {
+        self.max_file_size = bytes;
+        _ = save_config(self);
+    }
+
+    /// whether filename/extension/full_path columns are stored encrypted at rest.
+    pub fn get_private_metadata(&self) -> bool {
+        self.private_metadata
+    }
+
+    pub fn set_private_metadata(&mut self, enabled: bool) {
+        self.private_metadata = enabled;
+        _ = save_config(self);
+    }
+
+    /// OAuth scope requested from Google Drive when authorizing a new token.
+    pub fn get_cloud_scope(&self) -> CloudScope {
+        self.cloud_scope
+    }
+
+    pub fn set_cloud_scope(&mut self, scope: CloudScope) {
+        self.cloud_scope = scope;
+        _ = save_config(self);
+    }
+
+    /// Naming scheme used to pick a decrypted file's output path.
+    pub fn get_decrypt_naming(&self) -> DecryptNaming {
+        self.decrypt_naming
+    }
+
+    pub fn set_decrypt_naming(&mut self, naming: DecryptNaming) {
+        self.decrypt_naming = naming;
+        _ = save_config(self);
+    }
+
+    /// Policy `encrypt_file` follows when the target `.crypt` file already
+    /// belongs to a different source file than the one being encrypted.
+    pub fn get_encrypt_collision(&self) -> EncryptCollision {
+        self.encrypt_collision
+    }
+
+    pub fn set_encrypt_collision(&mut self, policy: EncryptCollision) {
+        self.encrypt_collision = policy;
+        _ = save_config(self);
+    }
+
+    /// Which [`crate::keystore::KeyStore`] backend encrypt/decrypt use to
+    /// persist and retrieve per-file keys.
+    pub fn get_key_store(&self) -> KeyStoreBackend {
+        self.key_store
+    }
+
+    pub fn set_key_store(&mut self, backend: KeyStoreBackend) {
+        self.key_store = backend;
+        _ = save_config(self);
+    }
+
+    /// File size (in bytes) at or above which `encrypt_file` hashes with Blake3
+    /// instead of Blake2s. `0` disables Blake3.
+    pub fn get_hash_parallel_threshold(&self) -> u64 {
+        self.hash_parallel_threshold
+    }
+
+    pub fn set_hash_parallel_threshold(&mut self, bytes: u64) {
+        self.hash_parallel_threshold = bytes;
+        _ = save_config(self);
+    }
+
+    /// Directory staging operations write temp files into, or `None` to use
+    /// [`std::env::temp_dir`].
+    pub fn get_temp_path(&self) -> Option<&str> {
+        self.temp_path.as_deref()
+    }
+
+    pub fn set_temp_path(&mut self, path: Option<String>) {
+        self.temp_path = path.map(|p| common::expand_tilde(&p).to_string_lossy().to_string());
+        _ = save_config(self);
+    }
+
+    /// Whether `encrypt_file` decrypts what it just wrote and verifies the hash
+    /// round-trips, by default, on every encrypt.
+    pub fn get_verify_on_encrypt(&self) -> bool {
+        self.verify_on_encrypt
+    }
+
+    pub fn set_verify_on_encrypt(&mut self, enabled: bool) {
+        self.verify_on_encrypt = enabled;
+        _ = save_config(self);
+    }
+
+    /// Whether `encrypt_file` generates an encrypted preview thumbnail for
+    /// image source files. Only takes effect in builds with the `thumbnails`
+    /// feature compiled in.
+    pub fn get_generate_thumbnails(&self) -> bool {
+        self.generate_thumbnails
+    }
+
+    pub fn set_generate_thumbnails(&mut self, enabled: bool) {
+        self.generate_thumbnails = enabled;
+        _ = save_config(self);
+    }
+
+    /// Character set `build_tree`/`ls` draw box-drawing connectors with.
+    pub fn get_tree_charset(&self) -> TreeCharset {
+        self.tree_charset
+    }
+
+    pub fn set_tree_charset(&mut self, charset: TreeCharset) {
+        self.tree_charset = charset;
+        _ = save_config(self);
+    }
+
+    /// Maximum number of sibling Drive folders `walk_cloud`/`g_walk` fetches
+    /// concurrently while building a `DirInfo` tree.
+    pub fn get_cloud_walk_concurrency(&self) -> usize {
+        self.cloud_walk_concurrency
+    }
+
+    pub fn set_cloud_walk_concurrency(&mut self, limit: usize) {
+        self.cloud_walk_concurrency = limit.max(1);
+        _ = save_config(self);
+    }
 }
 
 ///Loads configuration file -- creates default if missing
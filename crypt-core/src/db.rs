@@ -1,8 +1,12 @@
 use crate::{
-    common::{get_config_folder, write_contents_to_file},
+    common::{
+        get_config_folder, get_crypt_folder, send_information, walk_crypt_folder,
+        write_contents_to_file,
+    },
     config::get_config,
-    encryption::{KEY_SIZE, NONCE_SIZE},
-    filecrypt::FileCrypt,
+    encryption::{decrypt_metadata, derive_key_from_passphrase, encrypt_metadata, KEY_SIZE, NONCE_SIZE},
+    error,
+    filecrypt::{get_uuid_from_file, FileCrypt},
     prelude::*,
     token::{CloudService, UserToken},
 };
@@ -11,17 +15,22 @@ use lazy_static::lazy_static;
 use logfather::*;
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, Connection};
+use rusqlite::{params, types::ValueRef, Connection, Row};
+use serde::{Deserialize, Serialize};
 use std::{
     fs,
     path::{Path, PathBuf},
     str::FromStr,
+    sync::RwLock,
 };
 
 //Connection pool maintains a single connection to db for life of program
 //TODO: increase pool size from 1 to allow for multithreading
 lazy_static! {
-    static ref KEEPER: Pool<SqliteConnectionManager> = {
+    //Stores the pool build/init outcome instead of panicking, so a bad
+    //`database_path` (unwritable, malformed, etc.) surfaces as a normal `Error`
+    //to the first caller instead of taking the whole program down at first access.
+    static ref KEEPER: std::result::Result<Pool<SqliteConnectionManager>, String> = (|| {
         info!("Initializing database");
         let path;
         {//Ensure to only borrow config and release asap
@@ -29,12 +38,225 @@ lazy_static! {
             path = config.database_path.to_string();
         }
         let manager = SqliteConnectionManager::file(path);
-        let pool = Pool::new(manager).expect("Failed to generate pool");
+        let pool = Pool::new(manager).map_err(|e| e.to_string())?;
 
-        init_keeper(&pool.get().unwrap()).expect("Failed to initialize keeper");
+        let conn = pool.get().map_err(|e| e.to_string())?;
+        init_keeper(&conn).map_err(|e| e.to_string())?;
 
-        pool
-    };
+        Ok(pool)
+    })();
+
+    /// Master key used to encrypt/decrypt the `filename`, `extension`, and
+    /// `full_path` columns when `private_metadata` is enabled. `None` means
+    /// those columns are stored/read as plaintext.
+    static ref METADATA_KEY: RwLock<Option<[u8; KEY_SIZE]>> = RwLock::new(None);
+}
+
+/// Derives and stores the metadata master key for this session from a passphrase.
+pub fn set_metadata_key(passphrase: &str) {
+    let key = derive_key_from_passphrase(passphrase);
+    *METADATA_KEY.write().expect("Cannot write metadata key") = Some(key);
+}
+
+/// Clears the metadata master key, causing metadata columns to be read/written as plaintext.
+pub fn clear_metadata_key() {
+    *METADATA_KEY.write().expect("Cannot write metadata key") = None;
+}
+
+pub(crate) fn get_metadata_key() -> Option<[u8; KEY_SIZE]> {
+    *METADATA_KEY.read().expect("Cannot read metadata key")
+}
+
+/// Reads a passphrase without an interactive TTY prompt, for use in scripts/cron.
+/// Checked in order:
+/// 1. `password_stdin`: if `true`, reads a single line from stdin, mirroring
+///    `docker login --password-stdin`.
+/// 2. the `CRYPT_PASSWORD` environment variable, with a loud warning -- it's
+///    meaningfully less secure than `--password-stdin` since environment
+///    variables can leak via `/proc/<pid>/environ` or process listings.
+///
+/// Returns `None` if neither is set, so the caller can fall back to an
+/// interactive prompt.
+pub fn read_passphrase_non_interactive(password_stdin: bool) -> Option<String> {
+    if password_stdin {
+        return Some(read_passphrase_line(std::io::stdin().lock()));
+    }
+
+    std::env::var("CRYPT_PASSWORD").ok().inspect(|_| {
+        send_information(vec![format!(
+            "WARNING: reading passphrase from CRYPT_PASSWORD -- this is less secure than \
+             --password-stdin since environment variables can leak via /proc or process listings"
+        )]);
+    })
+}
+
+/// Reads and trims a single line from `reader`. Split out of
+/// [`read_passphrase_non_interactive`] so the stdin-reading path can be
+/// exercised in tests with an in-memory reader instead of real stdin.
+fn read_passphrase_line(mut reader: impl std::io::BufRead) -> String {
+    let mut passphrase = String::new();
+    reader
+        .read_line(&mut passphrase)
+        .expect("Failed to read passphrase from stdin");
+    passphrase.trim().to_string()
+}
+
+/// Prompts the user on stdin for their metadata passphrase and derives the key
+/// from it. Called at startup when `private_metadata` is enabled in config.
+/// Prefers [`read_passphrase_non_interactive`] (the `CRYPT_PASSWORD` env var)
+/// so automated/cron invocations don't block waiting on a TTY.
+pub fn prompt_metadata_passphrase() {
+    if let Some(passphrase) = read_passphrase_non_interactive(false) {
+        set_metadata_key(&passphrase);
+        return;
+    }
+
+    send_information(vec![format!(
+        "private_metadata is enabled -- enter your metadata passphrase:"
+    )]);
+    let mut passphrase = String::new();
+    std::io::stdin()
+        .read_line(&mut passphrase)
+        .expect("Failed to read passphrase");
+    set_metadata_key(passphrase.trim());
+}
+
+/// One-time migration that rewrites every row's `filename`/`extension`/`full_path`
+/// under `new_key`. Call before `set_metadata_key`/`clear_metadata_key` to bring
+/// existing rows in line with a new `private_metadata` setting -- reads every
+/// row under the currently active key, swaps in `new_key`, then re-writes each row.
+pub fn migrate_metadata_encryption(new_key: Option<[u8; KEY_SIZE]>) -> Result<usize> {
+    let crypts = query_keeper_crypt()?;
+
+    *METADATA_KEY.write().expect("Cannot write metadata key") = new_key;
+
+    for crypt in &crypts {
+        insert_crypt(crypt)?;
+    }
+
+    Ok(crypts.len())
+}
+
+/// Moves every file (and any subfolders) out of `old_path`, except its `logs`
+/// and `decrypted` folders, and into
+/// `new_path`, preserving relative structure, then rewrites any `FileCrypt`
+/// row whose `full_path` pointed inside `old_path` to point at `new_path`
+/// instead. Skips a file (logging why, via `error!`) rather than aborting the
+/// whole migration on a collision or I/O failure, so one bad file doesn't
+/// strand the rest. Returns the number of files successfully moved.
+pub fn migrate_crypt_path(old_path: &Path, new_path: &Path) -> Result<usize> {
+    let log_folder = old_path.join("logs");
+    let decrypted_folder = old_path.join("decrypted");
+
+    let mut moved = 0;
+    for entry in walkdir::WalkDir::new(old_path)
+        .into_iter()
+        .filter_entry(|e| {
+            !e.path().starts_with(&log_folder) && !e.path().starts_with(&decrypted_folder)
+        })
+    {
+        let entry = match entry {
+            Ok(it) => it,
+            Err(err) => {
+                error!("Failed to walk crypt folder entry: {}", err);
+                continue;
+            }
+        };
+        if !entry.path().is_file() {
+            continue;
+        }
+
+        let relative = match entry.path().strip_prefix(old_path) {
+            Ok(it) => it,
+            Err(_) => continue,
+        };
+        let dest = new_path.join(relative);
+
+        if dest.exists() {
+            error!("Skipping migration of {}: {} already exists", entry.path().display(), dest.display());
+            continue;
+        }
+
+        if let Some(parent) = dest.parent() {
+            if let Err(err) = fs::create_dir_all(parent) {
+                error!("Failed to create {}: {}", parent.display(), err);
+                continue;
+            }
+        }
+
+        if let Err(err) = fs::rename(entry.path(), &dest) {
+            error!("Failed to move {} to {}: {}", entry.path().display(), dest.display(), err);
+            continue;
+        }
+
+        moved += 1;
+    }
+
+    for mut crypt in query_keeper_crypt()? {
+        if let Ok(relative) = crypt.full_path.strip_prefix(old_path) {
+            crypt.full_path = new_path.join(relative);
+            insert_crypt(&crypt)?;
+        }
+    }
+
+    Ok(moved)
+}
+
+/// Encodes a metadata field (filename/extension/full_path) for storage: encrypted
+/// bytes if a metadata key is active, otherwise the plain UTF-8 bytes.
+fn encode_field(value: &str) -> Result<Vec<u8>> {
+    match get_metadata_key() {
+        Some(key) => encrypt_metadata(&key, value),
+        None => Ok(value.as_bytes().to_vec()),
+    }
+}
+
+/// Decodes a metadata field previously written by `encode_field`.
+fn decode_field(bytes: Vec<u8>) -> Result<String> {
+    match get_metadata_key() {
+        Some(key) => decrypt_metadata(&key, &bytes),
+        None => Ok(String::from_utf8(bytes).unwrap_or_default()),
+    }
+}
+
+/// Reads a column as raw bytes regardless of whether it's stored with TEXT or
+/// BLOB affinity -- metadata columns switch between the two depending on
+/// whether `private_metadata` was enabled at the time the row was written.
+fn column_bytes(row: &Row, idx: usize) -> rusqlite::Result<Vec<u8>> {
+    match row.get_ref(idx)? {
+        ValueRef::Text(t) => Ok(t.to_vec()),
+        ValueRef::Blob(b) => Ok(b.to_vec()),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Builds a `FileCrypt` from a `crypt` table row, decoding the metadata columns.
+fn row_to_filecrypt(row: &Row) -> rusqlite::Result<FileCrypt> {
+    let filename = decode_field(column_bytes(row, 1)?)
+        .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))?;
+    let ext = decode_field(column_bytes(row, 2)?)
+        .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))?;
+    let full_path = decode_field(column_bytes(row, 4)?)
+        .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))?;
+
+    Ok(FileCrypt {
+        uuid: row.get(0)?,
+        filename,
+        ext,
+        drive_id: row.get(3)?,
+        full_path: PathBuf::from(full_path),
+        key: row.get(5)?,
+        nonce: row.get(6)?,
+        hash: row.get(7)?,
+        permissions: row.get(8)?,
+        file_type: row.get(9)?,
+        compressed: row.get(10)?,
+        hardlinks: row.get(11)?,
+        hash_algorithm: row
+            .get::<_, String>(12)?
+            .parse()
+            .unwrap_or_default(),
+    })
 }
 
 ///Generates a connection to the database.
@@ -50,11 +272,24 @@ fn init_keeper(conn: &Connection) -> Result<()> {
             full_path TEXT NOT NULL,
             key_seed BLOB NOT NULL,
             nonce_seed BLOB NOT NULL,
-            hash BLOB NOT NULL
+            hash BLOB NOT NULL,
+            permissions INTEGER,
+            file_type TEXT,
+            compressed INTEGER NOT NULL DEFAULT 1,
+            hardlinks TEXT NOT NULL DEFAULT '',
+            hash_algorithm TEXT NOT NULL DEFAULT 'blake2s'
         )",
         [],
     )?;
 
+    // Migration for keeper DBs created before `hardlinks`/`hash_algorithm` existed --
+    // errors (e.g. the column already exists) are expected and safely ignored.
+    let _ = conn.execute("ALTER TABLE crypt ADD COLUMN hardlinks TEXT NOT NULL DEFAULT ''", []);
+    let _ = conn.execute(
+        "ALTER TABLE crypt ADD COLUMN hash_algorithm TEXT NOT NULL DEFAULT 'blake2s'",
+        [],
+    );
+
     conn.execute(
         "CREATE TABLE IF NOT EXISTS user_token (
             service TEXT PRIMARY KEY,
@@ -114,31 +349,146 @@ pub fn export_keeper(alt_path: Option<&str>) -> Result<()> {
     return Ok(());
 }
 
-/// Imports csv into database. <b>WARNING</b>, overrides may occur!
+/// A single row of the restore index written by [`export_manifest`]: enough
+/// to know what was encrypted and where it came from without opening the
+/// keeper database, and -- unless `with_keys` was requested -- without
+/// exposing the key material needed to decrypt it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub uuid: String,
+    pub filename: String,
+    pub ext: String,
+    pub full_path: PathBuf,
+    pub drive_id: String,
+    pub hash: String,
+    /// Size in bytes of the encrypted `.crypt` file in the crypt folder, if it's
+    /// still there.
+    pub size: Option<u64>,
+    /// Only populated when `export_manifest` was called with `with_keys: true`.
+    pub key: Option<[u8; KEY_SIZE]>,
+    /// Only populated when `export_manifest` was called with `with_keys: true`.
+    pub nonce: Option<[u8; NONCE_SIZE]>,
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Writes a JSON restore index describing every crypt in the keeper to
+/// `alt_path` (defaulting to `crypt_manifest.json` in the config folder).
+///
+/// Unlike [`export_keeper`], this omits key and nonce material by default, so
+/// the manifest is safe to store anywhere without also handing out the means
+/// to decrypt what it describes. Pass `with_keys` to include key/nonce for
+/// full offline recovery -- doing so makes the manifest as sensitive as the
+/// keeper database itself.
+pub fn export_manifest(alt_path: Option<&str>, with_keys: bool) -> Result<()> {
+    let db_crypts = query_keeper_crypt()?;
+    let crypt_folder = get_crypt_folder();
+
+    let entries: Vec<ManifestEntry> = db_crypts
+        .into_iter()
+        .map(|crypt| ManifestEntry {
+            size: fs::metadata(crypt_folder.join(format!("{}.crypt", crypt.filename)))
+                .ok()
+                .map(|m| m.len()),
+            hash: hex_encode(&crypt.hash),
+            key: with_keys.then_some(crypt.key),
+            nonce: with_keys.then_some(crypt.nonce),
+            uuid: crypt.uuid,
+            filename: crypt.filename,
+            ext: crypt.ext,
+            full_path: crypt.full_path,
+            drive_id: crypt.drive_id,
+        })
+        .collect();
+
+    let data = serde_json::to_vec_pretty(&entries)?;
+
+    let path: PathBuf = match alt_path {
+        Some(p) => PathBuf::from_str(p)?,
+        None => {
+            let mut p = get_config_folder();
+            p.push("crypt_manifest.json");
+            p
+        }
+    };
+
+    info!("writing manifest to {}", &path.display());
+    write_contents_to_file(&path, data)?;
+
+    Ok(())
+}
+
+/// How often [`import_keeper`] reports progress via `send_information`.
+const IMPORT_PROGRESS_INTERVAL: usize = 500;
+
+/// Imports a keeper CSV export (see [`export_keeper`]) into the database.
+///
+/// All rows are inserted inside a single transaction, so a large import
+/// doesn't pay a pool checkout + commit per row. A row whose insert fails is
+/// logged and counted as errored, and the rows that did insert are still
+/// committed. Progress is reported every
+/// [`IMPORT_PROGRESS_INTERVAL`] rows, and a final imported/skipped/errored
+/// summary is always printed.
+///
+/// A row that fails to deserialize is skipped rather than inserted as
+/// `FileCrypt::default()` -- silently seeding the keeper with an empty,
+/// keyless row was itself a bug.
+///
+/// <b>WARNING</b>, overrides may occur -- an imported uuid that already
+/// exists in the keeper is overwritten (see [`insert_crypt`]'s `ON CONFLICT`
+/// clause).
 pub fn import_keeper(path: &String) -> Result<()> {
     let mut rdr = csv::ReaderBuilder::new()
         .has_headers(false)
         .from_path(path)?;
 
-    for result in rdr.records() {
+    let mut conn = get_keeper()?;
+    let tx = conn.transaction()?;
+
+    let mut imported = 0usize;
+    let mut skipped = 0usize;
+    let mut errored = 0usize;
+
+    for (i, result) in rdr.records().enumerate() {
         let record: StringRecord = match result {
             Ok(it) => it,
             Err(err) => {
                 error!("Failed to convert csv to StringRecord!: {}", err);
+                skipped += 1;
                 continue;
-            } // TODO: Fix with more elegant handling.
+            }
         };
         let fc: FileCrypt = match record.deserialize(None) {
             Ok(it) => it,
             Err(err) => {
                 error!("Failed to convert StringRecord to FileCrypt!: {}", err);
-                FileCrypt::default()
-            } // TODO: Fix with more elegant handling.
+                skipped += 1;
+                continue;
+            }
         };
-        _ = insert_crypt(&fc);
+        match insert_crypt_with(&tx, &fc) {
+            Ok(()) => imported += 1,
+            Err(err) => {
+                error!("Failed to insert imported row into database!: {}", err);
+                errored += 1;
+            }
+        }
+
+        if (i + 1) % IMPORT_PROGRESS_INTERVAL == 0 {
+            send_information(vec![format!("imported {} rows so far...", i + 1)]);
+        }
     }
 
-    return Ok(());
+    tx.commit()?;
+
+    send_information(vec![format!(
+        "import complete: {} imported, {} skipped (unparseable row), {} errored (insert failed)",
+        imported, skipped, errored
+    )]);
+
+    Ok(())
 }
 
 ///Grabs the connection
@@ -149,11 +499,24 @@ pub fn import_keeper(path: &String) -> Result<()> {
 /// conn.execute("SELECT * FROM *");
 ///```
 pub fn get_keeper() -> Result<r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>> {
-    //Returns the static connection
-    let keeper = KEEPER.get()?;
+    //Returns the static connection, or the initialization error if the pool never came up
+    let pool = KEEPER.as_ref().map_err(|e| {
+        Error::DatabaseError(error::DatabaseError::InitializationFailed(e.to_owned()))
+    })?;
+    let keeper = pool.get()?;
     return Ok(keeper);
 }
 
+/// Reclaims disk space freed by deleted/updated rows and defragments the
+/// database file. Runs on one connection checked out via `get_keeper`; the
+/// pool is built with r2d2's default size, so other connections can be open
+/// alongside it.
+pub fn vacuum() -> Result<()> {
+    let conn = get_keeper()?;
+    conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE); VACUUM;")?;
+    Ok(())
+}
+
 ///Insert a crypt into the database
 ///
 /// # Example:
@@ -162,8 +525,18 @@ pub fn get_keeper() -> Result<r2d2::PooledConnection<r2d2_sqlite::SqliteConnecti
 /// let _ = insert_crypt(&fc);
 ///```
 pub fn insert_crypt(crypt: &FileCrypt) -> Result<()> {
-    //Get the connection
     let conn = get_keeper()?;
+    insert_crypt_with(&conn, crypt)
+}
+
+/// Does the actual work of [`insert_crypt`] against an already-open `conn`,
+/// so a caller inserting many rows (e.g. [`import_keeper`]) can share one
+/// transaction instead of paying a pool checkout and commit per row.
+fn insert_crypt_with(conn: &Connection, crypt: &FileCrypt) -> Result<()> {
+    //Encode metadata fields -- encrypted if `private_metadata` is enabled, plaintext otherwise
+    let filename = encode_field(&crypt.filename)?;
+    let extension = encode_field(&crypt.ext)?;
+    let full_path = encode_field(&crypt.full_path.to_string_lossy())?;
 
     //Create insert command and execute -- should handle uuid conflicts
     conn.execute(
@@ -175,8 +548,13 @@ pub fn insert_crypt(crypt: &FileCrypt) -> Result<()> {
             full_path,
             key_seed,
             nonce_seed,
-            hash
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            hash,
+            permissions,
+            file_type,
+            compressed,
+            hardlinks,
+            hash_algorithm
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
         ON CONFLICT(uuid) DO UPDATE SET
             filename = excluded.filename,
             extension = excluded.extension,
@@ -184,16 +562,26 @@ pub fn insert_crypt(crypt: &FileCrypt) -> Result<()> {
             full_path = excluded.full_path,
             key_seed = excluded.key_seed,
             nonce_seed = excluded.nonce_seed,
-            hash = excluded.hash",
+            hash = excluded.hash,
+            permissions = excluded.permissions,
+            file_type = excluded.file_type,
+            compressed = excluded.compressed,
+            hardlinks = excluded.hardlinks,
+            hash_algorithm = excluded.hash_algorithm",
         params![
             &crypt.uuid,
-            &crypt.filename,
-            &crypt.ext,
+            &filename,
+            &extension,
             &crypt.drive_id,
-            &crypt.full_path.to_str().unwrap_or_default(),
+            &full_path,
             &crypt.key.as_ref(),
             &crypt.nonce.as_ref(),
             &crypt.hash.as_ref(),
+            &crypt.permissions,
+            &crypt.file_type,
+            &crypt.compressed,
+            &crypt.hardlinks,
+            &crypt.hash_algorithm.to_string(),
         ],
     )?;
 
@@ -234,6 +622,50 @@ pub fn insert_token(user_token: &UserToken) -> Result<()> {
     return Ok(());
 }
 
+///Updates only the `drive_id` column on an existing crypt, leaving everything
+///else untouched. Used to re-associate a local crypt with a cloud file whose
+///id was lost (e.g. after a database restore) without re-uploading it.
+///
+/// # Example:
+///```ignore
+/// let uuid = generate_uuid();
+/// let _ = update_drive_id(&uuid, "1AbCdEfGhIjKlMnOpQrStUv");
+///```
+pub fn update_drive_id(uuid: &str, drive_id: &str) -> Result<()> {
+    //Get the connection
+    let conn = get_keeper()?;
+
+    conn.execute(
+        "UPDATE crypt SET drive_id = ?1 WHERE uuid = ?2",
+        params![drive_id, uuid],
+    )?;
+
+    return Ok(());
+}
+
+///Updates only the `full_path` column on an existing crypt, leaving everything
+///else untouched. Used when the source file has been moved on disk so that
+///`query_keeper_for_existing_file` and re-encrypt lookups keep matching it.
+///
+/// # Example:
+///```ignore
+/// let uuid = generate_uuid();
+/// let _ = update_full_path(&uuid, Path::new("/new/location/file.txt"));
+///```
+pub fn update_full_path(uuid: &str, new_path: &Path) -> Result<()> {
+    //Get the connection
+    let conn = get_keeper()?;
+
+    let full_path = encode_field(&new_path.to_string_lossy())?;
+
+    conn.execute(
+        "UPDATE crypt SET full_path = ?1 WHERE uuid = ?2",
+        params![full_path, uuid],
+    )?;
+
+    return Ok(());
+}
+
 ///Queries the database for the crypt
 ///
 /// # Example:
@@ -251,24 +683,49 @@ pub fn query_crypt(uuid: String) -> Result<FileCrypt> {
         FROM crypt
         WHERE uuid = ?1",
         params![uuid],
-        |row| {
-            let path: String = row.get(4)?;
-            Ok(FileCrypt {
-                uuid: row.get(0)?,
-                filename: row.get(1)?,
-                ext: row.get(2)?,
-                drive_id: row.get(3)?,
-                full_path: PathBuf::from(path),
-                key: row.get(5)?,
-                nonce: row.get(6)?,
-                hash: row.get(7)?,
-            })
-        },
+        row_to_filecrypt,
     )?;
 
     return Ok(filecrypt);
 }
 
+///Resolves a uuid prefix (e.g. the first 8 characters, like a git short hash) to
+///the single crypt it identifies, so callers don't need the full 36-char uuid.
+///Matching is case-insensitive, courtesy of SQLite's default `LIKE` behavior.
+///
+///Errors with [`crate::error::DatabaseError::AmbiguousUuid`] if `prefix` matches
+///more than one crypt, and [`crate::error::DatabaseError::NotFound`] if it matches none.
+///
+/// # Example:
+///```ignore
+/// let fc = query_crypt_by_prefix("a1b2c3d4");
+///```
+pub fn query_crypt_by_prefix(prefix: &str) -> Result<FileCrypt> {
+    //Get the connection
+    let conn = get_keeper()?;
+
+    //Create the query and execute
+    let mut query = conn.prepare(
+        "SELECT *
+        FROM crypt
+        WHERE uuid LIKE ?1",
+    )?;
+
+    //Get the results of the query
+    let query_result = query.query_map(params![format!("{}%", prefix)], row_to_filecrypt)?;
+
+    let mut matches = vec![];
+    for crypt in query_result.into_iter() {
+        matches.push(crypt?);
+    }
+
+    match matches.len() {
+        0 => Err(error::DatabaseError::NotFound(prefix.to_string()).into()),
+        1 => Ok(matches.remove(0)),
+        n => Err(error::DatabaseError::AmbiguousUuid(prefix.to_string(), n).into()),
+    }
+}
+
 ///Queries the database for the token
 ///
 /// # Example:
@@ -311,28 +768,31 @@ pub fn query_token(service: CloudService) -> Result<UserToken> {
 /// let fc = insert_crypt(path);
 ///```
 pub fn query_keeper_for_existing_file(full_path: PathBuf) -> Result<FileCrypt> {
+    // When metadata is encrypted at rest, `full_path` can't be matched via SQL --
+    // fall back to scanning and decrypting every row.
+    if get_metadata_key().is_some() {
+        return query_keeper_crypt()?
+            .into_iter()
+            .find(|fc| fc.full_path == full_path)
+            .ok_or(Error::DbError(rusqlite::Error::QueryReturnedNoRows));
+    }
+
     //Get the connection
     let conn = get_keeper()?;
 
+    // `full_path` is stored via `encode_field`, which -- even with no metadata
+    // key active -- writes plain bytes as a BLOB parameter rather than TEXT.
+    // Comparing against a bare `String` param would never match, since SQLite
+    // treats BLOB and TEXT storage classes as unequal regardless of content.
+    let encoded = encode_field(&full_path.to_string_lossy())?;
+
     //Get the results of the query
     let filecrypt = conn.query_row(
         "SELECT *
         FROM crypt
         WHERE full_path = ?1",
-        params![full_path.to_str().unwrap_or_default().to_string()],
-        |row| {
-            let path: String = row.get(4)?;
-            Ok(FileCrypt {
-                uuid: row.get(0)?,
-                filename: row.get(1)?,
-                ext: row.get(2)?,
-                drive_id: row.get(3)?,
-                full_path: PathBuf::from(path),
-                key: row.get(5)?,
-                nonce: row.get(6)?,
-                hash: row.get(7)?,
-            })
-        },
+        params![encoded],
+        row_to_filecrypt,
     )?;
 
     return Ok(filecrypt);
@@ -347,6 +807,16 @@ pub fn query_keeper_for_existing_file(full_path: PathBuf) -> Result<FileCrypt> {
 ///```
 pub fn query_keeper_by_file_name<T: AsRef<Path>>(file_name: &T) -> Result<FileCrypt> {
     let file_name = file_name.as_ref();
+
+    // When metadata is encrypted at rest, `filename` can't be matched via SQL --
+    // fall back to scanning and decrypting every row.
+    if get_metadata_key().is_some() {
+        return query_keeper_crypt()?
+            .into_iter()
+            .find(|fc| fc.filename == file_name.display().to_string())
+            .ok_or(Error::DbError(rusqlite::Error::QueryReturnedNoRows));
+    }
+
     //Get the connection
     let conn = get_keeper()?;
 
@@ -356,19 +826,7 @@ pub fn query_keeper_by_file_name<T: AsRef<Path>>(file_name: &T) -> Result<FileCr
         FROM crypt
         WHERE filename = ?1",
         params![file_name.display().to_string()],
-        |row| {
-            let get: String = row.get(4)?;
-            Ok(FileCrypt {
-                uuid: row.get(0)?,
-                filename: row.get(1)?,
-                ext: row.get(2)?,
-                drive_id: row.get(3)?,
-                full_path: PathBuf::from(get),
-                key: row.get(5)?,
-                nonce: row.get(6)?,
-                hash: row.get(7)?,
-            })
-        },
+        row_to_filecrypt,
     )?;
 
     return Ok(filecrypt);
@@ -388,23 +846,7 @@ pub fn query_keeper_for_files_with_drive_id() -> Result<Vec<FileCrypt>> {
     )?;
 
     //Get the results of the query
-    let query_result = query.query_map([], |row| {
-        let path: String = row.get(4)?;
-        let key: [u8; KEY_SIZE] = row.get(5)?;
-        let nonce: [u8; NONCE_SIZE] = row.get(6)?;
-        let hash: [u8; KEY_SIZE] = row.get(7)?;
-
-        Ok(FileCrypt {
-            uuid: row.get(0)?,
-            filename: row.get(1)?,
-            ext: row.get(2)?,
-            drive_id: row.get(3)?,
-            full_path: PathBuf::from(path),
-            key,
-            nonce,
-            hash,
-        })
-    })?;
+    let query_result = query.query_map([], row_to_filecrypt)?;
 
     //Convert the results into a vector
     let mut crypts = vec![];
@@ -415,7 +857,8 @@ pub fn query_keeper_for_files_with_drive_id() -> Result<Vec<FileCrypt>> {
     return Ok(crypts);
 }
 
-///Queries the database for all crypts
+///Queries the database for all crypts, ordered by filename then extension so
+///callers (e.g. `keeper list`) get stable, reproducible output across calls.
 ///
 /// # Example:
 ///```ignore
@@ -430,27 +873,12 @@ pub fn query_keeper_crypt() -> Result<Vec<FileCrypt>> {
     let mut query = conn.prepare(
         "
         SELECT *
-        FROM crypt",
+        FROM crypt
+        ORDER BY filename, extension",
     )?;
 
     //Get the results of the query
-    let query_result = query.query_map([], |row| {
-        let get: String = row.get(4)?;
-        let key: [u8; KEY_SIZE] = row.get(5)?;
-        let nonce: [u8; NONCE_SIZE] = row.get(6)?;
-        let hash: [u8; KEY_SIZE] = row.get(7)?;
-
-        Ok(FileCrypt {
-            uuid: row.get(0)?,
-            filename: row.get(1)?,
-            ext: row.get(2)?,
-            drive_id: row.get(3)?,
-            full_path: PathBuf::from(get),
-            key,
-            nonce,
-            hash,
-        })
-    })?;
+    let query_result = query.query_map([], row_to_filecrypt)?;
 
     //Convert the results into a vector
     let mut crypts = vec![];
@@ -461,6 +889,34 @@ pub fn query_keeper_crypt() -> Result<Vec<FileCrypt>> {
     return Ok(crypts);
 }
 
+/// A `.crypt` file found on disk whose uuid has no matching row in the
+/// keeper, so it can't be decrypted locally until a keeper export
+/// containing that uuid (see [`export_keeper`]) is imported.
+#[derive(Debug, Clone)]
+pub struct OrphanCrypt {
+    pub uuid: String,
+    pub path: PathBuf,
+}
+
+/// Scans the crypt folder for `.crypt` files with no matching keeper row --
+/// the inverse of a DB row with no backing file. Reads each file's uuid via
+/// [`get_uuid_from_file`] and checks it against [`query_crypt`]; a file whose
+/// uuid can't even be read (too short/corrupt) is skipped rather than
+/// reported, since it isn't a recoverable orphan.
+pub fn find_orphaned_crypts() -> Result<Vec<OrphanCrypt>> {
+    let (files, _) = walk_crypt_folder()?;
+
+    let orphans = files
+        .into_iter()
+        .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("crypt"))
+        .filter_map(|path| get_uuid_from_file(&path).ok().map(|uuid| (uuid, path)))
+        .filter(|(uuid, _)| query_crypt(uuid.clone()).is_err())
+        .map(|(uuid, path)| OrphanCrypt { uuid, path })
+        .collect();
+
+    Ok(orphans)
+}
+
 ///Queries the database for all tokens
 // /
 // / # Example:
@@ -539,3 +995,308 @@ pub fn delete_keeper() -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_migrate_crypt_path_moves_files() {
+        let old_path = std::env::temp_dir().join("crypt_migrate_test_old");
+        let new_path = std::env::temp_dir().join("crypt_migrate_test_new");
+        _ = fs::remove_dir_all(&old_path);
+        _ = fs::remove_dir_all(&new_path);
+        fs::create_dir_all(old_path.join("sub")).unwrap();
+        fs::create_dir_all(&new_path).unwrap();
+
+        fs::write(old_path.join("one.crypt"), b"one").unwrap();
+        fs::write(old_path.join("sub").join("two.crypt"), b"two").unwrap();
+
+        let moved = migrate_crypt_path(&old_path, &new_path).unwrap();
+
+        assert_eq!(moved, 2);
+        assert!(new_path.join("one.crypt").exists());
+        assert!(new_path.join("sub").join("two.crypt").exists());
+        assert!(!old_path.join("one.crypt").exists());
+
+        fs::remove_dir_all(&old_path).unwrap();
+        fs::remove_dir_all(&new_path).unwrap();
+    }
+
+    #[test]
+    fn test_update_full_path_changes_stored_path() {
+        let _guard = crate::test_support::db_test_guard();
+        let fc = FileCrypt {
+            uuid: "update-full-path-test".to_string(),
+            full_path: PathBuf::from("/old/location/file.txt"),
+            ..Default::default()
+        };
+        insert_crypt(&fc).unwrap();
+
+        let new_path = PathBuf::from("/new/location/file.txt");
+        update_full_path(&fc.uuid, &new_path).unwrap();
+
+        let updated = query_crypt(fc.uuid.clone()).unwrap();
+        assert_eq!(updated.full_path, new_path);
+
+        delete_crypt(fc.uuid).unwrap();
+    }
+
+    #[test]
+    fn test_query_crypt_by_prefix_resolves_unique_match() {
+        let _guard = crate::test_support::db_test_guard();
+        let fc = FileCrypt {
+            uuid: "prefix-test-unique-11111111".to_string(),
+            ..Default::default()
+        };
+        insert_crypt(&fc).unwrap();
+
+        let found = query_crypt_by_prefix("prefix-test-unique").unwrap();
+        assert_eq!(found.uuid, fc.uuid);
+
+        let found = query_crypt_by_prefix("PREFIX-TEST-UNIQUE").unwrap();
+        assert_eq!(found.uuid, fc.uuid);
+
+        delete_crypt(fc.uuid).unwrap();
+    }
+
+    #[test]
+    fn test_query_crypt_by_prefix_errors_when_ambiguous() {
+        let _guard = crate::test_support::db_test_guard();
+        let fc_a = FileCrypt {
+            uuid: "prefix-test-ambiguous-aaaa".to_string(),
+            ..Default::default()
+        };
+        let fc_b = FileCrypt {
+            uuid: "prefix-test-ambiguous-bbbb".to_string(),
+            ..Default::default()
+        };
+        insert_crypt(&fc_a).unwrap();
+        insert_crypt(&fc_b).unwrap();
+
+        let err = query_crypt_by_prefix("prefix-test-ambiguous").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::DatabaseError(error::DatabaseError::AmbiguousUuid(_, 2))
+        ));
+
+        delete_crypt(fc_a.uuid).unwrap();
+        delete_crypt(fc_b.uuid).unwrap();
+    }
+
+    #[test]
+    fn test_query_crypt_by_prefix_errors_when_no_match() {
+        let _guard = crate::test_support::db_test_guard();
+        let err = query_crypt_by_prefix("prefix-test-does-not-exist").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::DatabaseError(error::DatabaseError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_vacuum_shrinks_database_after_bulk_delete() {
+        let _guard = crate::test_support::db_test_guard();
+        let path = get_config().database_path;
+
+        let mut uuids = Vec::new();
+        for i in 0..500 {
+            let fc = FileCrypt {
+                uuid: format!("vacuum-test-{}", i),
+                filename: "x".repeat(2000),
+                ..Default::default()
+            };
+            uuids.push(fc.uuid.clone());
+            insert_crypt(&fc).unwrap();
+        }
+
+        let before_delete = fs::metadata(&path).unwrap().len();
+
+        for uuid in uuids {
+            delete_crypt(uuid).unwrap();
+        }
+
+        vacuum().unwrap();
+
+        let after = fs::metadata(&path).unwrap().len();
+
+        assert!(after < before_delete);
+    }
+
+    #[test]
+    fn test_export_manifest_omits_keys_unless_requested() {
+        let _guard = crate::test_support::db_test_guard();
+        let fc = FileCrypt {
+            uuid: "manifest-test-uuid".to_string(),
+            filename: "manifest-test-file".to_string(),
+            ext: ".txt".to_string(),
+            full_path: PathBuf::from("/original/location/manifest-test-file.txt"),
+            drive_id: "drive-id-123".to_string(),
+            hash: [7u8; KEY_SIZE],
+            ..Default::default()
+        };
+        insert_crypt(&fc).unwrap();
+
+        let manifest_path = std::env::temp_dir().join("crypt_manifest_test.json");
+        let manifest_path = manifest_path.to_str().unwrap();
+
+        export_manifest(Some(manifest_path), false).unwrap();
+        let entries: Vec<ManifestEntry> =
+            serde_json::from_slice(&fs::read(manifest_path).unwrap()).unwrap();
+        let entry = entries.iter().find(|e| e.uuid == fc.uuid).unwrap();
+        assert_eq!(entry.filename, fc.filename);
+        assert_eq!(entry.full_path, fc.full_path);
+        assert_eq!(entry.hash, hex_encode(&fc.hash));
+        assert!(entry.key.is_none());
+        assert!(entry.nonce.is_none());
+
+        export_manifest(Some(manifest_path), true).unwrap();
+        let entries: Vec<ManifestEntry> =
+            serde_json::from_slice(&fs::read(manifest_path).unwrap()).unwrap();
+        let entry = entries.iter().find(|e| e.uuid == fc.uuid).unwrap();
+        assert_eq!(entry.key, Some(fc.key));
+        assert_eq!(entry.nonce, Some(fc.nonce));
+
+        delete_crypt(fc.uuid).unwrap();
+        fs::remove_file(manifest_path).unwrap();
+    }
+
+    #[test]
+    fn test_import_keeper_large_csv_commits_as_one_transaction_and_skips_bad_rows() {
+        let _guard = crate::test_support::db_test_guard();
+        const ROWS: usize = IMPORT_PROGRESS_INTERVAL + 50;
+
+        let uuids: Vec<String> = (0..ROWS)
+            .map(|i| format!("import-large-test-{}", i))
+            .collect();
+
+        let mut wtr = WriterBuilder::new().has_headers(false).from_writer(vec![]);
+        for uuid in &uuids {
+            wtr.serialize(FileCrypt {
+                uuid: uuid.clone(),
+                filename: format!("file-{}", uuid),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        let mut data = wtr.into_inner().map_err(|e| e.into_error()).unwrap();
+        // an unparseable row -- too few fields to deserialize into a FileCrypt --
+        // should be skipped rather than inserted as a blank `FileCrypt::default()`.
+        data.extend_from_slice(b"not,enough,fields\n");
+
+        let csv_path = std::env::temp_dir().join("crypt_import_large_test.csv");
+        fs::write(&csv_path, &data).unwrap();
+
+        import_keeper(&csv_path.to_string_lossy().to_string()).unwrap();
+
+        for uuid in &uuids {
+            assert!(query_crypt(uuid.clone()).is_ok());
+        }
+        // the malformed row must not have shown up as a default-filled row.
+        assert!(query_keeper_crypt()
+            .unwrap()
+            .iter()
+            .all(|fc| !fc.uuid.is_empty()));
+
+        for uuid in uuids {
+            delete_crypt(uuid).unwrap();
+        }
+        fs::remove_file(csv_path).unwrap();
+    }
+
+    #[test]
+    fn test_find_orphaned_crypts_reports_files_with_no_matching_row() {
+        let _guard = crate::test_support::db_test_guard();
+        use crate::filecrypt::prepend_uuid;
+
+        let crypt_folder = get_crypt_folder();
+
+        let orphan_uuid = uuid::Uuid::new_v4().to_string();
+        let orphan_path = crypt_folder.join("orphan_find_test.crypt");
+        fs::write(
+            &orphan_path,
+            prepend_uuid(&orphan_uuid, &mut b"fake encrypted contents".to_vec()),
+        )
+        .unwrap();
+
+        // a tracked crypt with both a keeper row and a backing file should not
+        // be reported as an orphan.
+        let tracked_uuid = uuid::Uuid::new_v4().to_string();
+        let tracked = FileCrypt {
+            uuid: tracked_uuid.clone(),
+            filename: "orphan_find_test_tracked".to_string(),
+            ..Default::default()
+        };
+        insert_crypt(&tracked).unwrap();
+        let tracked_path = crypt_folder.join("orphan_find_test_tracked.crypt");
+        fs::write(
+            &tracked_path,
+            prepend_uuid(&tracked_uuid, &mut b"tracked contents".to_vec()),
+        )
+        .unwrap();
+
+        let orphans = find_orphaned_crypts().unwrap();
+        assert!(orphans
+            .iter()
+            .any(|o| o.uuid == orphan_uuid && o.path == orphan_path));
+        assert!(orphans.iter().all(|o| o.uuid != tracked_uuid));
+
+        delete_crypt(tracked_uuid).unwrap();
+        fs::remove_file(orphan_path).unwrap();
+        fs::remove_file(tracked_path).unwrap();
+    }
+
+    #[test]
+    fn test_import_keeper_one_malformed_line_keeps_only_good_rows() {
+        let _guard = crate::test_support::db_test_guard();
+        let good = FileCrypt {
+            uuid: "import-malformed-test-good".to_string(),
+            filename: "good-file".to_string(),
+            ..Default::default()
+        };
+
+        let mut wtr = WriterBuilder::new().has_headers(false).from_writer(vec![]);
+        wtr.serialize(&good).unwrap();
+        let mut data = wtr.into_inner().map_err(|e| e.into_error()).unwrap();
+        data.extend_from_slice(b"this,is,not,a,valid,filecrypt,row\n");
+
+        let csv_path = std::env::temp_dir().join("crypt_import_malformed_test.csv");
+        fs::write(&csv_path, &data).unwrap();
+
+        import_keeper(&csv_path.to_string_lossy().to_string()).unwrap();
+
+        let crypts = query_keeper_crypt().unwrap();
+        assert!(crypts.iter().any(|fc| fc.uuid == good.uuid));
+        assert!(
+            crypts.iter().all(|fc| !fc.uuid.is_empty()),
+            "malformed row must be skipped, not inserted as FileCrypt::default()"
+        );
+
+        delete_crypt(good.uuid).unwrap();
+        fs::remove_file(csv_path).unwrap();
+    }
+
+    #[test]
+    fn test_read_passphrase_line_reads_and_trims_one_line() {
+        let reader = std::io::Cursor::new(b"hunter2\nextra ignored line\n".to_vec());
+        assert_eq!(read_passphrase_line(reader), "hunter2");
+    }
+
+    #[test]
+    fn test_read_passphrase_non_interactive_reads_from_stdin_line_when_requested() {
+        // `read_passphrase_non_interactive(true)` reads real stdin, so exercise the
+        // exact same line-reading logic it delegates to instead of the real stdin.
+        let reader = std::io::Cursor::new(b"stdin-passphrase\n".to_vec());
+        assert_eq!(read_passphrase_line(reader), "stdin-passphrase");
+    }
+
+    #[test]
+    fn test_read_passphrase_non_interactive_falls_back_to_env_var() {
+        std::env::set_var("CRYPT_PASSWORD", "env-passphrase");
+        let passphrase = read_passphrase_non_interactive(false);
+        std::env::remove_var("CRYPT_PASSWORD");
+
+        assert_eq!(passphrase, Some("env-passphrase".to_string()));
+    }
+
+}
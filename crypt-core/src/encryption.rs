@@ -1,14 +1,67 @@
-use crate::{error, filecrypt::FileCrypt, prelude::*};
+use crate::{common, error, filecrypt::FileCrypt, prelude::*};
+use argon2::Argon2;
 use blake2::{Blake2s256, Digest, *};
 use chacha20poly1305::{
     aead::{Aead, AeadCore, KeyInit, OsRng},
     ChaCha20Poly1305, Key, Nonce,
 };
 use logfather::*;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
 
 pub const KEY_SIZE: usize = 32;
 pub const NONCE_SIZE: usize = 12;
 
+/// Which hash function `compute_hash_with_algorithm` uses. Recorded on
+/// [`FileCrypt::hash_algorithm`] so decrypt re-hashes with the same algorithm
+/// the content was originally hashed with.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// BLAKE2s-256, single-threaded. Default -- matches every `FileCrypt`
+    /// written before [`HashAlgorithm::Blake3`] existed.
+    #[default]
+    Blake2s,
+    /// BLAKE3, computed with `blake3::hash`: SIMD-accelerated, on a single
+    /// thread (blake3's `rayon` feature is not enabled, so hashing is not
+    /// multithreaded). Selected once a file's size crosses
+    /// [`crate::config::Config::hash_parallel_threshold`].
+    Blake3,
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Blake2s => write!(f, "blake2s"),
+            Self::Blake3 => write!(f, "blake3"),
+        }
+    }
+}
+
+impl std::str::FromStr for HashAlgorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "blake2s" => Ok(Self::Blake2s),
+            "blake3" => Ok(Self::Blake3),
+            _ => Err(Error::EncryptionError(
+                error::EncryptionError::InvalidHashAlgorithm(s.to_string()),
+            )),
+        }
+    }
+}
+
+/// Picks [`HashAlgorithm::Blake3`] for content at or above `threshold` bytes
+/// (when `threshold` is non-zero), and [`HashAlgorithm::Blake2s`] otherwise.
+pub fn select_hash_algorithm(content_len: usize, threshold: u64) -> HashAlgorithm {
+    if threshold != 0 && content_len as u64 >= threshold {
+        HashAlgorithm::Blake3
+    } else {
+        HashAlgorithm::Blake2s
+    }
+}
+
 /// Computes a 256-bit BLAKE2s hash for the given byte slice contents.
 ///
 /// # Arguments
@@ -29,6 +82,14 @@ pub fn compute_hash(contents: &[u8]) -> [u8; 32] {
     hasher.finalize().into()
 }
 
+/// Computes a 256-bit hash for `contents` using the given [`HashAlgorithm`].
+pub fn compute_hash_with_algorithm(contents: &[u8], algorithm: HashAlgorithm) -> [u8; 32] {
+    match algorithm {
+        HashAlgorithm::Blake2s => compute_hash(contents),
+        HashAlgorithm::Blake3 => blake3::hash(contents).into(),
+    }
+}
+
 /// compress is the Zstd compression algorithm <https://en.wikipedia.org/wiki/Zstd> to deflate file size
 /// prior to encryption.
 ///
@@ -124,6 +185,235 @@ pub fn encrypt(fc: &FileCrypt, contents: &[u8]) -> Result<Vec<u8>> {
         .map_err(|_| Error::EncryptionError(error::EncryptionError::ChaChaError))
 }
 
+/// Length of the per-install salt persisted alongside the config, used by
+/// [`derive_key_from_passphrase`].
+const PASSPHRASE_SALT_SIZE: usize = 16;
+
+lazy_static::lazy_static! {
+    /// Path to the per-install salt used to derive the `private_metadata`
+    /// master key. Kept alongside the rest of the config so a fresh install
+    /// (and therefore a fresh salt) can't be tricked into reusing another
+    /// machine's derived key even if the same passphrase is guessed.
+    static ref PASSPHRASE_SALT_PATH: std::path::PathBuf = {
+        let mut path = common::get_config_folder();
+        path.push(".config");
+        _ = std::fs::create_dir_all(&path);
+        path.push("metadata_salt");
+        path
+    };
+}
+
+/// Loads the per-install passphrase salt, generating and persisting a fresh
+/// random one on first use. Falls back to a fixed salt (logged as an error)
+/// if the salt file can't be read or written, mirroring `load_config`'s
+/// soft-failure behavior -- a missing salt shouldn't take down the whole
+/// program, just weaken this one feature's guarantees until disk access is
+/// restored.
+fn passphrase_salt() -> [u8; PASSPHRASE_SALT_SIZE] {
+    if let Ok(existing) = std::fs::read(PASSPHRASE_SALT_PATH.as_path()) {
+        if existing.len() == PASSPHRASE_SALT_SIZE {
+            let mut salt = [0u8; PASSPHRASE_SALT_SIZE];
+            salt.copy_from_slice(&existing);
+            return salt;
+        }
+    }
+
+    let mut salt = [0u8; PASSPHRASE_SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+    if let Err(err) = std::fs::write(PASSPHRASE_SALT_PATH.as_path(), salt) {
+        error!("failed to persist passphrase salt, falling back to a fixed salt: {}", err);
+        return *b"ByteCrypt-static";
+    }
+    salt
+}
+
+/// Derives a metadata master key from a user-supplied passphrase, for use with
+/// `encrypt_metadata`/`decrypt_metadata` when `private_metadata` is enabled.
+///
+/// Runs the passphrase through Argon2id with a per-install salt instead of a
+/// single unsalted hash -- `extension`/filename values are low-entropy, so an
+/// attacker with read access to the keeper DB could otherwise brute-force
+/// candidate passphrases offline at raw hash speed.
+pub fn derive_key_from_passphrase(passphrase: &str) -> [u8; KEY_SIZE] {
+    let salt = passphrase_salt();
+    let mut key = [0u8; KEY_SIZE];
+    if let Err(err) = Argon2::default().hash_password_into(passphrase.as_bytes(), &salt, &mut key) {
+        error!("argon2 key derivation failed, falling back to an unsalted hash: {}", err);
+        return compute_hash(passphrase.as_bytes());
+    }
+    key
+}
+
+/// Encrypts an arbitrary byte buffer with the given master key. A fresh nonce
+/// is generated per call and prepended to the returned ciphertext, mirroring
+/// how `prepend_uuid` prepends the uuid to encrypted file contents.
+pub fn encrypt_bytes_with_key(key: &[u8; KEY_SIZE], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| Error::EncryptionError(error::EncryptionError::ChaChaError))?;
+
+    let mut out = nonce.to_vec();
+    out.extend(ciphertext);
+    Ok(out)
+}
+
+/// Decrypts a byte buffer previously produced by `encrypt_bytes_with_key`.
+pub fn decrypt_bytes_with_key(key: &[u8; KEY_SIZE], data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < NONCE_SIZE {
+        return Err(Error::EncryptionError(
+            error::EncryptionError::MetadataTooShort,
+        ));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| Error::EncryptionError(error::EncryptionError::ChaChaError))
+}
+
+/// Encrypts a single metadata field (filename, extension, or full path) with
+/// the given master key. Thin wrapper over `encrypt_bytes_with_key`.
+pub fn encrypt_metadata(key: &[u8; KEY_SIZE], plaintext: &str) -> Result<Vec<u8>> {
+    encrypt_bytes_with_key(key, plaintext.as_bytes())
+}
+
+/// Decrypts a metadata field previously produced by `encrypt_metadata`.
+pub fn decrypt_metadata(key: &[u8; KEY_SIZE], data: &[u8]) -> Result<String> {
+    let plaintext = decrypt_bytes_with_key(key, data)?;
+    String::from_utf8(plaintext).map_err(|_| Error::EncryptionError(error::EncryptionError::ChaChaError))
+}
+
+/// Candidate zstd levels tried by [`suggest_zstd_level`], roughly spanning the
+/// fast/light end, the default, and the slow/heavy end of the valid range.
+const ZSTD_LEVEL_CANDIDATES: [i32; 7] = [-7, -3, 1, 3, 9, 15, 22];
+
+/// Benchmarks `sample` at a handful of candidate zstd levels and returns the
+/// level with the best compression ratio among those that finish within
+/// `time_budget`. Levels are tried from fastest to slowest, and the search
+/// stops as soon as a level's compression time exceeds the budget, since
+/// higher levels only get slower from there.
+///
+/// Falls back to the default level (3) if `sample` is empty or every
+/// candidate somehow exceeds the budget.
+pub fn suggest_zstd_level(sample: &[u8], time_budget: Duration) -> i32 {
+    if sample.is_empty() {
+        return 3;
+    }
+
+    let mut best_level = 3;
+    let mut best_ratio = 0f64;
+
+    for &level in ZSTD_LEVEL_CANDIDATES.iter() {
+        let start = Instant::now();
+        let compressed = compress(sample, level);
+        let elapsed = start.elapsed();
+
+        if elapsed > time_budget {
+            break;
+        }
+
+        let ratio = sample.len() as f64 / compressed.len() as f64;
+        if ratio > best_ratio {
+            best_ratio = ratio;
+            best_level = level;
+        }
+    }
+
+    best_level
+}
+
+/// Result of exercising a single cryptographic primitive during [`self_test`].
+#[derive(Debug, Clone)]
+pub struct SelfTestCheck {
+    /// The primitive under test, e.g. `"ChaCha20Poly1305"`.
+    pub algorithm: String,
+    /// What was exercised, e.g. `"encrypt/decrypt round-trip"`.
+    pub detail: String,
+    /// Whether the known-answer test matched.
+    pub passed: bool,
+}
+
+/// Report produced by [`self_test`]: one [`SelfTestCheck`] per cryptographic
+/// primitive the binary relies on, so a corrupted or mismatched build can be
+/// caught before it's trusted with real data.
+#[derive(Debug, Clone)]
+pub struct SelfTestReport {
+    pub checks: Vec<SelfTestCheck>,
+}
+
+impl SelfTestReport {
+    /// `true` only if every check in the report passed.
+    pub fn all_passed(&self) -> bool {
+        self.checks.iter().all(|c| c.passed)
+    }
+}
+
+impl std::fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for check in &self.checks {
+            let status = if check.passed { "PASS" } else { "FAIL" };
+            writeln!(f, "[{}] {} - {}", status, check.algorithm, check.detail)?;
+        }
+        write!(
+            f,
+            "self-test {}",
+            if self.all_passed() { "PASSED" } else { "FAILED" }
+        )
+    }
+}
+
+/// Runs each cryptographic primitive `crypt` relies on against a
+/// deterministic, known-answer test vector and reports whether the running
+/// binary's build of that primitive still behaves as expected. Intended as a
+/// quick "is this binary tampered or broken" check -- see `crypt selftest`.
+pub fn self_test() -> Result<SelfTestReport> {
+    let mut checks = Vec::new();
+
+    let fc = FileCrypt::new(
+        "selftest".to_string(),
+        "".to_string(),
+        "".to_string(),
+        std::path::PathBuf::from(""),
+        [0u8; KEY_SIZE],
+    );
+    let plaintext = b"the quick brown fox jumps over the lazy dog";
+    let chacha_passed = encrypt(&fc, plaintext)
+        .and_then(|ciphertext| decrypt(fc.clone(), &ciphertext))
+        .map(|decrypted| decrypted == plaintext)
+        .unwrap_or(false);
+    checks.push(SelfTestCheck {
+        algorithm: format!(
+            "ChaCha20Poly1305 ({KEY_SIZE}-byte key, {NONCE_SIZE}-byte nonce)"
+        ),
+        detail: "encrypt/decrypt round-trip".to_string(),
+        passed: chacha_passed,
+    });
+
+    let known_hash: [u8; 32] = [
+        79, 124, 186, 26, 222, 68, 179, 58, 201, 141, 84, 168, 242, 8, 48, 130, 131, 223, 134, 150,
+        210, 132, 93, 249, 24, 62, 200, 173, 167, 129, 67, 242,
+    ];
+    checks.push(SelfTestCheck {
+        algorithm: "Blake2s256".to_string(),
+        detail: "known-answer hash of \"hello there\"".to_string(),
+        passed: compute_hash(b"hello there") == known_hash,
+    });
+
+    let sample = b"zstd self-test payload, repeated for a better compression ratio. \
+                   zstd self-test payload, repeated for a better compression ratio.";
+    let zstd_passed = decompress(&compress(sample, 3))
+        .map(|decompressed| decompressed == sample)
+        .unwrap_or(false);
+    checks.push(SelfTestCheck {
+        algorithm: "zstd".to_string(),
+        detail: "compress/decompress round-trip".to_string(),
+        passed: zstd_passed,
+    });
+
+    Ok(SelfTestReport { checks })
+}
+
 // cargo nextest run
 #[cfg(test)]
 mod test {
@@ -191,4 +481,63 @@ mod test {
         let dec = decrypt(fc, &res).unwrap();
         assert_eq!(contents, dec.as_slice());
     }
+
+    #[test]
+    fn test_metadata_encrypt_decrypt_round_trip() {
+        let key = derive_key_from_passphrase("correct horse battery staple");
+        let plaintext = "secret_filename.txt";
+
+        let ciphertext = encrypt_metadata(&key, plaintext).unwrap();
+        assert_ne!(ciphertext.as_slice(), plaintext.as_bytes());
+
+        let decrypted = decrypt_metadata(&key, &ciphertext).unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn test_metadata_decrypt_wrong_key_fails() {
+        let key = derive_key_from_passphrase("correct horse battery staple");
+        let wrong_key = derive_key_from_passphrase("incorrect horse battery staple");
+
+        let ciphertext = encrypt_metadata(&key, "secret_filename.txt").unwrap();
+        assert!(decrypt_metadata(&wrong_key, &ciphertext).is_err());
+    }
+
+    #[test]
+    fn test_suggest_zstd_level_incompressible() {
+        let sample: Vec<u8> = (0..4096u32).map(|i| (i % 251) as u8).collect();
+        let level = suggest_zstd_level(&sample, Duration::from_secs(5));
+        assert!(ZSTD_LEVEL_CANDIDATES.contains(&level));
+    }
+
+    #[test]
+    fn test_suggest_zstd_level_highly_compressible() {
+        let sample = vec![b'a'; 4096];
+        let level = suggest_zstd_level(&sample, Duration::from_secs(5));
+        assert!(ZSTD_LEVEL_CANDIDATES.contains(&level));
+    }
+
+    #[test]
+    fn test_self_test_all_checks_pass() {
+        let report = self_test().unwrap();
+        assert!(report.all_passed());
+        assert_eq!(report.checks.len(), 3);
+    }
+
+    #[test]
+    fn test_hash_algorithm_round_trips_through_display_and_from_str() {
+        for algorithm in [HashAlgorithm::Blake2s, HashAlgorithm::Blake3] {
+            let parsed: HashAlgorithm = algorithm.to_string().parse().unwrap();
+            assert_eq!(algorithm, parsed);
+        }
+        assert!("not-a-hash-algorithm".parse::<HashAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn test_select_hash_algorithm_respects_threshold() {
+        assert_eq!(select_hash_algorithm(1024, 0), HashAlgorithm::Blake2s);
+        assert_eq!(select_hash_algorithm(1024, 2048), HashAlgorithm::Blake2s);
+        assert_eq!(select_hash_algorithm(2048, 2048), HashAlgorithm::Blake3);
+        assert_eq!(select_hash_algorithm(4096, 2048), HashAlgorithm::Blake3);
+    }
 }
@@ -45,6 +45,13 @@ pub enum Error {
     #[error(transparent)]
     WalkDirError(#[from] walkdir::Error),
 
+    #[error(transparent)]
+    IgnoreError(#[from] ignore::Error),
+
+    // #################### KeyStore Errors ####################
+    #[error(transparent)]
+    KeyStoreError(#[from] KeyStoreError),
+
     /// Errors that should/will never happen.
     #[error(transparent)]
     Infallible(#[from] std::convert::Infallible),
@@ -61,10 +68,40 @@ pub enum TokenError {
 
     #[error("Expired token.")]
     ExpiredToken,
+
+    #[error("Invalid cloud scope \"{0}\". Expected one of: full, file, readonly.")]
+    InvalidScope(String),
 }
 
 #[derive(Debug, Error)]
-pub enum DatabaseError {}
+pub enum DatabaseError {
+    #[error("failed to initialize the crypt keeper database: {0}")]
+    InitializationFailed(String),
+
+    /// A uuid prefix matched more than one crypt -- the caller needs to type more of it.
+    #[error("uuid prefix \"{0}\" is ambiguous: matches {1} crypts")]
+    AmbiguousUuid(String, usize),
+
+    /// A uuid prefix matched no crypt at all.
+    #[error("no crypt found matching uuid prefix \"{0}\"")]
+    NotFound(String),
+}
+
+/// Error types for the [`crate::keystore::KeyStore`] abstraction.
+#[derive(Debug, Error)]
+pub enum KeyStoreError {
+    /// No entry exists for the given uuid or `full_path`.
+    #[error("no key store entry found for {0}")]
+    NotFound(String),
+
+    /// The underlying backend (e.g. the OS keychain) reported a failure.
+    #[error("key store backend error: {0}")]
+    Backend(String),
+
+    /// Config named a backend `KeyStoreBackend::from_str` doesn't recognize.
+    #[error("invalid key store backend \"{0}\". Expected one of: sqlite, keyring.")]
+    InvalidBackend(String),
+}
 
 /// Represents various errors that can occur during file decryption.
 ///
@@ -120,12 +157,33 @@ pub enum FcError {
 
     #[error("Decryption failed: {0}")]
     DecryptError(String),
+
+    #[error("file is {0} bytes, exceeding the configured max_file_size of {1} bytes. Pass --allow-large to encrypt it anyway")]
+    FileTooLarge(u64, u64),
+
+    /// Authenticated decryption succeeded (the Poly1305 tag checked out) but the
+    /// resulting plaintext failed to decompress -- distinct from `DecryptError`,
+    /// which means the AEAD tag itself didn't match (e.g. wrong key).
+    #[error("decrypted contents failed to decompress, though decryption succeeded: {0}")]
+    CorruptAfterDecrypt(String),
+
+    #[error("Invalid decrypt naming scheme \"{0}\". Expected one of: subfolder, suffix, inline.")]
+    InvalidDecryptNaming(String),
+
+    #[error("Invalid encrypt collision policy \"{0}\". Expected one of: rename, skip, overwrite.")]
+    InvalidEncryptCollision(String),
 }
 
 #[derive(Debug, Error)]
 pub enum EncryptionError {
     #[error("ChaChaPoly1305 Error")]
     ChaChaError,
+
+    #[error("encrypted metadata field is too short to contain a nonce")]
+    MetadataTooShort,
+
+    #[error("invalid hash algorithm \"{0}\". Expected one of: blake2s, blake3.")]
+    InvalidHashAlgorithm(String),
 }
 
 #[derive(Debug, Error)]
@@ -135,4 +193,21 @@ pub enum CommonError {
 
     #[error("user aborted file search")]
     UserAbort,
+
+    /// Filenames are arbitrary bytes on Unix, but `FileCrypt`/the `crypt` table
+    /// store them as `String`, so a non-UTF-8 name gets mangled (via
+    /// `to_string_lossy`) on encrypt and can't be restored byte-for-byte on
+    /// decrypt. Not currently surfaced as a hard error -- see the warning
+    /// logged in `get_file_info`.
+    #[error("filename \"{0}\" is not valid UTF-8 and will be stored as a lossy approximation -- the original bytes cannot be restored on decrypt")]
+    NonUtf8Filename(String),
+
+    #[error("output path \"{0}\" resolves outside of the crypt folder \"{1}\"")]
+    PathTraversal(String, String),
+
+    #[error("cannot write to output directory \"{0}\": {1} -- check permissions or free disk space")]
+    OutputNotWritable(String, String),
+
+    #[error("invalid tree charset \"{0}\". Expected one of: unicode, ascii, plain.")]
+    InvalidTreeCharset(String),
 }
@@ -0,0 +1,90 @@
+//! A pluggable event sink so front-ends (CLI/TUI/GUI -- see
+//! [`crate::config::Interface`]) can observe operations instead of everything
+//! being hardcoded to `println!`. [`send_information`](crate::common::send_information)
+//! and file/upload progress reporting are routed through here; a GUI registers
+//! its own [`EventSink`] with [`set_event_sink`] to receive structured
+//! [`Event`]s instead of scraping stdout.
+
+use lazy_static::lazy_static;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// A single observable occurrence during a `crypt` operation.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// `path` was successfully encrypted into a `FileCrypt` identified by `uuid`.
+    FileEncrypted { path: PathBuf, uuid: String },
+
+    /// Running byte-count progress for an upload (or upload-adjacent) operation.
+    UploadProgress {
+        path: PathBuf,
+        bytes_done: u64,
+        total_bytes: u64,
+    },
+
+    /// Something went wrong; the message is already formatted for display.
+    Error(String),
+
+    /// General informational message; already formatted for display.
+    Info(String),
+}
+
+/// A registered handler for [`Event`]s.
+pub type EventSink = Box<dyn Fn(Event) + Send + Sync>;
+
+/// The default sink: prints to stdout, matching the CLI's original behavior.
+fn default_sink(event: Event) {
+    match event {
+        Event::FileEncrypted { path, uuid } => {
+            println!("encrypted {} ({})", path.display(), uuid)
+        }
+        Event::UploadProgress {
+            path,
+            bytes_done,
+            total_bytes,
+        } => println!("{}: {} / {} bytes", path.display(), bytes_done, total_bytes),
+        Event::Error(message) => println!("error: {}", message),
+        Event::Info(message) => println!("{}", message),
+    }
+}
+
+lazy_static! {
+    static ref SINK: RwLock<EventSink> = RwLock::new(Box::new(default_sink));
+}
+
+/// Registers `sink` as the handler for all future [`Event`]s, replacing whatever
+/// was registered before. A front-end calls this once at startup to observe
+/// operations instead of parsing stdout.
+pub fn set_event_sink(sink: EventSink) {
+    *SINK.write().expect("Cannot write event sink") = sink;
+}
+
+/// Dispatches `event` to the currently registered sink.
+pub fn emit(event: Event) {
+    (SINK.read().expect("Cannot read event sink"))(event);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_set_event_sink_receives_emitted_events() {
+        let received: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+        let received_clone = received.clone();
+
+        set_event_sink(Box::new(move |event| {
+            if let Event::Info(message) = event {
+                received_clone.lock().unwrap().push(message);
+            }
+        }));
+
+        emit(Event::Info("hello from a test".to_string()));
+
+        assert_eq!(received.lock().unwrap().as_slice(), ["hello from a test"]);
+
+        // restore the default sink so later tests in this process aren't affected
+        set_event_sink(Box::new(default_sink));
+    }
+}
@@ -1,16 +1,20 @@
 use crate::{
     common::{
-        chooser, get_crypt_folder, get_file_contents, get_full_file_path, get_vec_file_bytes,
+        self, chooser, get_crypt_folder, get_file_contents, get_full_file_path, get_vec_file_bytes,
         write_contents_to_file,
     },
     config::get_config,
-    db::{insert_crypt, query_crypt},
     encryption::{
-        compress, compute_hash, decompress, decrypt, encrypt, generate_seeds, KEY_SIZE, NONCE_SIZE,
+        compress, compute_hash, compute_hash_with_algorithm, decompress, decrypt, encrypt,
+        generate_seeds, select_hash_algorithm, HashAlgorithm, KEY_SIZE, NONCE_SIZE,
     },
     error,
+    events::{emit, Event},
+    keystore,
     prelude::*,
 };
+#[cfg(feature = "thumbnails")]
+use crate::encryption::encrypt_bytes_with_key;
 use logfather::*;
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
@@ -21,6 +25,12 @@ use std::{
     time::Duration,
 };
 
+/// Length in bytes of the uuid prefix stored at the front of every `.crypt`
+/// file, ahead of the encrypted contents. Defined once so a future format
+/// change (e.g. adding a version/format byte) only needs one edit -- see
+/// `strip_prefix` and `read_prefix`.
+pub const UUID_PREFIX_LEN: usize = 36;
+
 /// Represents cryptographic information associated with an encrypted file.
 ///
 /// The `FileCrypt` struct contains details such as the UUID, filename, extension, drive ID,
@@ -50,6 +60,28 @@ pub struct FileCrypt {
 
     /// The hash of the encrypted file.
     pub hash: [u8; KEY_SIZE],
+
+    /// The source file's Unix permission bits (`st_mode & 0o777`), captured at encrypt
+    /// time so `decrypt_file` can restore them. `None` on platforms without Unix modes.
+    pub permissions: Option<u32>,
+
+    /// The MIME type sniffed from the source file's magic bytes, if recognized.
+    pub file_type: Option<String>,
+
+    /// Whether the contents were zstd-compressed before encryption. `false` for
+    /// file types [`common::FileType::is_compressible`] flagged as already
+    /// compressed, so `decrypt_contents` knows to skip decompression.
+    pub compressed: bool,
+
+    /// Semicolon-separated list of other paths that shared `full_path`'s inode
+    /// at encrypt time (empty if none). A directory encrypt only encrypts one
+    /// path per inode by default; this records the rest so decrypt can recreate
+    /// them as hardlinks instead of losing the link relationship entirely.
+    pub hardlinks: String,
+
+    /// Which [`crate::encryption::HashAlgorithm`] `hash` was computed with, so
+    /// decrypt re-hashes with the matching algorithm instead of assuming Blake2s.
+    pub hash_algorithm: HashAlgorithm,
 }
 
 impl FileCrypt {
@@ -88,6 +120,11 @@ impl FileCrypt {
             ext,
             uuid,
             hash,
+            permissions: None,
+            file_type: None,
+            compressed: true,
+            hardlinks: String::new(),
+            hash_algorithm: HashAlgorithm::default(),
         }
     }
 
@@ -99,15 +136,77 @@ impl FileCrypt {
     pub fn set_drive_id(&mut self, drive_id: String) {
         self.drive_id = drive_id;
     }
+
+    /// Sets the source file's Unix permission bits, captured at encrypt time.
+    pub fn set_permissions(&mut self, permissions: Option<u32>) {
+        self.permissions = permissions;
+    }
+
+    /// Records the sniffed file type and whether its contents should be
+    /// zstd-compressed, per [`common::FileType::is_compressible`].
+    pub fn set_file_type(&mut self, file_type: Option<common::FileType>) {
+        self.compressed = file_type.as_ref().is_none_or(common::FileType::is_compressible);
+        self.file_type = file_type.map(|t| t.mime);
+    }
+
+    /// Records which [`HashAlgorithm`] `hash` was computed with.
+    pub fn set_hash_algorithm(&mut self, algorithm: HashAlgorithm) {
+        self.hash_algorithm = algorithm;
+    }
+
+    /// Records the other paths that shared this file's inode at encrypt time,
+    /// so decrypt can recreate them as hardlinks alongside the primary output.
+    pub fn set_hardlinks(&mut self, hardlinks: &[PathBuf]) {
+        self.hardlinks = hardlinks
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(";");
+    }
+
+    /// The paths recorded by [`set_hardlinks`](Self::set_hardlinks), parsed back out.
+    pub fn hardlink_paths(&self) -> Vec<PathBuf> {
+        if self.hardlinks.is_empty() {
+            return Vec::new();
+        }
+        self.hardlinks.split(';').map(PathBuf::from).collect()
+    }
+}
+
+/// Reads the Unix permission bits (`st_mode & 0o777`) of the file at `path`.
+/// Returns `None` on platforms without Unix modes, or if the file can't be stat'd.
+#[cfg(unix)]
+fn get_unix_permissions<T: AsRef<Path>>(path: T) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).ok().map(|m| m.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn get_unix_permissions<T: AsRef<Path>>(_path: T) -> Option<u32> {
+    None
+}
+
+/// Restores the Unix permission bits captured in `fc.permissions` onto `file`, if any.
+/// No-op on platforms without Unix modes.
+#[cfg(unix)]
+fn apply_unix_permissions<T: AsRef<Path>>(file: T, fc: &FileCrypt) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Some(mode) = fc.permissions {
+        _ = std::fs::set_permissions(file, std::fs::Permissions::from_mode(mode));
+    }
 }
 
+#[cfg(not(unix))]
+fn apply_unix_permissions<T: AsRef<Path>>(_file: T, _fc: &FileCrypt) {}
+
 /// Decrypts a file using ChaCha20Poly1305 encryption and verifies its integrity.
 ///
 /// # Arguments
 ///
 /// * `filename` - filename of the .crypt file residing in the crypt folder.
 /// * `output` - An optional output path for the decrypted content.
-/// * `conf` - An optional configuration, if not provided, the default configuration is used.
+/// * `preserve_permissions` - If `true`, restores the source file's captured Unix
+///   permission bits onto the decrypted file. No-op on non-Unix platforms.
 ///
 /// # Returns
 ///
@@ -126,20 +225,28 @@ impl FileCrypt {
 /// # Panics
 ///
 /// This function may panic in case of critical errors, but most errors are returned in the `Result`.
-pub fn decrypt_file<T: AsRef<Path>>(path: T, output: String) -> Result<()> {
+pub fn decrypt_file<T: AsRef<Path>>(
+    path: T,
+    output: String,
+    preserve_permissions: bool,
+    allow_absolute: bool,
+) -> Result<()> {
     let path = path.as_ref();
 
-    // have user choose
-    let file_match = chooser(path.to_str().unwrap_or(""))?;
+    // if `path` already resolves to a real `.crypt` file (the common case when
+    // called with a full/relative path rather than a bare fuzzy name), use it
+    // directly instead of forcing the user through the interactive chooser.
+    let file_match = if path.is_file() {
+        path.to_path_buf()
+    } else {
+        chooser(path.to_str().unwrap_or(""))?
+    };
 
     let content = read(file_match)?;
 
     let (uuid, contents) = get_uuid(&content)?;
 
-    let fc = match query_crypt(uuid) {
-        Ok(f) => f,
-        Err(e) => panic!("{}", e.to_string()),
-    };
+    let fc = keystore::current().get_key(&uuid)?;
 
     let fc_hash: [u8; 32] = fc.hash.to_owned();
 
@@ -147,23 +254,42 @@ pub fn decrypt_file<T: AsRef<Path>>(path: T, output: String) -> Result<()> {
     // get location of crypt folder and append "decrypted" path
     let mut crypt_folder = get_crypt_folder();
     crypt_folder.push("decrypted");
-    let file = generate_output_file(&fc, output, &mut crypt_folder);
+    let file = generate_output_file(&fc, output, &mut crypt_folder, allow_absolute)?;
     dbg!(&file);
 
-    let mut decrypted_content = decrypt(fc.clone(), &contents.to_vec())?;
-    decrypted_content = decompress(&decrypted_content)?;
+    // fail fast on a read-only/full-disk output dir rather than after decrypting
+    // and decompressing a potentially large file.
+    common::check_dir_writable(Path::new(&file).parent().unwrap_or(&crypt_folder))?;
+
+    // authenticated decryption gates everything below -- a wrong key/uuid collision
+    // fails the Poly1305 tag check here and returns `Error::EncryptionError` before
+    // we ever touch the (potentially garbage) plaintext.
+    let decrypted_content = decrypt(fc.clone(), &contents.to_vec())?;
+    let decrypted_content = if fc.compressed {
+        decompress(&decrypted_content).map_err(|e| {
+            Error::FcError(error::FcError::CorruptAfterDecrypt(e.to_string()))
+        })?
+    } else {
+        decrypted_content
+    };
 
-    let hash = compute_hash(&decrypted_content);
+    let hash = compute_hash_with_algorithm(&decrypted_content, fc.hash_algorithm);
 
     if hash != fc_hash {
         return Err(Error::FcError(error::FcError::HashFail(fc_hash, hash)));
     }
     write_contents_to_file(&file, decrypted_content)?;
 
+    if preserve_permissions {
+        apply_unix_permissions(&file, &fc);
+    }
+
+    recreate_hardlinks(Path::new(&file), &fc);
+
     Ok(())
 }
 
-pub fn decrypt_contents(fc: FileCrypt, contents: Vec<u8>) -> Result<()> {
+pub fn decrypt_contents(fc: FileCrypt, contents: Vec<u8>, preserve_permissions: bool) -> Result<()> {
     let fc_hash: [u8; 32] = fc.hash.to_owned();
 
     // get location of crypt folder and append "decrypted" path
@@ -171,31 +297,84 @@ pub fn decrypt_contents(fc: FileCrypt, contents: Vec<u8>) -> Result<()> {
     crypt_folder.push("decrypted");
 
     // get output file
-    let file = generate_output_file(&fc, String::new(), &mut PathBuf::from(&crypt_folder));
+    let file = generate_output_file(&fc, String::new(), &mut PathBuf::from(&crypt_folder), false)?;
+
+    // fail fast on a read-only/full-disk output dir rather than after decrypting
+    // and decompressing a potentially large file.
+    common::check_dir_writable(Path::new(&file).parent().unwrap_or(&crypt_folder))?;
 
     // strip out uuid from contents
     let (_uuid, stripped_contents) = get_uuid(&contents)?;
 
-    // Decrypt contents
-    let mut decrypted_content =
-        decrypt(fc.clone(), &stripped_contents.to_vec()).expect("failed decryption");
-
-    // unzip contents
-    decrypted_content = decompress(&decrypted_content)?;
+    // authenticated decryption gates everything below -- a wrong key/uuid collision
+    // fails the Poly1305 tag check here and returns `Error::EncryptionError` before
+    // we ever touch the (potentially garbage) plaintext.
+    let decrypted_content = decrypt(fc.clone(), &stripped_contents.to_vec())?;
+
+    // unzip contents, unless they were never compressed in the first place
+    let decrypted_content = if fc.compressed {
+        decompress(&decrypted_content).map_err(|e| {
+            Error::FcError(error::FcError::CorruptAfterDecrypt(e.to_string()))
+        })?
+    } else {
+        decrypted_content
+    };
 
     // compute hash on contents
-    let hash = compute_hash(&decrypted_content);
+    let hash = compute_hash_with_algorithm(&decrypted_content, fc.hash_algorithm);
 
     // verify file integrity
     if hash != fc_hash {
         return Err(Error::FcError(error::FcError::HashFail(fc_hash, hash)));
     }
     // Write contents to file
-    write_contents_to_file(file, decrypted_content)?;
+    write_contents_to_file(&file, decrypted_content)?;
+
+    if preserve_permissions {
+        apply_unix_permissions(&file, &fc);
+    }
+
+    recreate_hardlinks(Path::new(&file), &fc);
 
     Ok(())
 }
 
+/// Recreates the hardlinks recorded in `fc.hardlinks` (see [`FileCrypt::set_hardlinks`])
+/// alongside the freshly decrypted `primary` file. Best-effort: a link that can't be
+/// created (e.g. its directory is missing, or it crosses a filesystem boundary) is
+/// logged and skipped rather than failing the decrypt.
+fn recreate_hardlinks(primary: &Path, fc: &FileCrypt) {
+    for link in fc.hardlink_paths() {
+        if let Some(parent) = link.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                error!("failed to create {} for hardlink: {}", parent.display(), e);
+                continue;
+            }
+        }
+        _ = std::fs::remove_file(&link);
+        if let Err(e) = std::fs::hard_link(primary, &link) {
+            error!("failed to recreate hardlink {}: {}", link.display(), e);
+        }
+    }
+}
+
+/// Reports what [`encrypt_file`] actually did with a source file's tracked crypt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptOutcome {
+    /// No existing crypt was found for this `full_path` -- a new uuid was generated.
+    Created,
+    /// A crypt already existed for this `full_path` and its content had changed,
+    /// so the existing row/uuid was overwritten with fresh key, nonce, and hash.
+    Updated,
+    /// A crypt already existed for this `full_path` and its content hash was
+    /// unchanged, so encryption was skipped (unless `force` was set).
+    Unchanged,
+    /// A different source file already occupied this `<filename>.crypt` slot
+    /// and `Config::encrypt_collision` was set to [`EncryptCollision::Skip`],
+    /// so encryption was skipped without touching the existing file or its row.
+    Skipped,
+}
+
 /// Encrypts the contents of a file and performs additional operations based on the provided configuration.
 ///
 /// # Arguments
@@ -203,6 +382,14 @@ pub fn decrypt_contents(fc: FileCrypt, contents: Vec<u8>) -> Result<()> {
 /// * `conf` - A reference to a Config struct containing encryption and configuration settings.
 /// * `path` - A string representing the path to the file to be encrypted.
 /// * `in_place` - A boolean indicating whether to perform in-place encryption.
+/// * `allow_large` - If `true`, bypasses the configured `max_file_size` guard.
+/// * `force` - If `true`, re-encrypts even when the tracked crypt's content hash
+///   is unchanged, instead of returning [`EncryptOutcome::Unchanged`].
+///
+/// # Errors
+///
+/// Returns `Error::FcError(FcError::FileTooLarge)` if the file exceeds the configured
+/// `max_file_size` and `allow_large` was not set.
 ///
 /// # Example
 ///
@@ -211,69 +398,230 @@ pub fn decrypt_contents(fc: FileCrypt, contents: Vec<u8>) -> Result<()> {
 /// # use crypt_lib::encryption::{encrypt_file};
 ///
 /// let path = "/path/to/your/file.txt";
-/// encrypt_file(&conf, path, false);
+/// encrypt_file(path, &None, false, false, false, false);
 /// ```
-pub fn encrypt_file(path: &str, output: &Option<String>) {
+pub fn encrypt_file(
+    path: &str,
+    output: &Option<String>,
+    allow_large: bool,
+    allow_absolute: bool,
+    force: bool,
+    verify: bool,
+) -> Result<EncryptOutcome> {
     let conf = get_config();
+
+    // guard against accidentally reading something huge (e.g. a disk image) into memory
+    let limit = conf.get_max_file_size();
+    if limit > 0 && !allow_large {
+        let size = std::fs::metadata(path)?.len();
+        if size > limit {
+            return Err(Error::FcError(error::FcError::FileTooLarge(size, limit)));
+        }
+    }
+
     // parse out file path
-    let (fp, _, filename, extension) = get_file_info(path);
+    let (fp, _, filename, mut extension) = get_file_info(path);
 
     // get contents of file
     let binding = get_vec_file_bytes(path);
     let mut contents = binding.as_slice();
 
-    let fc = FileCrypt::new(
-        filename,
-        extension,
-        "".to_string(),
-        fp,
-        compute_hash(contents),
-    );
+    let algorithm = select_hash_algorithm(contents.len(), conf.get_hash_parallel_threshold());
+    let hash = compute_hash_with_algorithm(contents, algorithm);
+
+    // re-encrypting a file we've already tracked should update its existing
+    // row rather than leave the old uuid orphaned in the keeper -- and if the
+    // content hasn't actually changed, there's nothing to redo.
+    let existing = keystore::current().find_by_full_path(&fp).ok();
+    if !force {
+        if let Some(existing) = &existing {
+            if existing.hash == hash {
+                return Ok(EncryptOutcome::Unchanged);
+            }
+        }
+    }
 
-    // zip contents
-    let binding = compress(contents, conf.zstd_level);
-    contents = binding.as_slice();
+    // sniff the real file type from magic bytes rather than trusting the extension --
+    // also lets us recover an extension when the original file didn't have one.
+    let detected = common::detect_file_type(contents);
+    if extension == "." {
+        if let Some(t) = &detected {
+            extension = format!(".{}", t.extension);
+        }
+    }
+
+    let mut fc = FileCrypt::new(filename, extension, "".to_string(), fp, hash);
+    fc.set_hash_algorithm(algorithm);
+    let outcome = match &existing {
+        Some(existing) => {
+            fc.uuid = existing.uuid.clone();
+            fc.drive_id = existing.drive_id.clone();
+            EncryptOutcome::Updated
+        }
+        None => EncryptOutcome::Created,
+    };
+    fc.set_permissions(get_unix_permissions(path));
+    fc.set_file_type(detected);
+
+    // zip contents -- skip compression for types that are already compressed
+    let compressed_binding;
+    if fc.compressed {
+        compressed_binding = compress(contents, conf.zstd_level);
+        contents = compressed_binding.as_slice();
+    }
 
     let mut encrypted_contents = encrypt(&fc, contents).unwrap();
 
     // prepend uuid to contents
     encrypted_contents = prepend_uuid(&fc.uuid, &mut encrypted_contents);
 
-    let mut path = get_crypt_folder();
+    let crypt_folder = get_crypt_folder();
+    let mut path = crypt_folder.clone();
     match output {
         Some(o) => {
-            let mut alt_path = path.clone();
-            alt_path.push(o);
-            if !PathBuf::from(&alt_path).exists() {
-                match std::fs::create_dir_all(&alt_path) {
-                    Ok(_) => (),
-                    Err(e) => panic!("{}", e.to_string()),
-                }
+            let alt_path = common::resolve_within_root(&crypt_folder, o, allow_absolute)?;
+            if !alt_path.exists() {
+                std::fs::create_dir_all(&alt_path)?;
             }
-            path.push(format!(r#"{}\{}{}"#, o, fc.filename, ".crypt"));
+            path = alt_path.join(format!("{}{}", fc.filename, ".crypt"));
         }
         None => path.push(format!("{}{}", fc.filename, ".crypt")),
     }
 
+    // a tracked file whose crypt an earlier collision moved to `name(n).crypt`
+    // is rewritten there, never over the crypt of whichever file holds `path`.
+    if let Some(existing) = &existing {
+        let parent_dir = path.parent().unwrap_or(&crypt_folder).to_path_buf();
+        let mut counter = 1;
+        while path.exists() && get_uuid_from_file(&path).ok().as_deref() != Some(existing.uuid.as_str()) {
+            path = parent_dir.join(format!("{}({}).crypt", fc.filename, counter));
+            counter += 1;
+        }
+    }
+
+    // `path` already exists but doesn't belong to `existing` (the row we found,
+    // if any, for this exact `full_path`) -- some other source file claimed this
+    // `<filename>.crypt` slot first. Apply the configured policy instead of
+    // unconditionally truncating it, so a same-named-different-content file
+    // doesn't silently destroy the only copy of the other file's contents.
+    if existing.is_none() && path.exists() {
+        match conf.get_encrypt_collision() {
+            EncryptCollision::Skip => return Ok(EncryptOutcome::Skipped),
+            EncryptCollision::Rename => {
+                let parent_dir = path.parent().unwrap_or(&crypt_folder).to_path_buf();
+                let mut counter = 1;
+                loop {
+                    let candidate = parent_dir.join(format!("{}({}).crypt", fc.filename, counter));
+                    if !candidate.exists() {
+                        path = candidate;
+                        break;
+                    }
+                    counter += 1;
+                }
+            }
+            EncryptCollision::Overwrite => {
+                // the crypt at `path` belongs to a different uuid -- drop its row
+                // first so overwriting the file doesn't leave that key orphaned,
+                // pointing at contents that no longer exist.
+                if let Ok(old_uuid) = get_uuid_from_file(&path) {
+                    _ = keystore::current().delete(&old_uuid);
+                }
+            }
+        }
+    }
+
     // write fc to crypt_keeper
-    insert_crypt(&fc).expect("failed to insert FileCrypt data into database!");
+    keystore::current()
+        .put_key(&fc)
+        .expect("failed to insert FileCrypt data into database!");
 
     dbg!(&path);
     write_contents_to_file(path.to_str().unwrap(), encrypted_contents.clone())
         .expect("failed to write contents to file!");
+
+    if verify || conf.get_verify_on_encrypt() {
+        verify_encrypted_write(&fc, &path)?;
+    }
+
+    #[cfg(feature = "thumbnails")]
+    if conf.get_generate_thumbnails() {
+        write_encrypted_thumbnail(&fc, &binding, &path)?;
+    }
+
+    emit(Event::FileEncrypted {
+        path: fc.full_path.clone(),
+        uuid: fc.uuid.clone(),
+    });
+
+    Ok(outcome)
+}
+
+/// If `contents` decodes as an image, generates a small preview thumbnail,
+/// encrypts it under `fc.key` (with a freshly generated nonce, distinct from
+/// `fc.nonce`), and writes it next to the `.crypt` at `crypt_path` with a
+/// `.thumb` extension. Does nothing if `contents` isn't a recognized image --
+/// this is a best-effort preview, not a hard requirement of encrypting.
+#[cfg(feature = "thumbnails")]
+fn write_encrypted_thumbnail(fc: &FileCrypt, contents: &[u8], crypt_path: &Path) -> Result<()> {
+    let Some(thumbnail) = common::make_thumbnail(contents) else {
+        return Ok(());
+    };
+
+    let encrypted_thumbnail = encrypt_bytes_with_key(&fc.key, &thumbnail)?;
+    let thumbnail_path = crypt_path.with_extension("thumb");
+    write_contents_to_file(thumbnail_path.to_str().unwrap(), encrypted_thumbnail)?;
+
+    Ok(())
+}
+
+/// Decrypts and (if applicable) decompresses the `.crypt` at `path`, then
+/// confirms the recomputed hash still matches `fc.hash` -- the same round-trip
+/// [`encrypt_file`] runs when `verify_on_encrypt` is set, exposed here so a
+/// scan over the whole keeper (see `crypt scan`) can reuse it against a crypt
+/// long after it was written, not just immediately after.
+pub fn verify_encrypted_write(fc: &FileCrypt, path: &Path) -> Result<()> {
+    let contents = get_vec_file_bytes(path.to_str().unwrap());
+    let (_uuid, stripped_contents) = get_uuid(&contents)?;
+
+    let decrypted_content = decrypt(fc.clone(), &stripped_contents)?;
+    let decrypted_content = if fc.compressed {
+        decompress(&decrypted_content)
+            .map_err(|e| Error::FcError(error::FcError::CorruptAfterDecrypt(e.to_string())))?
+    } else {
+        decrypted_content
+    };
+
+    let hash = compute_hash_with_algorithm(&decrypted_content, fc.hash_algorithm);
+    if hash != fc.hash {
+        return Err(Error::FcError(error::FcError::HashFail(fc.hash, hash)));
+    }
+
+    Ok(())
+}
+
+/// Hashes the plaintext at `path` with the same algorithm `fc` was originally
+/// hashed with, and reports whether it matches `fc.hash` -- lets a caller
+/// confirm a previously-decrypted file is still the original without
+/// re-decrypting the `.crypt` to compare against.
+pub fn compare_to_stored_hash<T: AsRef<Path>>(path: T, fc: &FileCrypt) -> Result<bool> {
+    let contents = get_vec_file_bytes(path.as_ref().to_str().unwrap());
+    let hash = compute_hash_with_algorithm(&contents, fc.hash_algorithm);
+    Ok(hash == fc.hash)
 }
 
 pub fn create_file_crypt<T: AsRef<Path>>(path: T, contents: &[u8]) -> FileCrypt {
     let path = path.as_ref();
     // parse out file path
     let (fp, _, filename, extension) = get_file_info(path);
-    FileCrypt::new(
+    let mut fc = FileCrypt::new(
         filename,
         extension,
         "".to_string(),
         fp,
         compute_hash(contents),
-    )
+    );
+    fc.set_permissions(get_unix_permissions(path));
+    fc
 }
 
 pub fn zip_contents(contents: &[u8]) -> Result<Vec<u8>> {
@@ -338,7 +686,7 @@ pub fn encrypt_contents(path: &str) -> Option<Vec<u8>> {
 
     // TODO: fix this later.
     // write fc to crypt_keeper
-    match insert_crypt(&fc) {
+    match keystore::current().put_key(&fc) {
         Ok(_) => (),
         Err(_) => todo!(),
     }
@@ -346,32 +694,140 @@ pub fn encrypt_contents(path: &str) -> Option<Vec<u8>> {
     Some(encrypted_contents)
 }
 
+/// Naming scheme used to pick a decrypted file's output path when the user
+/// doesn't pass an explicit `-o`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecryptNaming {
+    /// Write into a `decrypted` subfolder of the crypt folder, with `(n)`
+    /// collision counters. Today's default.
+    #[default]
+    Subfolder,
+    /// Write `name-decrypted.ext` in the same location `Subfolder` would use.
+    SuffixDecrypted,
+    /// Restore the file to its original `full_path`, overwriting it in place.
+    Inline,
+}
+
+impl std::fmt::Display for DecryptNaming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Subfolder => write!(f, "subfolder"),
+            Self::SuffixDecrypted => write!(f, "suffix"),
+            Self::Inline => write!(f, "inline"),
+        }
+    }
+}
+
+impl std::str::FromStr for DecryptNaming {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "subfolder" => Ok(Self::Subfolder),
+            "suffix" | "suffix-decrypted" | "suffixdecrypted" => Ok(Self::SuffixDecrypted),
+            "inline" => Ok(Self::Inline),
+            _ => Err(Error::FcError(error::FcError::InvalidDecryptNaming(
+                s.to_string(),
+            ))),
+        }
+    }
+}
+
+/// Policy for what `encrypt_file` does when the target `<filename>.crypt`
+/// already exists but doesn't belong to the file being encrypted (e.g. two
+/// different source files sharing a name, or an unrelated prior encrypt) --
+/// as opposed to a re-encrypt of the same `full_path`, which always updates
+/// the existing row in place regardless of this setting.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EncryptCollision {
+    /// Pick the next available `name(n).crypt`, mirroring `DecryptNaming::Subfolder`'s
+    /// counter. Never destroys an existing encrypted file. Today's default.
+    #[default]
+    Rename,
+    /// Leave the existing `.crypt` file (and its DB row) untouched and don't encrypt.
+    Skip,
+    /// Overwrite the existing `.crypt` file, deleting its DB row first so the
+    /// key it held isn't left orphaned pointing at contents that no longer exist.
+    Overwrite,
+}
+
+impl std::fmt::Display for EncryptCollision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rename => write!(f, "rename"),
+            Self::Skip => write!(f, "skip"),
+            Self::Overwrite => write!(f, "overwrite"),
+        }
+    }
+}
+
+impl std::str::FromStr for EncryptCollision {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "rename" => Ok(Self::Rename),
+            "skip" => Ok(Self::Skip),
+            "overwrite" => Ok(Self::Overwrite),
+            _ => Err(Error::FcError(error::FcError::InvalidEncryptCollision(
+                s.to_string(),
+            ))),
+        }
+    }
+}
+
 /// Generates the output file path for decrypted content based on the provided parameters.
 ///
 /// # Arguments
 ///
 /// * `fc` - A reference to a `FileCrypt` struct containing file information.
 /// * `output` - An optional string specifying an alternative output path or filename.
+/// * `allow_absolute` - If `false`, `output` must resolve to somewhere inside `parent_dir`;
+///   an absolute path or a `..` sequence that escapes it is rejected.
 ///
 /// # Returns
 ///
 /// Returns a string representing the final output file path.
 ///
+/// # Errors
+///
+/// Returns `Error::CommonError(CommonError::PathTraversal)` if `output` resolves outside
+/// of `parent_dir` and `allow_absolute` was not set.
+///
 /// # Panics
 ///
 /// The function may panic if there are issues with creating directories or manipulating file paths.
-fn generate_output_file(fc: &FileCrypt, mut output: String, parent_dir: &mut PathBuf) -> String {
-    // default output case
-    let mut file = format!("{}/{}{}", &parent_dir.display(), &fc.filename, &fc.ext);
+fn generate_output_file(
+    fc: &FileCrypt,
+    mut output: String,
+    parent_dir: &mut PathBuf,
+    allow_absolute: bool,
+) -> Result<String> {
+    let decrypt_naming = get_config().decrypt_naming;
+    let restoring_inline = output.is_empty() && decrypt_naming == DecryptNaming::Inline;
+    let suffix = match decrypt_naming {
+        DecryptNaming::SuffixDecrypted => "-decrypted",
+        DecryptNaming::Subfolder | DecryptNaming::Inline => "",
+    };
 
-    if !Path::new(&parent_dir).exists() {
-        _ = std::fs::create_dir(&parent_dir);
-    }
+    // default output case
+    let mut file = if restoring_inline {
+        if let Some(parent) = fc.full_path.parent() {
+            *parent_dir = parent.to_path_buf();
+            _ = std::fs::create_dir_all(parent);
+        }
+        fc.full_path.display().to_string()
+    } else {
+        if !Path::new(&parent_dir).exists() {
+            _ = std::fs::create_dir(&parent_dir);
+        }
+        format!("{}/{}{}{}", &parent_dir.display(), &fc.filename, suffix, &fc.ext)
+    };
 
     // if user passes in a alternative path and or filename for us to use, use it.
     if !output.is_empty() {
         let rel_path = PathBuf::from(&output);
-        parent_dir.push(rel_path.clone());
+        *parent_dir = common::resolve_within_root(parent_dir, &output, allow_absolute)?;
 
         match rel_path.extension().is_some() {
             // 'tis a file
@@ -405,15 +861,17 @@ fn generate_output_file(fc: &FileCrypt, mut output: String, parent_dir: &mut Pat
         };
     }
 
-    // if we already have an existing file, we will loop and count up until we find a verison that is not there
-    if Path::new(&file).exists() {
+    // if we already have an existing file, we will loop and count up until we find a verison that is not there.
+    // skip this for an inline restore -- overwriting the original at its own `full_path` is the point.
+    if !restoring_inline && Path::new(&file).exists() {
         let mut counter = 1;
         // dont know if this is the right path at the moment, but works for now.
         loop {
             file = format!(
-                "{}/{}({}){}",
+                "{}/{}{}({}){}",
                 &parent_dir.display(),
                 &fc.filename,
+                suffix,
                 counter,
                 &fc.ext
             );
@@ -424,7 +882,34 @@ fn generate_output_file(fc: &FileCrypt, mut output: String, parent_dir: &mut Pat
             }
         }
     }
-    file
+    Ok(file)
+}
+
+/// Scans `crypts` for any (key, nonce) pair reused across more than one row.
+///
+/// Keys and nonces are both generated fresh per file, so a repeated pair
+/// across two different `FileCrypt`s would indicate an RNG failure rather
+/// than ordinary bad luck -- reusing a (key, nonce) pair with ChaCha20Poly1305
+/// is catastrophic, so this is reported loudly by `keeper audit-nonces`
+/// rather than left to be discovered later.
+///
+/// Returns one group per reused pair, each group holding every `FileCrypt`
+/// that shares it. An empty result means no reuse was found.
+pub fn find_reused_nonces(crypts: &[FileCrypt]) -> Vec<Vec<FileCrypt>> {
+    let mut by_pair: std::collections::HashMap<([u8; KEY_SIZE], [u8; NONCE_SIZE]), Vec<FileCrypt>> =
+        std::collections::HashMap::new();
+
+    for crypt in crypts {
+        by_pair
+            .entry((crypt.key, crypt.nonce))
+            .or_default()
+            .push(crypt.clone());
+    }
+
+    by_pair
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect()
 }
 
 /// Generates a Universally Unique Identifier (UUID) incorporating a timestamp and random bytes.
@@ -488,13 +973,20 @@ pub fn generate_uuid() -> String {
 ///
 /// # Panics
 ///
-/// The function will panic if the length of `contents` is less than 36.
+/// Does not panic: input shorter than `UUID_PREFIX_LEN` returns `FcError::UuidError` instead.
 pub fn get_uuid(contents: &[u8]) -> Result<(String, Vec<u8>)> {
-    if contents.len() < 36 {
+    strip_prefix(contents)
+}
+
+/// Splits the `UUID_PREFIX_LEN`-byte uuid prefix off the front of `contents`,
+/// returning it alongside the remaining bytes. Shared by `get_uuid` so the
+/// boundary is only ever computed in one place.
+fn strip_prefix(contents: &[u8]) -> Result<(String, Vec<u8>)> {
+    if contents.len() < UUID_PREFIX_LEN {
         return Err(Error::FcError(error::FcError::UuidError));
     }
 
-    let (uuid, contents) = contents.split_at(36);
+    let (uuid, contents) = contents.split_at(UUID_PREFIX_LEN);
     Ok((
         String::from_utf8(uuid.to_vec()).unwrap_or(String::from_utf8_lossy(uuid).to_string()),
         contents.to_vec(),
@@ -557,18 +1049,25 @@ pub fn get_uuid_from_file<T: AsRef<Path>>(file: T) -> Result<String> {
     let file = File::open(path)?;
 
     // Create a buffered reader
-    let mut reader = BufReader::new(file);
-
-    // Create a buffer to store the content
-    let mut buffer = [0; 36];
+    let reader = BufReader::new(file);
 
-    // Read the first 36 characters into the buffer
-    let bytes_read = reader.read(&mut buffer)?;
-
-    // Convert the buffer to a string
-    let uuid = String::from_utf8_lossy(&buffer[..bytes_read]).to_string();
+    read_prefix(reader)
+}
 
-    Ok(uuid)
+/// Reads exactly `UUID_PREFIX_LEN` bytes from `reader` and interprets them as
+/// a uuid string.
+///
+/// Uses `read_exact` rather than a single `Read::read` call, since `read`
+/// is allowed to return fewer bytes than requested (e.g. for pipes or
+/// network-backed readers), which would silently produce a truncated uuid.
+fn read_prefix<R: Read>(mut reader: R) -> Result<String> {
+    let mut buffer = [0; UUID_PREFIX_LEN];
+    reader.read_exact(&mut buffer).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => Error::FcError(error::FcError::UuidError),
+        _ => Error::IoError(e),
+    })?;
+
+    Ok(String::from_utf8_lossy(&buffer).to_string())
 }
 
 /// Prepends a UUID represented as a string to a vector of encrypted contents. Modifies vector in place.
@@ -592,11 +1091,18 @@ pub fn get_uuid_from_file<T: AsRef<Path>>(file: T) -> Result<String> {
 ///
 /// let result = prepend_uuid(uuid, &mut encrypted_data);
 ///
-/// assert_eq!(result.len(), encrypted_data.len() + 36); // UUID is 36 bytes
+/// assert_eq!(result.len(), encrypted_data.len() + 36); // UUID is UUID_PREFIX_LEN bytes
 /// assert_eq!(&result[0..36], uuid.as_bytes());        // Check if UUID is prepended correctly
 /// assert_eq!(&result[36..], encrypted_data.as_slice()); // Check if original contents are preserved
 /// ```
+///
+/// # Panics
+///
+/// Panics (via `debug_assert!`) if `uuid` is not `UUID_PREFIX_LEN` bytes long,
+/// since anything else would desync `get_uuid`/`get_uuid_from_file`'s read of
+/// the prefix on the other end.
 pub fn prepend_uuid(uuid: &str, encrypted_contents: &mut [u8]) -> Vec<u8> {
+    debug_assert_eq!(uuid.len(), UUID_PREFIX_LEN);
     let mut uuid_bytes = uuid.as_bytes().to_vec();
     let mut encc = encrypted_contents.to_owned();
     uuid_bytes.append(&mut encc);
@@ -623,7 +1129,15 @@ pub fn get_file_info<T: AsRef<Path>>(path: T) -> (PathBuf, PathBuf, String, Stri
     // get filename, extension, and full path info
     let fp = get_full_file_path(path);
     let parent_dir = fp.parent().unwrap().to_owned();
-    let name = fp.file_name().unwrap().to_string_lossy().to_string(); // Convert to owned String
+    let os_name = fp.file_name().unwrap();
+    let name = os_name.to_string_lossy().to_string(); // Convert to owned String
+
+    // filenames are arbitrary bytes on Unix -- warn when the lossy conversion above
+    // actually dropped/replaced bytes, since decrypt won't be able to restore them.
+    if os_name.to_str().is_none() {
+        error!("{}", error::CommonError::NonUtf8Filename(name.clone()));
+    }
+
     let index = name.find('.').unwrap();
     let (filename, extension) = name.split_at(index);
 
@@ -645,14 +1159,14 @@ mod test {
     #[test]
     #[ignore = "works locally, fails in CI"]
     fn test_encrypt_decrypt_file() {
-        encrypt_file("crypt-core/benches/files/dracula.txt", &None);
+        _ = encrypt_file("crypt-core/benches/files/dracula.txt", &None, false, false, false, false);
         let mut crypt = get_crypt_folder();
         crypt.push("dracula.crypt");
         assert!(crypt.exists());
 
         thread::sleep(Duration::from_secs(1));
 
-        _ = decrypt_file(crypt.to_str().unwrap(), String::from(""));
+        _ = decrypt_file(crypt.to_str().unwrap(), String::from(""), true, false);
 
         let mut dracula_decypted = get_crypt_folder();
         dracula_decypted.push("decrypted");
@@ -663,6 +1177,262 @@ mod test {
         _ = std::fs::remove_file(dracula_decypted);
     }
 
+    /// A reader that only ever hands back a handful of bytes per call, to
+    /// exercise `read_prefix`'s handling of readers that don't fill the buffer
+    /// in one shot (like pipes or network-backed readers).
+    struct TrickleReader<'a> {
+        remaining: &'a [u8],
+    }
+
+    impl<'a> Read for TrickleReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = std::cmp::min(3, std::cmp::min(buf.len(), self.remaining.len()));
+            buf[..n].copy_from_slice(&self.remaining[..n]);
+            self.remaining = &self.remaining[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_read_uuid_assembles_full_uuid_from_short_reads() {
+        let uuid = uuid::Uuid::new_v4().to_string();
+        assert_eq!(uuid.len(), UUID_PREFIX_LEN);
+
+        let reader = TrickleReader {
+            remaining: uuid.as_bytes(),
+        };
+        assert_eq!(read_prefix(reader).unwrap(), uuid);
+    }
+
+    #[test]
+    fn test_read_uuid_errors_on_short_input() {
+        let reader = TrickleReader {
+            remaining: b"too short",
+        };
+        assert!(read_prefix(reader).is_err());
+    }
+
+    #[test]
+    fn test_get_uuid_and_read_prefix_agree_on_boundary() {
+        let uuid = uuid::Uuid::new_v4().to_string();
+        let mut contents = prepend_uuid(&uuid, &mut b"rest of the file".to_vec());
+
+        let (from_slice, rest) = get_uuid(&contents).unwrap();
+        let from_reader = read_prefix(contents.as_slice()).unwrap();
+
+        assert_eq!(from_slice, uuid);
+        assert_eq!(from_reader, uuid);
+        assert_eq!(rest, b"rest of the file");
+
+        contents.truncate(UUID_PREFIX_LEN - 1);
+        assert!(get_uuid(&contents).is_err());
+        assert!(read_prefix(contents.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_file_too_large() {
+        let mut tmp = std::env::temp_dir();
+        tmp.push("crypt_max_file_size_test.txt");
+        std::fs::write(&tmp, vec![0u8; 32]).unwrap();
+
+        {
+            let mut conf = crate::config::get_config_write();
+            conf.set_max_file_size(16);
+        }
+
+        let res = encrypt_file(tmp.to_str().unwrap(), &None, false, false, false, false);
+
+        {
+            let mut conf = crate::config::get_config_write();
+            conf.set_max_file_size(0);
+        }
+
+        _ = std::fs::remove_file(&tmp);
+
+        assert!(matches!(
+            res,
+            Err(Error::FcError(error::FcError::FileTooLarge(32, 16)))
+        ));
+    }
+
+    #[test]
+    fn test_generate_output_file_subfolder() {
+        let mut tmp = std::env::temp_dir();
+        tmp.push("crypt_decrypt_naming_subfolder_test");
+        _ = std::fs::remove_dir_all(&tmp);
+
+        {
+            let mut conf = crate::config::get_config_write();
+            conf.set_decrypt_naming(DecryptNaming::Subfolder);
+        }
+
+        let fc = FileCrypt {
+            filename: String::from("report"),
+            ext: String::from(".txt"),
+            full_path: PathBuf::from("/original/location/report.txt"),
+            ..Default::default()
+        };
+
+        let mut parent_dir = tmp.clone();
+        let path = generate_output_file(&fc, String::new(), &mut parent_dir, false).unwrap();
+
+        {
+            let mut conf = crate::config::get_config_write();
+            conf.set_decrypt_naming(DecryptNaming::default());
+        }
+        _ = std::fs::remove_dir_all(&tmp);
+
+        assert_eq!(path, format!("{}/report.txt", tmp.display()));
+    }
+
+    #[test]
+    fn test_generate_output_file_suffix_decrypted() {
+        let mut tmp = std::env::temp_dir();
+        tmp.push("crypt_decrypt_naming_suffix_test");
+        _ = std::fs::remove_dir_all(&tmp);
+
+        {
+            let mut conf = crate::config::get_config_write();
+            conf.set_decrypt_naming(DecryptNaming::SuffixDecrypted);
+        }
+
+        let fc = FileCrypt {
+            filename: String::from("report"),
+            ext: String::from(".txt"),
+            full_path: PathBuf::from("/original/location/report.txt"),
+            ..Default::default()
+        };
+
+        let mut parent_dir = tmp.clone();
+        let path = generate_output_file(&fc, String::new(), &mut parent_dir, false).unwrap();
+
+        {
+            let mut conf = crate::config::get_config_write();
+            conf.set_decrypt_naming(DecryptNaming::default());
+        }
+        _ = std::fs::remove_dir_all(&tmp);
+
+        assert_eq!(path, format!("{}/report-decrypted.txt", tmp.display()));
+    }
+
+    #[test]
+    fn test_generate_output_file_inline_restores_full_path() {
+        let mut tmp = std::env::temp_dir();
+        tmp.push("crypt_decrypt_naming_inline_test");
+        _ = std::fs::remove_dir_all(&tmp);
+        let original = tmp.join("nested").join("report.txt");
+
+        {
+            let mut conf = crate::config::get_config_write();
+            conf.set_decrypt_naming(DecryptNaming::Inline);
+        }
+
+        let fc = FileCrypt {
+            filename: String::from("report"),
+            ext: String::from(".txt"),
+            full_path: original.clone(),
+            ..Default::default()
+        };
+
+        let mut parent_dir = std::env::temp_dir();
+        parent_dir.push("crypt_decrypt_naming_inline_parent_test");
+        let path = generate_output_file(&fc, String::new(), &mut parent_dir, false).unwrap();
+
+        {
+            let mut conf = crate::config::get_config_write();
+            conf.set_decrypt_naming(DecryptNaming::default());
+        }
+        _ = std::fs::remove_dir_all(&tmp);
+
+        assert_eq!(path, original.display().to_string());
+        // `parent_dir` is repointed at the original file's own folder
+        assert_eq!(parent_dir, tmp.join("nested"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_decrypt_preserves_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut tmp = std::env::temp_dir();
+        tmp.push("crypt_permissions_test.txt");
+        std::fs::write(&tmp, b"shh").unwrap();
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600)).unwrap();
+        let full_path = get_full_file_path(&tmp);
+        _ = keystore::current()
+            .find_by_full_path(&full_path)
+            .map(|fc| keystore::current().delete(&fc.uuid));
+
+        let outcome = encrypt_file(tmp.to_str().unwrap(), &None, false, false, false, false).unwrap();
+        assert_eq!(outcome, EncryptOutcome::Created);
+
+        let mut crypt = get_crypt_folder();
+        crypt.push("crypt_permissions_test.crypt");
+        assert!(crypt.exists());
+
+        decrypt_file(crypt.to_str().unwrap(), String::from(""), true, false).unwrap();
+
+        // Default `DecryptNaming::Subfolder` writes into a `decrypted`
+        // subfolder of the crypt folder using the original filename.
+        let mut decrypted = get_crypt_folder();
+        decrypted.push("decrypted");
+        decrypted.push("crypt_permissions_test.txt");
+
+        let mode = std::fs::metadata(&decrypted).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+
+        let uuid = keystore::current().find_by_full_path(&full_path).unwrap().uuid;
+        _ = keystore::current().delete(&uuid);
+        _ = std::fs::remove_file(&tmp);
+        _ = std::fs::remove_file(&crypt);
+        _ = std::fs::remove_file(&decrypted);
+    }
+
+    #[test]
+    fn test_decrypt_contents_wrong_key_fails() {
+        let fc = FileCrypt::new(
+            "test".to_string(),
+            ".txt".to_string(),
+            "".to_string(),
+            PathBuf::from(""),
+            compute_hash(b"hello there"),
+        );
+
+        let compressed = compress(b"hello there", 3);
+        let mut encrypted = encrypt(&fc, &compressed).unwrap();
+        let contents = prepend_uuid(&fc.uuid, &mut encrypted);
+
+        // same nonce, different key -- simulates a uuid collision pointing at the wrong FileCrypt
+        let mut wrong_fc = fc.clone();
+        let (wrong_key, _) = generate_seeds();
+        wrong_fc.key = wrong_key;
+
+        let res = decrypt_contents(wrong_fc, contents, false);
+        assert!(matches!(res, Err(Error::EncryptionError(_))));
+    }
+
+    #[test]
+    fn test_decrypt_contents_corrupt_after_decrypt() {
+        let fc = FileCrypt::new(
+            "test".to_string(),
+            ".txt".to_string(),
+            "".to_string(),
+            PathBuf::from(""),
+            compute_hash(b"hello there"),
+        );
+
+        // encrypt un-compressed plaintext directly -- decryption authenticates fine,
+        // but the result isn't valid zstd, so decompression fails.
+        let mut encrypted = encrypt(&fc, b"not zstd data").unwrap();
+        let contents = prepend_uuid(&fc.uuid, &mut encrypted);
+
+        let res = decrypt_contents(fc, contents, false);
+        assert!(matches!(
+            res,
+            Err(Error::FcError(error::FcError::CorruptAfterDecrypt(_)))
+        ));
+    }
+
     #[test]
     fn test_get_uuid() {
         let contents: Vec<u8> = vec![
@@ -677,4 +1447,383 @@ mod test {
         let (uuid, _) = get_uuid(&contents).unwrap();
         assert_eq!(uuid, uuid_test);
     }
+
+    #[test]
+    fn test_compare_to_stored_hash_matches_unchanged_file() {
+        let mut path = std::env::temp_dir();
+        path.push("crypt_compare_hash_match_test.txt");
+        std::fs::write(&path, b"the original contents").unwrap();
+
+        let fc = create_file_crypt(&path, b"the original contents");
+        assert!(compare_to_stored_hash(&path, &fc).unwrap());
+
+        _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_compare_to_stored_hash_flags_a_changed_file() {
+        let mut path = std::env::temp_dir();
+        path.push("crypt_compare_hash_mismatch_test.txt");
+        std::fs::write(&path, b"the original contents").unwrap();
+
+        let fc = create_file_crypt(&path, b"the original contents");
+        std::fs::write(&path, b"tampered contents").unwrap();
+        assert!(!compare_to_stored_hash(&path, &fc).unwrap());
+
+        _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_file_info_non_utf8_filename_does_not_panic() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let name = OsString::from_vec(vec![b'f', b'o', 0x80, b'o', b'.', b't', b'x', b't']);
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, b"contents").unwrap();
+
+        let (_, _, filename, extension) = get_file_info(&path);
+
+        assert!(filename.contains('\u{FFFD}'));
+        assert_eq!(extension, ".txt");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn crypt_with_pair(uuid: &str, key: [u8; KEY_SIZE], nonce: [u8; NONCE_SIZE]) -> FileCrypt {
+        FileCrypt {
+            uuid: uuid.to_string(),
+            key,
+            nonce,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_find_reused_nonces_flags_duplicated_pair() {
+        let key_a = [1u8; KEY_SIZE];
+        let key_b = [2u8; KEY_SIZE];
+        let nonce_a = [3u8; NONCE_SIZE];
+        let nonce_b = [4u8; NONCE_SIZE];
+
+        let crypts = vec![
+            crypt_with_pair("one", key_a, nonce_a),
+            crypt_with_pair("two", key_b, nonce_b),
+            // "three" reuses the exact (key, nonce) pair from "one".
+            crypt_with_pair("three", key_a, nonce_a),
+        ];
+
+        let reused = find_reused_nonces(&crypts);
+        assert_eq!(reused.len(), 1);
+        let mut uuids: Vec<&str> = reused[0].iter().map(|fc| fc.uuid.as_str()).collect();
+        uuids.sort();
+        assert_eq!(uuids, vec!["one", "three"]);
+    }
+
+    #[test]
+    fn test_find_reused_nonces_empty_when_all_unique() {
+        let crypts = vec![
+            crypt_with_pair("one", [1u8; KEY_SIZE], [3u8; NONCE_SIZE]),
+            crypt_with_pair("two", [2u8; KEY_SIZE], [4u8; NONCE_SIZE]),
+        ];
+
+        assert!(find_reused_nonces(&crypts).is_empty());
+    }
+
+    #[test]
+    fn test_encrypt_file_new_file_creates_row() {
+        let mut tmp = std::env::temp_dir();
+        tmp.push("crypt_reencrypt_new_test.txt");
+        std::fs::write(&tmp, b"hello").unwrap();
+        let full_path = get_file_info(tmp.to_str().unwrap()).0;
+        _ = keystore::current()
+            .find_by_full_path(&full_path)
+            .map(|fc| keystore::current().delete(&fc.uuid));
+
+        let outcome = encrypt_file(tmp.to_str().unwrap(), &None, false, false, false, false).unwrap();
+        assert_eq!(outcome, EncryptOutcome::Created);
+
+        let uuid = keystore::current().find_by_full_path(&full_path).unwrap().uuid;
+        let mut crypt = get_crypt_folder();
+        crypt.push("crypt_reencrypt_new_test.crypt");
+        _ = keystore::current().delete(&uuid);
+        _ = std::fs::remove_file(crypt);
+        _ = std::fs::remove_file(&tmp);
+    }
+
+    #[test]
+    fn test_encrypt_file_with_verify_succeeds_on_a_healthy_write() {
+        let mut tmp = std::env::temp_dir();
+        tmp.push("crypt_verify_success_test.txt");
+        std::fs::write(&tmp, b"verify me").unwrap();
+        let full_path = get_file_info(tmp.to_str().unwrap()).0;
+        _ = keystore::current()
+            .find_by_full_path(&full_path)
+            .map(|fc| keystore::current().delete(&fc.uuid));
+
+        let outcome = encrypt_file(tmp.to_str().unwrap(), &None, false, false, false, true).unwrap();
+        assert_eq!(outcome, EncryptOutcome::Created);
+
+        let uuid = keystore::current().find_by_full_path(&full_path).unwrap().uuid;
+        let mut crypt = get_crypt_folder();
+        crypt.push("crypt_verify_success_test.crypt");
+        assert!(crypt.exists());
+
+        _ = keystore::current().delete(&uuid);
+        _ = std::fs::remove_file(crypt);
+        _ = std::fs::remove_file(&tmp);
+    }
+
+    #[test]
+    fn test_encrypt_file_with_verify_fails_on_a_corrupted_write() {
+        let mut tmp = std::env::temp_dir();
+        tmp.push("crypt_verify_failure_test.txt");
+        std::fs::write(&tmp, b"verify me too").unwrap();
+        let full_path = get_full_file_path(&tmp);
+        _ = keystore::current()
+            .find_by_full_path(&full_path)
+            .map(|fc| keystore::current().delete(&fc.uuid));
+
+        let outcome = encrypt_file(tmp.to_str().unwrap(), &None, false, false, false, false).unwrap();
+        assert_eq!(outcome, EncryptOutcome::Created);
+
+        let mut crypt = get_crypt_folder();
+        crypt.push("crypt_verify_failure_test.crypt");
+
+        // flip a byte in the ciphertext to simulate a corrupted write, then
+        // verify against the fc row that was persisted for the healthy write.
+        let mut contents = std::fs::read(&crypt).unwrap();
+        let last = contents.len() - 1;
+        contents[last] ^= 0xFF;
+        std::fs::write(&crypt, &contents).unwrap();
+
+        let fc = keystore::current().find_by_full_path(&full_path).unwrap();
+        assert!(verify_encrypted_write(&fc, &crypt).is_err());
+
+        _ = keystore::current().delete(&fc.uuid);
+        _ = std::fs::remove_file(crypt);
+        _ = std::fs::remove_file(&tmp);
+    }
+
+    #[test]
+    fn test_encrypt_file_unchanged_content_is_skipped() {
+        let mut tmp = std::env::temp_dir();
+        tmp.push("crypt_reencrypt_unchanged_test.txt");
+        std::fs::write(&tmp, b"same content").unwrap();
+        let full_path = get_file_info(tmp.to_str().unwrap()).0;
+        _ = keystore::current()
+            .find_by_full_path(&full_path)
+            .map(|fc| keystore::current().delete(&fc.uuid));
+
+        let first = encrypt_file(tmp.to_str().unwrap(), &None, false, false, false, false).unwrap();
+        assert_eq!(first, EncryptOutcome::Created);
+
+        let second = encrypt_file(tmp.to_str().unwrap(), &None, false, false, false, false).unwrap();
+        assert_eq!(second, EncryptOutcome::Unchanged);
+
+        let uuid = keystore::current().find_by_full_path(&full_path).unwrap().uuid;
+        let mut crypt = get_crypt_folder();
+        crypt.push("crypt_reencrypt_unchanged_test.crypt");
+        _ = keystore::current().delete(&uuid);
+        _ = std::fs::remove_file(crypt);
+        _ = std::fs::remove_file(&tmp);
+    }
+
+    #[test]
+    fn test_encrypt_file_changed_content_reuses_uuid() {
+        let mut tmp = std::env::temp_dir();
+        tmp.push("crypt_reencrypt_changed_test.txt");
+        std::fs::write(&tmp, b"original content").unwrap();
+
+        let full_path = get_file_info(tmp.to_str().unwrap()).0;
+        _ = keystore::current()
+            .find_by_full_path(&full_path)
+            .map(|fc| keystore::current().delete(&fc.uuid));
+
+        let first = encrypt_file(tmp.to_str().unwrap(), &None, false, false, false, false).unwrap();
+        assert_eq!(first, EncryptOutcome::Created);
+        let first_uuid = keystore::current()
+            .find_by_full_path(&full_path)
+            .unwrap()
+            .uuid;
+
+        std::fs::write(&tmp, b"changed content").unwrap();
+        let second = encrypt_file(tmp.to_str().unwrap(), &None, false, false, false, false).unwrap();
+        assert_eq!(second, EncryptOutcome::Updated);
+        let second_uuid = keystore::current().find_by_full_path(&full_path).unwrap().uuid;
+
+        assert_eq!(first_uuid, second_uuid);
+
+        let mut crypt = get_crypt_folder();
+        crypt.push("crypt_reencrypt_changed_test.crypt");
+        _ = keystore::current().delete(&second_uuid);
+        _ = std::fs::remove_file(crypt);
+        _ = std::fs::remove_file(&tmp);
+    }
+
+    /// Two different source files that happen to share a filename, used to
+    /// exercise `Config::encrypt_collision` -- both write to the same
+    /// `<filename>.crypt` slot in the crypt folder unless a policy intervenes.
+    fn write_collision_pair(name: &str) -> (PathBuf, PathBuf) {
+        let dir_a = std::env::temp_dir().join(format!("{}_a", name));
+        let dir_b = std::env::temp_dir().join(format!("{}_b", name));
+        std::fs::create_dir_all(&dir_a).unwrap();
+        std::fs::create_dir_all(&dir_b).unwrap();
+        let file_a = dir_a.join(format!("{}.txt", name));
+        let file_b = dir_b.join(format!("{}.txt", name));
+        std::fs::write(&file_a, b"contents from a").unwrap();
+        std::fs::write(&file_b, b"contents from b").unwrap();
+
+        // clear any stale keeper row/crypt file left behind by a previous run that
+        // panicked before reaching its own cleanup, so this run isn't sunk by
+        // leftover state.
+        for file in [&file_a, &file_b] {
+            let full_path = get_file_info(file.to_str().unwrap()).0;
+            _ = keystore::current()
+                .find_by_full_path(&full_path)
+                .map(|fc| keystore::current().delete(&fc.uuid));
+        }
+        let crypt_folder = get_crypt_folder();
+        _ = std::fs::remove_file(crypt_folder.join(format!("{}.crypt", name)));
+        _ = std::fs::remove_file(crypt_folder.join(format!("{}(1).crypt", name)));
+
+        (file_a, file_b)
+    }
+
+    #[test]
+    fn test_encrypt_file_collision_default_renames_instead_of_overwriting() {
+        let _guard = crate::test_support::db_test_guard();
+
+        let (file_a, file_b) = write_collision_pair("crypt_collision_rename_test");
+
+        assert_eq!(
+            encrypt_file(file_a.to_str().unwrap(), &None, false, false, false, false).unwrap(),
+            EncryptOutcome::Created
+        );
+        let uuid_a = keystore::current()
+            .find_by_full_path(&get_file_info(file_a.to_str().unwrap()).0)
+            .unwrap()
+            .uuid;
+
+        assert_eq!(
+            encrypt_file(file_b.to_str().unwrap(), &None, false, false, false, false).unwrap(),
+            EncryptOutcome::Created
+        );
+        let uuid_b = keystore::current()
+            .find_by_full_path(&get_file_info(file_b.to_str().unwrap()).0)
+            .unwrap()
+            .uuid;
+
+        assert_ne!(uuid_a, uuid_b);
+
+        let crypt_folder = get_crypt_folder();
+        let original = crypt_folder.join("crypt_collision_rename_test.crypt");
+        let renamed = crypt_folder.join("crypt_collision_rename_test(1).crypt");
+        assert!(original.exists(), "first encrypt's crypt should be untouched");
+        assert!(renamed.exists(), "second encrypt should have picked a fresh name");
+        assert!(keystore::current().get_key(&uuid_a).is_ok());
+        assert!(keystore::current().get_key(&uuid_b).is_ok());
+
+        _ = keystore::current().delete(&uuid_a);
+        _ = keystore::current().delete(&uuid_b);
+        _ = std::fs::remove_file(original);
+        _ = std::fs::remove_file(renamed);
+        _ = std::fs::remove_dir_all(file_a.parent().unwrap());
+        _ = std::fs::remove_dir_all(file_b.parent().unwrap());
+    }
+
+    #[test]
+    fn test_encrypt_file_collision_skip_leaves_existing_crypt_untouched() {
+        let _guard = crate::test_support::db_test_guard();
+
+        let (file_a, file_b) = write_collision_pair("crypt_collision_skip_test");
+
+        assert_eq!(
+            encrypt_file(file_a.to_str().unwrap(), &None, false, false, false, false).unwrap(),
+            EncryptOutcome::Created
+        );
+        let uuid_a = keystore::current()
+            .find_by_full_path(&get_file_info(file_a.to_str().unwrap()).0)
+            .unwrap()
+            .uuid;
+
+        {
+            let mut conf = crate::config::get_config_write();
+            conf.set_encrypt_collision(EncryptCollision::Skip);
+        }
+
+        let outcome = encrypt_file(file_b.to_str().unwrap(), &None, false, false, false, false).unwrap();
+
+        {
+            let mut conf = crate::config::get_config_write();
+            conf.set_encrypt_collision(EncryptCollision::Rename);
+        }
+
+        assert_eq!(outcome, EncryptOutcome::Skipped);
+        assert!(
+            keystore::current()
+                .find_by_full_path(&get_file_info(file_b.to_str().unwrap()).0)
+                .is_err(),
+            "b should never have gotten a row"
+        );
+
+        let crypt_folder = get_crypt_folder();
+        let crypt = crypt_folder.join("crypt_collision_skip_test.crypt");
+        assert!(crypt.exists());
+        assert!(keystore::current().get_key(&uuid_a).is_ok());
+
+        _ = keystore::current().delete(&uuid_a);
+        _ = std::fs::remove_file(crypt);
+        _ = std::fs::remove_dir_all(file_a.parent().unwrap());
+        _ = std::fs::remove_dir_all(file_b.parent().unwrap());
+    }
+
+    #[test]
+    fn test_encrypt_file_collision_overwrite_drops_old_row() {
+        let _guard = crate::test_support::db_test_guard();
+
+        let (file_a, file_b) = write_collision_pair("crypt_collision_overwrite_test");
+
+        assert_eq!(
+            encrypt_file(file_a.to_str().unwrap(), &None, false, false, false, false).unwrap(),
+            EncryptOutcome::Created
+        );
+        let uuid_a = keystore::current()
+            .find_by_full_path(&get_file_info(file_a.to_str().unwrap()).0)
+            .unwrap()
+            .uuid;
+
+        {
+            let mut conf = crate::config::get_config_write();
+            conf.set_encrypt_collision(EncryptCollision::Overwrite);
+        }
+
+        let outcome = encrypt_file(file_b.to_str().unwrap(), &None, false, false, false, false).unwrap();
+
+        {
+            let mut conf = crate::config::get_config_write();
+            conf.set_encrypt_collision(EncryptCollision::Rename);
+        }
+
+        assert_eq!(outcome, EncryptOutcome::Created);
+        let uuid_b = keystore::current()
+            .find_by_full_path(&get_file_info(file_b.to_str().unwrap()).0)
+            .unwrap()
+            .uuid;
+
+        assert!(
+            keystore::current().get_key(&uuid_a).is_err(),
+            "a's row should have been dropped rather than left orphaned"
+        );
+
+        let crypt_folder = get_crypt_folder();
+        let crypt = crypt_folder.join("crypt_collision_overwrite_test.crypt");
+        assert!(crypt.exists());
+
+        _ = keystore::current().delete(&uuid_b);
+        _ = std::fs::remove_file(crypt);
+        _ = std::fs::remove_dir_all(file_a.parent().unwrap());
+        _ = std::fs::remove_dir_all(file_b.parent().unwrap());
+    }
 }
@@ -1,31 +1,48 @@
 use super::tree::*;
+use crate::{common::TreeCharset, config};
 
 pub fn print_tree(root: &str, dir: &Directory) {
-    const OTHER_CHILD: &str = "│   "; // prefix: pipe
-    const OTHER_ENTRY: &str = "├── "; // connector: tee
-    const FINAL_CHILD: &str = "    "; // prefix: no siblings
-    const FINAL_ENTRY: &str = "└── "; // connector: elbow
+    let (other_child, other_entry, final_child, final_entry) =
+        match config::get_config().get_tree_charset() {
+            TreeCharset::Unicode => ("│   ", "├── ", "    ", "└── "),
+            TreeCharset::Ascii => ("|   ", "|-- ", "    ", "`-- "),
+            TreeCharset::Plain => ("    ", "    ", "    ", "    "),
+        };
 
     println!("{}", root);
-    let (d, f) = visit(dir, "");
+    let (d, f) = visit(dir, "", other_child, other_entry, final_child, final_entry);
     println!("\n{} directories, {} files", d, f);
 
-    fn visit(node: &Directory, prefix: &str) -> (usize, usize) {
+    fn visit(
+        node: &Directory,
+        prefix: &str,
+        other_child: &str,
+        other_entry: &str,
+        final_child: &str,
+        final_entry: &str,
+    ) -> (usize, usize) {
         let mut dirs: usize = 1; // counting this directory
         let mut files: usize = 0;
         let mut count = node.entries.len();
         for entry in &node.entries {
             count -= 1;
-            let connector = if count == 0 { FINAL_ENTRY } else { OTHER_ENTRY };
+            let connector = if count == 0 { final_entry } else { other_entry };
             match entry {
                 FileTree::DirNode(sub_dir) => {
                     println!("{}{}{}", prefix, connector, sub_dir.name);
                     let new_prefix = format!(
                         "{}{}",
                         prefix,
-                        if count == 0 { FINAL_CHILD } else { OTHER_CHILD }
+                        if count == 0 { final_child } else { other_child }
+                    );
+                    let (d, f) = visit(
+                        sub_dir,
+                        &new_prefix,
+                        other_child,
+                        other_entry,
+                        final_child,
+                        final_entry,
                     );
-                    let (d, f) = visit(sub_dir, &new_prefix);
                     dirs += d;
                     files += f;
                 }
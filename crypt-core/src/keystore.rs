@@ -0,0 +1,233 @@
+//! Pluggable storage for per-file keys (and the rest of a [`FileCrypt`]'s
+//! metadata), so keys can live somewhere other than the SQLite keeper --
+//! e.g. the OS keychain. Backend is selected via [`crate::config::Config::key_store`].
+
+use crate::{
+    db,
+    error::{self, Error},
+    filecrypt::FileCrypt,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Where [`FileCrypt`] entries (key material plus metadata) are persisted.
+/// Implemented by the SQLite keeper ([`SqliteKeyStore`]) and by the OS
+/// keychain ([`KeyringKeyStore`]).
+pub trait KeyStore: Send + Sync {
+    /// Persists `crypt`, creating or overwriting the entry for `crypt.uuid`.
+    fn put_key(&self, crypt: &FileCrypt) -> Result<()>;
+
+    /// Retrieves the `FileCrypt` stored under `uuid`.
+    fn get_key(&self, uuid: &str) -> Result<FileCrypt>;
+
+    /// Removes the entry for `uuid`, if any.
+    fn delete(&self, uuid: &str) -> Result<()>;
+
+    /// Lists every stored `FileCrypt`.
+    fn list(&self) -> Result<Vec<FileCrypt>>;
+
+    /// Finds the `FileCrypt` whose `full_path` matches `path`. The default
+    /// implementation scans [`list`](Self::list); backends indexed by path
+    /// (like the SQLite keeper) can override this with a direct lookup.
+    fn find_by_full_path(&self, path: &Path) -> Result<FileCrypt> {
+        self.list()?
+            .into_iter()
+            .find(|fc| fc.full_path == path)
+            .ok_or_else(|| {
+                Error::KeyStoreError(error::KeyStoreError::NotFound(path.display().to_string()))
+            })
+    }
+}
+
+/// [`KeyStore`] backed by the SQLite keeper (`db.rs`) -- the original,
+/// default storage.
+pub struct SqliteKeyStore;
+
+impl KeyStore for SqliteKeyStore {
+    fn put_key(&self, crypt: &FileCrypt) -> Result<()> {
+        db::insert_crypt(crypt)
+    }
+
+    fn get_key(&self, uuid: &str) -> Result<FileCrypt> {
+        db::query_crypt(uuid.to_string())
+    }
+
+    fn delete(&self, uuid: &str) -> Result<()> {
+        db::delete_crypt(uuid.to_string())
+    }
+
+    fn list(&self) -> Result<Vec<FileCrypt>> {
+        db::query_keeper_crypt()
+    }
+
+    fn find_by_full_path(&self, path: &Path) -> Result<FileCrypt> {
+        db::query_keeper_for_existing_file(path.to_path_buf())
+    }
+}
+
+/// The `keyring` crate service name entries are stored under.
+const KEYRING_SERVICE: &str = "bytecrypt";
+
+/// Username of the index entry that tracks every uuid stored under
+/// [`KEYRING_SERVICE`], since most platform keychains don't expose a portable
+/// "list all entries for this service" API.
+const KEYRING_INDEX_USER: &str = "__index__";
+
+/// [`KeyStore`] backed by the OS keychain (Keychain/Credential Manager/Secret
+/// Service) via the `keyring` crate. Each `FileCrypt` is serialized to JSON
+/// and stored under its uuid.
+pub struct KeyringKeyStore;
+
+impl KeyringKeyStore {
+    fn backend_err(e: keyring::Error) -> Error {
+        Error::KeyStoreError(error::KeyStoreError::Backend(e.to_string()))
+    }
+
+    fn index(&self) -> Result<Vec<String>> {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_INDEX_USER).map_err(Self::backend_err)?;
+        match entry.get_password() {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(keyring::Error::NoEntry) => Ok(Vec::new()),
+            Err(e) => Err(Self::backend_err(e)),
+        }
+    }
+
+    fn save_index(&self, uuids: &[String]) -> Result<()> {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_INDEX_USER).map_err(Self::backend_err)?;
+        let json = serde_json::to_string(uuids)?;
+        entry.set_password(&json).map_err(Self::backend_err)
+    }
+}
+
+impl KeyStore for KeyringKeyStore {
+    fn put_key(&self, crypt: &FileCrypt) -> Result<()> {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, &crypt.uuid).map_err(Self::backend_err)?;
+        let json = serde_json::to_string(crypt)?;
+        entry.set_password(&json).map_err(Self::backend_err)?;
+
+        let mut uuids = self.index()?;
+        if !uuids.contains(&crypt.uuid) {
+            uuids.push(crypt.uuid.clone());
+            self.save_index(&uuids)?;
+        }
+        Ok(())
+    }
+
+    fn get_key(&self, uuid: &str) -> Result<FileCrypt> {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, uuid).map_err(Self::backend_err)?;
+        let json = entry.get_password().map_err(|e| match e {
+            keyring::Error::NoEntry => Error::KeyStoreError(error::KeyStoreError::NotFound(uuid.to_string())),
+            e => Self::backend_err(e),
+        })?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    fn delete(&self, uuid: &str) -> Result<()> {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, uuid).map_err(Self::backend_err)?;
+        entry.delete_credential().map_err(Self::backend_err)?;
+
+        let mut uuids = self.index()?;
+        uuids.retain(|u| u != uuid);
+        self.save_index(&uuids)
+    }
+
+    fn list(&self) -> Result<Vec<FileCrypt>> {
+        self.index()?.into_iter().map(|uuid| self.get_key(&uuid)).collect()
+    }
+}
+
+/// Which [`KeyStore`] backend a [`crate::config::Config`] selects.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyStoreBackend {
+    /// Store keys and metadata in the SQLite keeper. Default.
+    #[default]
+    Sqlite,
+    /// Store keys and metadata in the OS keychain via the `keyring` crate.
+    Keyring,
+}
+
+impl std::fmt::Display for KeyStoreBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sqlite => write!(f, "sqlite"),
+            Self::Keyring => write!(f, "keyring"),
+        }
+    }
+}
+
+impl std::str::FromStr for KeyStoreBackend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "sqlite" => Ok(Self::Sqlite),
+            "keyring" => Ok(Self::Keyring),
+            _ => Err(Error::KeyStoreError(error::KeyStoreError::InvalidBackend(
+                s.to_string(),
+            ))),
+        }
+    }
+}
+
+/// Returns the [`KeyStore`] backend configured via [`crate::config::Config::key_store`].
+pub fn current() -> Box<dyn KeyStore> {
+    match crate::config::get_config().get_key_store() {
+        KeyStoreBackend::Sqlite => Box::new(SqliteKeyStore),
+        KeyStoreBackend::Keyring => Box::new(KeyringKeyStore),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample_crypt(uuid_seed: &str) -> FileCrypt {
+        let mut fc = FileCrypt::new(
+            "f".to_string(),
+            ".txt".to_string(),
+            "".to_string(),
+            std::path::PathBuf::from(format!("/tmp/{}.txt", uuid_seed)),
+            [0u8; 32],
+        );
+        fc.uuid = uuid_seed.to_string();
+        fc
+    }
+
+    #[test]
+    fn test_sqlite_key_store_round_trip() {
+        let store = SqliteKeyStore;
+        let fc = sample_crypt("keystore-sqlite-test-uuid");
+        store.put_key(&fc).unwrap();
+
+        let fetched = store.get_key(&fc.uuid).unwrap();
+        assert_eq!(fetched.uuid, fc.uuid);
+
+        store.delete(&fc.uuid).unwrap();
+        assert!(store.get_key(&fc.uuid).is_err());
+    }
+
+    #[test]
+    #[ignore = "works locally, fails in CI: no OS keychain/Secret Service available"]
+    fn test_keyring_key_store_round_trip() {
+        let store = KeyringKeyStore;
+        let fc = sample_crypt("keystore-keyring-test-uuid");
+        store.put_key(&fc).unwrap();
+
+        let fetched = store.get_key(&fc.uuid).unwrap();
+        assert_eq!(fetched.uuid, fc.uuid);
+        assert!(store.list().unwrap().iter().any(|c| c.uuid == fc.uuid));
+
+        store.delete(&fc.uuid).unwrap();
+        assert!(store.get_key(&fc.uuid).is_err());
+    }
+
+    #[test]
+    fn test_key_store_backend_round_trips_through_display_and_from_str() {
+        assert_eq!("sqlite".parse::<KeyStoreBackend>().unwrap(), KeyStoreBackend::Sqlite);
+        assert_eq!("keyring".parse::<KeyStoreBackend>().unwrap(), KeyStoreBackend::Keyring);
+        assert!("bogus".parse::<KeyStoreBackend>().is_err());
+        assert_eq!(KeyStoreBackend::Sqlite.to_string(), "sqlite");
+        assert_eq!(KeyStoreBackend::Keyring.to_string(), "keyring");
+    }
+}
@@ -1,10 +1,14 @@
 #![allow(clippy::needless_return)]
+pub mod bundle;
 pub mod common;
 pub mod config;
 pub mod db;
 pub mod encryption;
 pub mod error;
+pub mod events;
 pub mod filecrypt;
 pub mod filetree;
+pub mod keystore;
 pub mod prelude;
+mod test_support;
 pub mod token;
@@ -0,0 +1,20 @@
+#![cfg(test)]
+
+//! Shared test-only synchronization for the crate's singleton `KEEPER` DB pool
+//! and `Config` (see `db.rs`/`config.rs`): both are process-wide, so any two
+//! tests that touch the database or mutate config concurrently (the default
+//! under `cargo test`) can race -- one test's write lands mid another's
+//! transaction, or a config setter leaks into an assertion in a different
+//! test. `db_test_guard` gives every such test one shared lock to serialize
+//! behind instead of each module inventing its own scoped mutex.
+
+use std::sync::Mutex;
+
+static DB_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// Acquire the crate-wide DB/config test lock. Poison-safe: a prior test
+/// panicking while holding the guard must not cascade into every later test
+/// failing with `PoisonError` instead of actually running.
+pub(crate) fn db_test_guard() -> std::sync::MutexGuard<'static, ()> {
+    DB_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -3,7 +3,7 @@ use crate::{
     common::{get_config_folder, get_crypt_folder, parse_json_token, send_information},
     config::get_config,
     db,
-    encryption::{compress, decompress, generate_seeds},
+    encryption::{compress, decompress, decrypt_bytes_with_key, encrypt_bytes_with_key, generate_seeds},
     encryption::{KEY_SIZE, NONCE_SIZE},
     error::*,
 };
@@ -21,10 +21,16 @@ use std::{
     io::{BufRead, BufReader, Write},
     net::TcpListener,
     path::Path,
+    sync::Mutex,
     time::{SystemTime, UNIX_EPOCH},
 };
 use url::Url;
 
+/// Tokens expiring within this many seconds of "now" are treated as already
+/// expired, so a slightly-fast local clock (or a token that expires mid-request)
+/// doesn't hand out a token that the server rejects moments later.
+const TOKEN_EXPIRY_SKEW_SECS: u64 = 60;
+
 lazy_static! {
     ///Path for the google user token
     pub static ref GOOGLE_TOKEN_PATH: String = {
@@ -49,6 +55,14 @@ lazy_static! {
         path.push(".dropbox");
         format!("{}", path.display())
     };
+
+    /// Single-flight guard for the Google auth flow -- held for the entire
+    /// duration of `new_google`'s interactive/refresh path so that concurrent
+    /// callers block instead of racing to bind the redirect listener.
+    static ref GOOGLE_AUTH_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Single-flight guard for the Dropbox auth flow, mirroring `GOOGLE_AUTH_LOCK`.
+    static ref DROPBOX_AUTH_LOCK: Mutex<()> = Mutex::new(());
 }
 
 ///Supported cloud platforms
@@ -88,6 +102,62 @@ impl std::str::FromStr for CloudService {
     }
 }
 
+/// OAuth scope requested when authorizing a Google Drive token. Controls which
+/// operations the resulting token can perform, and how alarming the consent
+/// screen looks to the user -- narrower scopes are preferred when the caller
+/// doesn't need full Drive access.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CloudScope {
+    /// Full read/write access to all of the user's Drive files. Required to
+    /// upload/update files this app didn't itself create.
+    #[default]
+    Full,
+    /// Read/write access, but only to files this app created or that the user
+    /// explicitly opened with it. Supports upload.
+    File,
+    /// Read-only access to all of the user's Drive files. Does not support upload.
+    ReadOnly,
+}
+
+impl CloudScope {
+    /// The OAuth scope string sent to Google for this variant.
+    pub fn as_scope_str(&self) -> &'static str {
+        match self {
+            Self::Full => "https://www.googleapis.com/auth/drive",
+            Self::File => "https://www.googleapis.com/auth/drive.file",
+            Self::ReadOnly => "https://www.googleapis.com/auth/drive.readonly",
+        }
+    }
+
+    /// Whether a token authorized with this scope is allowed to upload/update files.
+    pub fn supports_upload(&self) -> bool {
+        !matches!(self, Self::ReadOnly)
+    }
+}
+
+impl Display for CloudScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Full => write!(f, "full"),
+            Self::File => write!(f, "file"),
+            Self::ReadOnly => write!(f, "readonly"),
+        }
+    }
+}
+
+impl std::str::FromStr for CloudScope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::prelude::v1::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "full" | "drive" => Ok(Self::Full),
+            "file" | "drive.file" => Ok(Self::File),
+            "readonly" | "read-only" | "drive.readonly" => Ok(Self::ReadOnly),
+            _ => Err(Error::TokenError(TokenError::InvalidScope(s.to_string()))),
+        }
+    }
+}
+
 ///Holds user authentication information
 ///
 /// # Fields
@@ -114,181 +184,213 @@ pub struct UserToken {
     pub access_token: String,
 }
 
+/// Runs `flow` to completion under `lock`, unless another caller already
+/// refreshed `service`'s token while we were waiting for the lock.
+///
+/// Only the first thread to acquire `lock` performs the (potentially
+/// interactive) auth flow; every other thread blocks on the mutex, then
+/// re-checks the cached token and reuses whatever the first thread produced
+/// instead of running the flow a second time.
+fn single_flight_token(
+    lock: &Mutex<()>,
+    service: CloudService,
+    flow: impl FnOnce() -> UserToken,
+) -> UserToken {
+    let _guard = lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    if let Ok(user_token) = get_access_token(service) {
+        return user_token;
+    }
+
+    flow()
+}
+
 impl UserToken {
     /// Generate a new user token to use with Google Drive.
     /// - Prompts user with link to authenticate with google.
     /// - Once the user successfully authenticates, a token will be created.
     ///
+    /// Concurrent callers single-flight through [`GOOGLE_AUTH_LOCK`]: only the
+    /// first caller runs the interactive flow below, the rest reuse its result.
+    ///
     /// # Options:
     ///```ignore
     /// let google_token = UserToken::new_google();
     ///```
-    #[allow(clippy::manual_flatten)]
     pub fn new_google() -> Self {
-        //Check if user_token already exists in database
-        let user_token = get_access_token(CloudService::Google);
-        if let Ok(user_token) = user_token {
-            return user_token;
-        }
-
-        let _parse_json_token = parse_json_token();
-
-        // Unwrapping token_result will either produce a Token or a RequestTokenError.
-        let google_client_id = ClientId::new(
-            env::var("GOOGLE_CLIENT_ID")
-                .expect("Missing the GOOGLE_CLIENT_ID environment variable."),
-        );
-        let google_client_secret = ClientSecret::new(
-            env::var("GOOGLE_CLIENT_SECRET")
-                .expect("Missing the GOOGLE_CLIENT_SECRET environment variable."),
-        );
-        let auth_url = AuthUrl::new("https://accounts.google.com/o/oauth2/v2/auth".to_string())
-            .expect("Invalid authorization endpoint URL");
-        let token_url = TokenUrl::new("https://www.googleapis.com/oauth2/v3/token".to_string())
-            .expect("Invalid token endpoint URL");
-
-        // Set up the config for the Google OAuth2 process.
-        let client = BasicClient::new(
-            google_client_id,
-            Some(google_client_secret),
-            auth_url,
-            Some(token_url),
-        )
-        // This example will be running its own server at localhost:8080.
-        // See below for the server implementation.
-        .set_redirect_uri(
-            RedirectUrl::new("http://127.0.0.1:3000".to_string()).expect("Invalid redirect URL"),
-        );
-        // Google supports OAuth 2.0 Token Revocation (RFC-7009)
-        // .set_revocation_uri(
-        //     RevocationUrl::new("https://oauth2.googleapis.com/revoke".to_string())
-        //         .expect("Invalid revocation endpoint URL"),
-        // );
-
-        // Google supports Proof Key for Code Exchange (PKCE - https://oauth.net/2/pkce/).
-        // Create a PKCE code verifier and SHA-256 encode it as a code challenge.
-        let (pkce_code_challenge, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
-
-        // Generate the authorization URL to which we'll redirect the user.
-        let (authorize_url, _csrf_state) = client
-            .authorize_url(CsrfToken::new_random)
-            // This example is requesting access to the "calendar" features and the user's profile.
-            .add_scope(Scope::new(
-                "https://www.googleapis.com/auth/drive".to_string(),
-            ))
-            .set_pkce_challenge(pkce_code_challenge)
-            .url();
-
-        // println!("Open this URL in your browser:\n{}\n", authorize_url);
-        let _open = webbrowser::open(authorize_url.as_ref());
-
-        // A very naive implementation of the redirect server.
-        let listener = TcpListener::bind("127.0.0.1:3000").unwrap();
-        for stream in listener.incoming() {
-            if let Ok(mut stream) = stream {
-                let code;
-                let _state;
-                {
-                    let mut reader = BufReader::new(&stream);
-
-                    let mut request_line = String::new();
-                    reader.read_line(&mut request_line).unwrap();
-
-                    let redirect_url = request_line.split_whitespace().nth(1).unwrap();
-                    let url = Url::parse(&("http://localhost".to_string() + redirect_url)).unwrap();
-
-                    let code_pair = url
-                        .query_pairs()
-                        .find(|pair| {
-                            let (key, _) = pair;
-                            key == "code"
-                        })
-                        .unwrap();
-
-                    let (_, value) = code_pair;
-                    code = AuthorizationCode::new(value.into_owned());
-
-                    let state_pair = url
-                        .query_pairs()
-                        .find(|pair| {
-                            let (key, _) = pair;
-                            key == "state"
-                        })
-                        .unwrap();
-
-                    let (_, value) = state_pair;
-                    _state = CsrfToken::new(value.into_owned());
-                }
+        single_flight_token(&GOOGLE_AUTH_LOCK, CloudService::Google, google_auth_flow)
+    }
+}
 
-                let message = "Go back to your terminal :)";
-                let response = format!(
-                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
-                    message.len(),
-                    message
-                );
-                stream.write_all(response.as_bytes()).unwrap();
-
-                // Exchange the code with a token.
-                let token_response = client
-                    .exchange_code(code)
-                    .set_pkce_verifier(pkce_code_verifier)
-                    .request(http_client);
-
-                println!(
-                    "Google returned the following token:\n{:?}\n",
-                    token_response
-                );
-
-                let token_response = token_response.unwrap();
-                let access_token = token_response.access_token();
-                let expire = token_response.expires_in().unwrap();
-
-                //Create the user_token
-                let (key_seed, nonce_seed) = generate_seeds();
-                let user_token = Self {
-                    service: CloudService::Google,
-                    key_seed,
-                    nonce_seed,
-                    expiration: SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .expect("Somehow, time has gone backwards")
-                        .as_secs()
-                        + expire.as_secs(),
-                    access_token: access_token.secret().to_owned(),
-                };
-
-                let _ = db::insert_token(&user_token);
-                let _ = save_access_token(&user_token);
-                return user_token;
+/// Runs the interactive Google OAuth flow and returns the resulting token.
+/// Only ever invoked from behind [`GOOGLE_AUTH_LOCK`] via [`single_flight_token`].
+#[allow(clippy::manual_flatten)]
+fn google_auth_flow() -> UserToken {
+    let _parse_json_token = parse_json_token();
+
+    // Unwrapping token_result will either produce a Token or a RequestTokenError.
+    let google_client_id = ClientId::new(
+        env::var("GOOGLE_CLIENT_ID").expect("Missing the GOOGLE_CLIENT_ID environment variable."),
+    );
+    let google_client_secret = ClientSecret::new(
+        env::var("GOOGLE_CLIENT_SECRET")
+            .expect("Missing the GOOGLE_CLIENT_SECRET environment variable."),
+    );
+    let auth_url = AuthUrl::new("https://accounts.google.com/o/oauth2/v2/auth".to_string())
+        .expect("Invalid authorization endpoint URL");
+    let token_url = TokenUrl::new("https://www.googleapis.com/oauth2/v3/token".to_string())
+        .expect("Invalid token endpoint URL");
+
+    // Set up the config for the Google OAuth2 process.
+    let client = BasicClient::new(
+        google_client_id,
+        Some(google_client_secret),
+        auth_url,
+        Some(token_url),
+    )
+    // This example will be running its own server at localhost:8080.
+    // See below for the server implementation.
+    .set_redirect_uri(
+        RedirectUrl::new("http://127.0.0.1:3000".to_string()).expect("Invalid redirect URL"),
+    );
+    // Google supports OAuth 2.0 Token Revocation (RFC-7009)
+    // .set_revocation_uri(
+    //     RevocationUrl::new("https://oauth2.googleapis.com/revoke".to_string())
+    //         .expect("Invalid revocation endpoint URL"),
+    // );
+
+    // Google supports Proof Key for Code Exchange (PKCE - https://oauth.net/2/pkce/).
+    // Create a PKCE code verifier and SHA-256 encode it as a code challenge.
+    let (pkce_code_challenge, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
+
+    // Generate the authorization URL to which we'll redirect the user.
+    let cloud_scope = get_config().cloud_scope;
+    let (authorize_url, _csrf_state) = client
+        .authorize_url(CsrfToken::new_random)
+        .add_scope(Scope::new(cloud_scope.as_scope_str().to_string()))
+        .set_pkce_challenge(pkce_code_challenge)
+        .url();
+
+    // println!("Open this URL in your browser:\n{}\n", authorize_url);
+    let _open = webbrowser::open(authorize_url.as_ref());
+
+    // A very naive implementation of the redirect server.
+    let listener = TcpListener::bind("127.0.0.1:3000").unwrap();
+    for stream in listener.incoming() {
+        if let Ok(mut stream) = stream {
+            let code;
+            let _state;
+            {
+                let mut reader = BufReader::new(&stream);
+
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+
+                let redirect_url = request_line.split_whitespace().nth(1).unwrap();
+                let url = Url::parse(&("http://localhost".to_string() + redirect_url)).unwrap();
+
+                let code_pair = url
+                    .query_pairs()
+                    .find(|pair| {
+                        let (key, _) = pair;
+                        key == "code"
+                    })
+                    .unwrap();
+
+                let (_, value) = code_pair;
+                code = AuthorizationCode::new(value.into_owned());
+
+                let state_pair = url
+                    .query_pairs()
+                    .find(|pair| {
+                        let (key, _) = pair;
+                        key == "state"
+                    })
+                    .unwrap();
+
+                let (_, value) = state_pair;
+                _state = CsrfToken::new(value.into_owned());
             }
+
+            let message = "Go back to your terminal :)";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
+                message.len(),
+                message
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+
+            // Exchange the code with a token.
+            let token_response = client
+                .exchange_code(code)
+                .set_pkce_verifier(pkce_code_verifier)
+                .request(http_client);
+
+            println!(
+                "Google returned the following token:\n{:?}\n",
+                token_response
+            );
+
+            let token_response = token_response.unwrap();
+            let access_token = token_response.access_token();
+            let expire = token_response.expires_in().unwrap();
+
+            //Create the user_token
+            let (key_seed, nonce_seed) = generate_seeds();
+            let user_token = UserToken {
+                service: CloudService::Google,
+                key_seed,
+                nonce_seed,
+                expiration: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Somehow, time has gone backwards")
+                    .as_secs()
+                    + expire.as_secs(),
+                access_token: access_token.secret().to_owned(),
+            };
+
+            let _ = db::insert_token(&user_token);
+            let _ = save_access_token(&user_token);
+            return user_token;
         }
-        return UserToken::default();
     }
+    UserToken::default()
+}
 
+impl UserToken {
     /// Generate a new user token to use with Dropbox.
     /// - Prompts user with link to authenticate with Dropbox.
     /// - Once the user successfully authenticates, a token will be created.
     ///
+    /// Concurrent callers single-flight through [`DROPBOX_AUTH_LOCK`], mirroring
+    /// [`UserToken::new_google`].
+    ///
     /// # Options:
     ///```ignore
     /// let dropbox_token = UserToken::new_dropbox();
     ///```
     pub fn new_dropbox() -> Self {
-        let client_id = "im68gew9aehy2pn".to_string();
+        single_flight_token(&DROPBOX_AUTH_LOCK, CloudService::Dropbox, dropbox_auth_flow)
+    }
+}
 
-        let client = BasicClient::new(
-            ClientId::new(client_id),
-            None,
-            AuthUrl::new("https://www.dropbox.com/oauth2/authorize".to_string())
-                .expect("Invalid authorization endpoint URL"),
-            None,
-        )
-        .set_redirect_uri(RedirectUrl::new("http://localhost:3000".to_string()).unwrap());
+/// Runs the interactive Dropbox OAuth flow and returns the resulting token.
+/// Only ever invoked from behind [`DROPBOX_AUTH_LOCK`] via [`single_flight_token`].
+fn dropbox_auth_flow() -> UserToken {
+    let client_id = "im68gew9aehy2pn".to_string();
 
-        let (_authorize_url, _csrf_state) = client.authorize_url(CsrfToken::new_random).url();
+    let client = BasicClient::new(
+        ClientId::new(client_id),
+        None,
+        AuthUrl::new("https://www.dropbox.com/oauth2/authorize".to_string())
+            .expect("Invalid authorization endpoint URL"),
+        None,
+    )
+    .set_redirect_uri(RedirectUrl::new("http://localhost:3000".to_string()).unwrap());
 
-        todo!()
-    }
+    let (_authorize_url, _csrf_state) = client.authorize_url(CsrfToken::new_random).url();
+
+    todo!()
 }
 
 ///Attempts to get an access token from the database
@@ -313,7 +415,7 @@ fn get_access_token(service: CloudService) -> Result<UserToken> {
                 .expect("Somehow, time has gone backwards")
                 .as_secs();
 
-            match user_token.expiration > current_time {
+            match user_token.expiration > current_time + TOKEN_EXPIRY_SKEW_SECS {
                 true => {
                     user_token.access_token = decrypt_token(&user_token, access_token);
                     Ok(user_token)
@@ -344,6 +446,13 @@ pub fn encrypt_token(user_token: &UserToken) -> Result<Vec<u8>> {
     let compressed_token = compress(token, conf.zstd_level);
     token = compressed_token.as_slice();
 
+    // When a metadata master key is unlocked for this session, wrap the token
+    // with it instead of the per-token key_seed/nonce_seed -- the wrap key then
+    // never touches the keeper database at all.
+    if let Some(kek) = db::get_metadata_key() {
+        return encrypt_bytes_with_key(&kek, token);
+    }
+
     let cipher = ChaCha20Poly1305::new(Key::from_slice(&user_token.key_seed))
         .encrypt(Nonce::from_slice(&user_token.nonce_seed), token)
         .expect("Failed to encrypt access_token");
@@ -353,9 +462,13 @@ pub fn encrypt_token(user_token: &UserToken) -> Result<Vec<u8>> {
 pub fn decrypt_token(user_token: &UserToken, access_token: Vec<u8>) -> String {
     let token = access_token.as_slice();
 
-    let cipher = ChaCha20Poly1305::new(Key::from_slice(&user_token.key_seed))
-        .decrypt(Nonce::from_slice(&user_token.nonce_seed), token.as_ref())
-        .expect("Failed to decrypt access_token");
+    let cipher = if let Some(kek) = db::get_metadata_key() {
+        decrypt_bytes_with_key(&kek, token).expect("Failed to decrypt access_token")
+    } else {
+        ChaCha20Poly1305::new(Key::from_slice(&user_token.key_seed))
+            .decrypt(Nonce::from_slice(&user_token.nonce_seed), token.as_ref())
+            .expect("Failed to decrypt access_token")
+    };
 
     let decompressed_token = match decompress(cipher.as_slice()) {
         Ok(d) => d,
@@ -365,6 +478,38 @@ pub fn decrypt_token(user_token: &UserToken, access_token: Vec<u8>) -> String {
     String::from_utf8(decompressed_token).expect("Could not decrypt token")
 }
 
+/// Re-encrypts every stored OAuth token under fresh wrap key material.
+///
+/// Under a metadata master key ([`db::get_metadata_key`], i.e. `private_metadata`
+/// unlocked for this session), the wrap key is re-derived from the currently
+/// active passphrase rather than stored anywhere, so this just rewrites the
+/// token file under a freshly generated nonce. Otherwise, rolls a brand new
+/// random `key_seed`/`nonce_seed` pair per token and persists it to both the
+/// keeper database and the on-disk token file, retiring whatever pair was
+/// there before -- useful if that pair is suspected to have leaked.
+///
+/// Returns the number of tokens rotated.
+pub fn rotate_token_keys() -> Result<usize> {
+    let mut rotated = 0;
+
+    for service in [CloudService::Google, CloudService::Dropbox] {
+        let mut user_token = match get_access_token(service) {
+            Ok(user_token) => user_token,
+            Err(_) => continue,
+        };
+
+        if db::get_metadata_key().is_none() {
+            (user_token.key_seed, user_token.nonce_seed) = generate_seeds();
+        }
+
+        db::insert_token(&user_token)?;
+        save_access_token(&user_token)?;
+        rotated += 1;
+    }
+
+    Ok(rotated)
+}
+
 pub fn purge_tokens() {
     let mut path = get_crypt_folder();
     path.push(".config");
@@ -381,3 +526,186 @@ pub fn purge_tokens() {
         send_information(vec![format!("removed dropbox token file.")]);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_authorize_url_contains_configured_scope() {
+        for scope in [CloudScope::Full, CloudScope::File, CloudScope::ReadOnly] {
+            let client = BasicClient::new(
+                ClientId::new("test_client_id".to_string()),
+                None,
+                AuthUrl::new("https://accounts.google.com/o/oauth2/v2/auth".to_string()).unwrap(),
+                Some(TokenUrl::new("https://www.googleapis.com/oauth2/v3/token".to_string()).unwrap()),
+            )
+            .set_redirect_uri(RedirectUrl::new("http://127.0.0.1:3000".to_string()).unwrap());
+            let (pkce_code_challenge, _pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
+
+            let (authorize_url, _csrf_state) = client
+                .authorize_url(CsrfToken::new_random)
+                .add_scope(Scope::new(scope.as_scope_str().to_string()))
+                .set_pkce_challenge(pkce_code_challenge)
+                .url();
+
+            let requested_scope = authorize_url
+                .query_pairs()
+                .find(|(key, _)| key == "scope")
+                .map(|(_, value)| value.into_owned())
+                .expect("authorize_url is missing a scope parameter");
+
+            assert_eq!(requested_scope, scope.as_scope_str());
+        }
+    }
+
+    #[test]
+    fn test_single_flight_runs_auth_flow_only_once_across_threads() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Arc, Barrier};
+
+        let _guard = crate::test_support::db_test_guard();
+
+        // Start from a clean slate so both threads see a missing/expired token.
+        _ = fs::remove_file(GOOGLE_TOKEN_PATH.as_str());
+
+        let flow_runs = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(2));
+
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let flow_runs = Arc::clone(&flow_runs);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    single_flight_token(&GOOGLE_AUTH_LOCK, CloudService::Google, || {
+                        flow_runs.fetch_add(1, Ordering::SeqCst);
+
+                        // Stand in for the interactive flow: fabricate a token
+                        // as if the user had just finished authenticating.
+                        let (key_seed, nonce_seed) = generate_seeds();
+                        let user_token = UserToken {
+                            service: CloudService::Google,
+                            key_seed,
+                            nonce_seed,
+                            expiration: SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .unwrap()
+                                .as_secs()
+                                + 3600,
+                            access_token: "test-access-token".to_string(),
+                        };
+                        let _ = db::insert_token(&user_token);
+                        let _ = save_access_token(&user_token);
+                        user_token
+                    })
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(flow_runs.load(Ordering::SeqCst), 1);
+
+        _ = fs::remove_file(GOOGLE_TOKEN_PATH.as_str());
+    }
+
+    fn make_test_token(service: CloudService) -> UserToken {
+        let (key_seed, nonce_seed) = generate_seeds();
+        UserToken {
+            service,
+            key_seed,
+            nonce_seed,
+            expiration: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                + 3600,
+            access_token: "test-access-token".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_rotate_token_keys_legacy_mode_still_decrypts_after_rotation() {
+        let _guard = crate::test_support::db_test_guard();
+
+        db::clear_metadata_key();
+
+        let user_token = make_test_token(CloudService::Google);
+        db::insert_token(&user_token).unwrap();
+        save_access_token(&user_token).unwrap();
+        let old_key_seed = user_token.key_seed;
+
+        let rotated = rotate_token_keys().unwrap();
+        assert!(rotated >= 1);
+
+        let refreshed = get_access_token(CloudService::Google).unwrap();
+        assert_ne!(refreshed.key_seed, old_key_seed);
+        assert_eq!(refreshed.access_token, "test-access-token");
+
+        _ = fs::remove_file(GOOGLE_TOKEN_PATH.as_str());
+    }
+
+    #[test]
+    fn test_rotate_token_keys_kek_mode_still_decrypts_after_rotation() {
+        let _guard = crate::test_support::db_test_guard();
+
+        let user_token = make_test_token(CloudService::Dropbox);
+        db::insert_token(&user_token).unwrap();
+
+        db::set_metadata_key("rotation-test-passphrase");
+        save_access_token(&user_token).unwrap();
+
+        let rotated = rotate_token_keys().unwrap();
+        assert!(rotated >= 1);
+
+        let refreshed = get_access_token(CloudService::Dropbox).unwrap();
+        assert_eq!(refreshed.access_token, "test-access-token");
+
+        db::clear_metadata_key();
+        _ = fs::remove_file(DROPBOX_TOKEN_PATH.as_str());
+    }
+
+    #[test]
+    fn test_get_access_token_treats_token_expiring_within_skew_as_expired() {
+        let _guard = crate::test_support::db_test_guard();
+
+        let mut user_token = make_test_token(CloudService::Google);
+        user_token.expiration = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + TOKEN_EXPIRY_SKEW_SECS
+            - 1;
+        db::insert_token(&user_token).unwrap();
+        save_access_token(&user_token).unwrap();
+
+        assert!(matches!(
+            get_access_token(CloudService::Google),
+            Err(Error::TokenError(TokenError::ExpiredToken))
+        ));
+
+        _ = fs::remove_file(GOOGLE_TOKEN_PATH.as_str());
+    }
+
+    #[test]
+    fn test_get_access_token_accepts_token_expiring_just_beyond_skew() {
+        let _guard = crate::test_support::db_test_guard();
+
+        let mut user_token = make_test_token(CloudService::Google);
+        user_token.expiration = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + TOKEN_EXPIRY_SKEW_SECS
+            + 60;
+        db::insert_token(&user_token).unwrap();
+        save_access_token(&user_token).unwrap();
+
+        assert!(get_access_token(CloudService::Google).is_ok());
+
+        _ = fs::remove_file(GOOGLE_TOKEN_PATH.as_str());
+    }
+}
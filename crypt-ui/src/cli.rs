@@ -2,15 +2,21 @@ use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
 use crypt_cloud::crypt_core::{
-    common::{get_machine_name, send_information},
+    common::{get_machine_name, send_information, TreeCharset},
     config::{self, ConfigTask, ItemsTask},
-    db::import_keeper,
+    db::{import_keeper, read_passphrase_non_interactive},
+    encryption::self_test,
+    filecrypt::{DecryptNaming, EncryptCollision},
+    keystore::KeyStoreBackend,
+    token::CloudScope,
 };
+use crypt_cloud::drive::Quota;
 
 use crate::directive::{
     self, dropbox_download, dropbox_upload, dropbox_view, google_download, google_view,
+    UploadOutcome, UploadResult,
 };
-// use crate::tui::load_tui;
+use crate::tui::load_tui;
 
 ///CLI arguments
 #[derive(Parser, Debug)]
@@ -25,9 +31,9 @@ pub struct CommandLineArgs {
     #[arg(long, hide = true)]
     md: bool,
 
-    ///TUI mode
-    // #[arg(short, long, default_value_t = false)]
-    // pub tui: bool,
+    /// Launch the interactive TUI to browse the crypt folder and decrypt files
+    #[arg(long, default_value_t = false)]
+    pub tui: bool,
 
     #[arg(short, default_value_t = false)]
     pub test: bool,
@@ -63,6 +69,41 @@ enum Commands {
         ///Change the output path
         #[arg(short = 'o', long, required = false)]
         output: Option<String>,
+
+        ///Bypass the configured `max_file_size` guard
+        #[arg(long, default_value_t = false)]
+        allow_large: bool,
+
+        ///Allow `-o` to point outside the crypt folder via an absolute path
+        #[arg(long, default_value_t = false)]
+        allow_absolute_output: bool,
+
+        ///Suppress the byte-count progress reported while encrypting a directory
+        #[arg(short = 'q', long, default_value_t = false)]
+        quiet: bool,
+
+        ///Upload the resulting .crypt to Google Drive in the same command
+        #[arg(short = 'u', long, default_value_t = false)]
+        upload: bool,
+
+        ///Re-encrypt even if the tracked crypt's content hash is unchanged
+        #[arg(short = 'f', long, default_value_t = false)]
+        force: bool,
+
+        ///When encrypting a directory, encrypt every hardlinked path independently
+        ///instead of encrypting one and recording the rest to be relinked on decrypt
+        #[arg(long, default_value_t = false)]
+        dereference: bool,
+
+        ///Decrypt the freshly-written .crypt back in memory and confirm the hash
+        ///matches before reporting success. Also enabled by the verify_on_encrypt config default.
+        #[arg(long, default_value_t = false)]
+        verify: bool,
+
+        ///Ignore any batch-resume state left by a previous interrupted run of this
+        ///directory and start fresh instead of skipping files already marked done
+        #[arg(long, default_value_t = false)]
+        no_resume: bool,
     },
 
     ///Decrypt file or folder of files
@@ -74,6 +115,14 @@ enum Commands {
         ///Change the output path
         #[arg(short = 'o', long, required = false)]
         output: Option<String>,
+
+        ///Restore the original file's Unix permissions on the decrypted output
+        #[arg(long, default_value_t = cfg!(unix))]
+        preserve_permissions: bool,
+
+        ///Allow `-o` to point outside the decrypted folder via an absolute path
+        #[arg(long, default_value_t = false)]
+        allow_absolute_output: bool,
     },
 
     ///Import | Export | Purge database
@@ -93,6 +142,88 @@ enum Commands {
         #[arg(short = 'c', long, default_value_t = false)]
         cloud: bool,
     },
+
+    /// Tar the crypt folder and keeper database into a single portable backup file
+    ExportBundle {
+        /// Path to write the `.cryptbundle` file to
+        #[arg(required = true)]
+        out_path: String,
+
+        /// Encrypt the bundle with a passphrase
+        #[arg(short = 'p', long, required = false)]
+        passphrase: Option<String>,
+
+        /// Read the passphrase from stdin instead of passing it on the command
+        /// line (falls back to CRYPT_PASSWORD if not set). Ignored if
+        /// --passphrase is also given.
+        #[arg(long, default_value_t = false)]
+        password_stdin: bool,
+    },
+
+    /// Write a JSON restore index (filename, full path, extension, hash,
+    /// drive_id, and size for every tracked crypt), independent of the keeper
+    /// database
+    ExportManifest {
+        /// Path to write the manifest to
+        #[arg(required = true)]
+        out_path: String,
+
+        /// Include key/nonce material in the manifest for full offline
+        /// recovery. Makes the manifest as sensitive as the keeper database.
+        #[arg(long, default_value_t = false)]
+        with_keys: bool,
+    },
+
+    /// Restore a bundle produced by `export-bundle` into a target directory
+    ImportBundle {
+        /// Path to the `.cryptbundle` file to restore
+        #[arg(required = true)]
+        path: String,
+
+        /// Directory to restore the crypt folder and keeper database into
+        #[arg(required = true)]
+        target_dir: String,
+
+        /// Passphrase the bundle was encrypted with
+        #[arg(short = 'p', long, required = false)]
+        passphrase: Option<String>,
+
+        /// Read the passphrase from stdin instead of passing it on the command
+        /// line (falls back to CRYPT_PASSWORD if not set). Ignored if
+        /// --passphrase is also given.
+        #[arg(long, default_value_t = false)]
+        password_stdin: bool,
+    },
+
+    /// Run a known-answer test against every cryptographic primitive this build
+    /// relies on (ChaCha20Poly1305, Blake2s256, zstd) and report PASS/FAIL
+    Selftest,
+
+    /// Confirm a previously-decrypted file still matches the hash stored for
+    /// its crypt, without re-decrypting the `.crypt` to compare against
+    Compare {
+        /// Path to the plaintext file to check
+        #[arg(required = true)]
+        plaintext: String,
+
+        /// Uuid (or uuid prefix) of the crypt to compare against, or the path
+        /// to the `.crypt` file itself
+        #[arg(required = true)]
+        uuid_or_crypt: String,
+    },
+
+    /// Sweep every crypt tracked in the keeper, decrypting and rehashing each
+    /// to detect silent corruption. Exits non-zero if any crypt is flagged.
+    Scan {
+        /// Download each `.crypt` from Google Drive instead of reading it from
+        /// the local crypt folder
+        #[arg(long, default_value_t = false)]
+        cloud: bool,
+
+        /// Print the report as JSON instead of the human-readable summary
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
 }
 
 ///Subcommands for Upload
@@ -119,12 +250,17 @@ pub enum DriveCommand {
     /// Upload a file or folder
     #[command(short_flag = 'u')]
     Upload {
-        // /// Path to the file to be encrypted and uploaded to the cloud
-        // #[arg(required = false, default_value_t = String::from(""))]
-        // path: String,
-        // /// if flag is passed, do not encrypt.
-        // #[arg(long, short)]
-        // no_encrypt: bool,
+        /// Upload this path as-is, with no encryption, no FileCrypt, and no
+        /// keeper DB row -- for backing up already-encrypted or non-sensitive
+        /// files. Content type is guessed from the extension. If omitted, falls
+        /// back to the normal interactive chooser over managed crypts.
+        #[arg(long)]
+        raw: Option<String>,
+
+        /// Nested folder path (created if it doesn't exist) under the root
+        /// `Crypt` folder to upload into, instead of the root itself
+        #[arg(long)]
+        remote_folder: Option<String>,
     },
 
     /// Download a file or folder
@@ -133,6 +269,11 @@ pub enum DriveCommand {
         /// name of the file you want to get from the cloud
         #[arg(required = false, default_value_t = String::from(""))]
         path: String,
+
+        /// Nested folder path (created if it doesn't exist) under the root
+        /// `Crypt` folder to resolve/download from, instead of the root itself
+        #[arg(long)]
+        remote_folder: Option<String>,
     },
 
     /// View a file or folder
@@ -143,6 +284,20 @@ pub enum DriveCommand {
         #[arg(required = false, default_value_t = String::from("Crypt"))]
         path: String,
     },
+
+    /// Re-associate a local crypt with an existing cloud file by id
+    #[command(short_flag = 'l')]
+    Link {
+        /// Path to the local crypt file
+        path: String,
+
+        /// The Drive id of the existing cloud file to link to
+        drive_id: String,
+    },
+
+    /// Show remaining cloud storage
+    #[command(short_flag = 'q')]
+    Quota,
 }
 
 /// Subcommands for Config
@@ -162,6 +317,12 @@ pub enum ConfigCommand {
         /// Database path; if empty, prints current path
         #[arg(required = false, default_value_t = String::from(""))]
         path: String,
+
+        /// Move the old crypt path's files (all but its `logs` and `decrypted`
+        /// folders) to the new one and re-point their DB entries before
+        /// switching the config path.
+        #[arg(long, default_value_t = false)]
+        migrate: bool,
     },
 
     /// View or change which directories and/or filetypes are to be ignored
@@ -180,14 +341,131 @@ pub enum ConfigCommand {
     #[command()]
     Hwid {},
 
-    /// View or change the compression level (-7 to 22) -- higher is more compression
+    /// View or change the compression level (-7 to 22) -- higher is more compression.
+    /// Pass `auto` to benchmark a sample from the crypt folder and pick the best level.
     #[command(short_flag = 'z')]
     ZstdLevel {
-        /// value to update config
+        /// value to update config, or `auto`
         #[arg(required = false, default_value_t = String::from(""))]
         level: String,
     },
 
+    /// View or change the maximum file size (in bytes) `encrypt` will read into memory. `0` = unlimited.
+    #[command(short_flag = 'm')]
+    MaxFileSize {
+        /// value to update config
+        #[arg(required = false, default_value_t = String::from(""))]
+        bytes: String,
+    },
+
+    /// Enable or disable at-rest encryption of filename/extension/full_path metadata
+    #[command(short_flag = 'p')]
+    PrivateMetadata {
+        /// "on"/"true" to enable, "off"/"false" to disable
+        enabled: String,
+
+        /// Read the passphrase to encrypt with from stdin instead of prompting
+        /// the terminal (falls back to CRYPT_PASSWORD, then a prompt, if unset)
+        #[arg(long, default_value_t = false)]
+        password_stdin: bool,
+    },
+
+    /// View or change the OAuth scope requested from Google Drive on the next
+    /// `token generate`. Narrower scopes are safer and less scary in the
+    /// consent screen, but `readonly` cannot upload.
+    #[command(short_flag = 's')]
+    CloudScope {
+        /// "full", "file", or "readonly"; if empty, prints current scope
+        #[arg(required = false, default_value_t = String::from(""))]
+        scope: String,
+    },
+
+    /// View or change how decrypted files are named when no `-o` is given:
+    /// `subfolder` (today's default), `suffix` (name-decrypted.ext next to
+    /// where it would go), or `inline` (restore to the original full_path).
+    #[command(short_flag = 'n')]
+    DecryptNaming {
+        /// "subfolder", "suffix", or "inline"; if empty, prints current mode
+        #[arg(required = false, default_value_t = String::from(""))]
+        mode: String,
+    },
+
+    /// View or change which backend `filecrypt`'s encrypt/decrypt store keys in.
+    #[command(short_flag = 'k')]
+    KeyStore {
+        /// "sqlite" or "keyring"; if empty, prints current backend
+        #[arg(required = false, default_value_t = String::from(""))]
+        backend: String,
+    },
+
+    /// View or change the file size (in bytes) at or above which `encrypt` hashes
+    /// content with Blake3 instead of Blake2s. `0` = always use Blake2s.
+    #[command(short_flag = 't')]
+    HashParallelThreshold {
+        /// value to update config
+        #[arg(required = false, default_value_t = String::from(""))]
+        bytes: String,
+    },
+
+    /// View or change the directory staging operations (stdout decrypt, staged
+    /// download, streaming) write temp files into. Pass `default` to reset to
+    /// the system temp directory.
+    #[command(short_flag = 'e')]
+    TempPath {
+        /// value to update config, `default` to reset, or empty to print the current path
+        #[arg(required = false, default_value_t = String::from(""))]
+        path: String,
+    },
+
+    /// View or change what `encrypt` does when the target `.crypt` file already
+    /// belongs to a different source file: `rename` (today's default, picks the
+    /// next `name(n).crypt`), `skip`, or `overwrite` (deletes the old DB row first).
+    #[command(short_flag = 'x')]
+    EncryptCollision {
+        /// "rename", "skip", or "overwrite"; if empty, prints current policy
+        #[arg(required = false, default_value_t = String::from(""))]
+        policy: String,
+    },
+
+    /// View or change whether `encrypt` decrypts what it just wrote and verifies
+    /// the hash round-trips before reporting success, by default.
+    #[command(short_flag = 'v')]
+    VerifyOnEncrypt {
+        /// "on"/"true" to enable, "off"/"false" to disable; if empty, prints current setting
+        #[arg(required = false, default_value_t = String::from(""))]
+        enabled: String,
+    },
+
+    /// View or change whether `encrypt` generates a small encrypted preview
+    /// thumbnail alongside the `.crypt` for image source files. No-op in
+    /// builds without the `thumbnails` feature.
+    #[command(short_flag = 'h')]
+    GenerateThumbnails {
+        /// "on"/"true" to enable, "off"/"false" to disable; if empty, prints current setting
+        #[arg(required = false, default_value_t = String::from(""))]
+        enabled: String,
+    },
+
+    /// View or change the character set `build_tree`/`ls` draw box-drawing
+    /// connectors with: `unicode` (today's default), `ascii` (`|--`, `` `-- ``),
+    /// or `plain` (simple indentation, no connectors).
+    #[command(short_flag = 'r')]
+    TreeCharset {
+        /// "unicode", "ascii", or "plain"; if empty, prints current charset
+        #[arg(required = false, default_value_t = String::from(""))]
+        charset: String,
+    },
+
+    /// View or change how many sibling Drive folders `cloud -g` walks fetch
+    /// concurrently when building a tree. Higher values finish wide trees
+    /// faster at the cost of more simultaneous requests to Drive.
+    #[command(short_flag = 'w')]
+    CloudWalkConcurrency {
+        /// value to update config; if empty, prints the current limit
+        #[arg(required = false, default_value_t = String::from(""))]
+        limit: String,
+    },
+
     /// Revert config back to default
     #[command(short_flag = 'l')]
     LoadDefault,
@@ -222,6 +500,36 @@ pub enum KeeperCommand {
     /// List each file in the database
     #[command(short_flag = 'l')]
     List {},
+
+    /// Reclaim space freed by deleted/updated rows and defragment the database file
+    #[command(short_flag = 'c')]
+    Compact {},
+
+    /// Scan the keeper database for any (key, nonce) pair reused across FileCrypts
+    #[command(short_flag = 'n')]
+    AuditNonces {},
+
+    /// Update the stored full_path for a crypt after moving its source file
+    #[command(short_flag = 's')]
+    SetPath {
+        /// UUID of the crypt to update
+        uuid: String,
+
+        /// New full path of the source file
+        path: String,
+    },
+
+    /// List `.crypt` files in the crypt folder with no matching keeper row --
+    /// the inverse of a keeper row with no backing file. These can't be
+    /// decrypted locally until a keeper export containing their uuid is imported.
+    #[command(short_flag = 'o')]
+    Orphans {},
+
+    /// Re-encrypt stored OAuth token files under fresh wrap key material: a new
+    /// key_seed/nonce_seed pair retires the one on disk/in the database, except
+    /// while `private_metadata` is unlocked, where only a fresh nonce is used.
+    #[command(short_flag = 'r')]
+    RotateTokenKeys {},
 }
 
 /// Subcommands for Keeper
@@ -229,11 +537,19 @@ pub enum KeeperCommand {
 pub enum KeeperPurgeSubCommand {
     /// Purges google and Dropbox tokens
     #[command(short_flag = 't', alias = "tokens")]
-    Token {},
+    Token {
+        /// Report what would be purged without actually deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
 
     /// Purges database file and IS UNREVERSABLE!
     #[command(short_flag = 'd', alias = "db")]
-    Database {},
+    Database {
+        /// Report what would be purged without actually deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 impl KeeperCommand {
@@ -251,7 +567,10 @@ impl KeeperCommand {
 
 /// Runs the CLI and returns a directive to be processed
 pub fn load_cli() {
-    config::init(config::Interface::CLI);
+    if let Err(err) = config::init(config::Interface::CLI) {
+        eprintln!("failed to initialize crypt: {}", err);
+        std::process::exit(1);
+    }
 
     // Run the cli and get responses
     let cli = CommandLineArgs::parse();
@@ -267,9 +586,12 @@ pub fn load_cli() {
     }
 
     // Call TUI if flag was passed
-    // if cli.tui {
-    //     // load_tui().expect("failed to load TUI");
-    // }
+    if cli.tui {
+        if let Err(e) = load_tui() {
+            eprintln!("TUI exited with an error: {}", e);
+        }
+        return;
+    }
 
     if cli.test {
         directive::test();
@@ -286,14 +608,48 @@ pub fn load_cli() {
         }
 
         // Encryption
-        Some(Commands::Encrypt { path, output }) => {
-            let res = directive::encrypt(path, output.to_owned());
+        Some(Commands::Encrypt {
+            path,
+            output,
+            allow_large,
+            allow_absolute_output,
+            quiet,
+            upload,
+            force,
+            dereference,
+            verify,
+            no_resume,
+        }) => {
+            let res = directive::encrypt(
+                path,
+                output.to_owned(),
+                directive::EncryptOptions {
+                    allow_large: *allow_large,
+                    allow_absolute_output: *allow_absolute_output,
+                    quiet: *quiet,
+                    upload: *upload,
+                    force: *force,
+                    dereference: *dereference,
+                    verify: *verify,
+                    no_resume: *no_resume,
+                },
+            );
             println!("encrypt result: {:?}", res);
         }
 
         // Decryption
-        Some(Commands::Decrypt { path, output }) => {
-            directive::decrypt(path, output.to_owned());
+        Some(Commands::Decrypt {
+            path,
+            output,
+            preserve_permissions,
+            allow_absolute_output,
+        }) => {
+            directive::decrypt(
+                path,
+                output.to_owned(),
+                *preserve_permissions,
+                *allow_absolute_output,
+            );
         }
 
         // Cloud commands - upload | download | view for Google Drive and TODO: Dropbox
@@ -301,19 +657,39 @@ pub fn load_cli() {
             // Google
             Some(CloudCommand::Google { task }) => {
                 match task {
-                    Some(DriveCommand::Upload {}) => {
-                        let response = directive::google_upload();
-                        if let Err(e) = response {
+                    Some(DriveCommand::Upload {
+                        raw: Some(path), ..
+                    }) => {
+                        if let Err(e) = directive::google_upload_raw(path) {
                             println!("error: {}", e);
                         }
                     }
-                    Some(DriveCommand::Download { path }) => {
-                        let response = google_download(path);
+                    Some(DriveCommand::Upload {
+                        raw: None,
+                        remote_folder,
+                    }) => match directive::google_upload(remote_folder.as_deref()) {
+                        Ok(results) => print_upload_summary(&results),
+                        Err(e) => println!("error: {}", e),
+                    },
+                    Some(DriveCommand::Download {
+                        path,
+                        remote_folder,
+                    }) => {
+                        let response = google_download(path, remote_folder.as_deref());
                         if let Err(e) = response {
                             println!("error: {}", e);
                         }
                     }
                     Some(DriveCommand::View { path }) => _ = google_view(path),
+                    Some(DriveCommand::Link { path, drive_id }) => {
+                        if let Err(e) = directive::google_link(path, drive_id) {
+                            println!("error: {}", e);
+                        }
+                    }
+                    Some(DriveCommand::Quota) => match directive::google_quota() {
+                        Ok(quota) => print_quota(&quota),
+                        Err(e) => println!("error: {}", e),
+                    },
                     None => panic!("invalid input"),
                 };
             }
@@ -322,9 +698,15 @@ pub fn load_cli() {
             // TODO:
             Some(CloudCommand::Dropbox { task }) => {
                 match task {
-                    Some(DriveCommand::Upload {}) => dropbox_upload(""),
-                    Some(DriveCommand::Download { path }) => dropbox_download(path),
+                    Some(DriveCommand::Upload { .. }) => dropbox_upload(""),
+                    Some(DriveCommand::Download { path, .. }) => dropbox_download(path),
                     Some(DriveCommand::View { path }) => dropbox_view(path),
+                    Some(DriveCommand::Link { .. }) => {
+                        println!("linking is not yet supported for Dropbox")
+                    }
+                    Some(DriveCommand::Quota) => {
+                        println!("quota is not yet supported for Dropbox")
+                    }
                     None => panic!("invalid input"),
                 };
             }
@@ -344,8 +726,8 @@ pub fn load_cli() {
                     directive::config(path, ConfigTask::DatabasePath);
                 }
 
-                Some(ConfigCommand::CryptPath { path }) => {
-                    directive::config(path, ConfigTask::CryptPath);
+                Some(ConfigCommand::CryptPath { path, migrate }) => {
+                    directive::config(path, ConfigTask::CryptPath(*migrate));
                 }
 
                 // IgnoreItems
@@ -361,8 +743,136 @@ pub fn load_cli() {
 
                 // ZstdLevel
                 Some(ConfigCommand::ZstdLevel { level }) => {
-                    let level: i32 = level.parse().expect("Could not interpret passed value");
-                    directive::config("", ConfigTask::ZstdLevel(level));
+                    if level.eq_ignore_ascii_case("auto") {
+                        directive::config("", ConfigTask::AutoZstdLevel);
+                    } else {
+                        let level: i32 = level.parse().expect("Could not interpret passed value");
+                        directive::config("", ConfigTask::ZstdLevel(level));
+                    }
+                }
+
+                // MaxFileSize
+                Some(ConfigCommand::MaxFileSize { bytes }) => {
+                    let bytes: u64 = bytes.parse().expect("Could not interpret passed value");
+                    directive::config("", ConfigTask::MaxFileSize(bytes));
+                }
+
+                // HashParallelThreshold
+                Some(ConfigCommand::HashParallelThreshold { bytes }) => {
+                    let bytes: u64 = bytes.parse().expect("Could not interpret passed value");
+                    directive::config("", ConfigTask::HashParallelThreshold(bytes));
+                }
+
+                // TempPath
+                Some(ConfigCommand::TempPath { path }) => {
+                    directive::config(path, ConfigTask::TempPath);
+                }
+
+                // PrivateMetadata
+                Some(ConfigCommand::PrivateMetadata {
+                    enabled,
+                    password_stdin,
+                }) => {
+                    let enabled = match enabled.to_lowercase().as_str() {
+                        "on" | "true" | "1" => true,
+                        "off" | "false" | "0" => false,
+                        _ => panic!("invalid input, expected on/off"),
+                    };
+                    directive::config("", ConfigTask::PrivateMetadata(enabled, *password_stdin));
+                }
+
+                // CloudScope
+                Some(ConfigCommand::CloudScope { scope }) => {
+                    if scope.is_empty() {
+                        send_information(vec![format!(
+                            "Current cloud_scope: {}",
+                            config::get_config().get_cloud_scope()
+                        )]);
+                    } else {
+                        match scope.parse::<CloudScope>() {
+                            Ok(scope) => directive::config("", ConfigTask::CloudScope(scope)),
+                            Err(e) => send_information(vec![format!("{}", e)]),
+                        }
+                    }
+                }
+
+                // DecryptNaming
+                Some(ConfigCommand::DecryptNaming { mode }) => {
+                    if mode.is_empty() {
+                        send_information(vec![format!(
+                            "Current decrypt_naming: {}",
+                            config::get_config().get_decrypt_naming()
+                        )]);
+                    } else {
+                        match mode.parse::<DecryptNaming>() {
+                            Ok(naming) => directive::config("", ConfigTask::DecryptNaming(naming)),
+                            Err(e) => send_information(vec![format!("{}", e)]),
+                        }
+                    }
+                }
+
+                // KeyStore
+                Some(ConfigCommand::KeyStore { backend }) => {
+                    if backend.is_empty() {
+                        send_information(vec![format!(
+                            "Current key_store: {}",
+                            config::get_config().get_key_store()
+                        )]);
+                    } else {
+                        match backend.parse::<KeyStoreBackend>() {
+                            Ok(backend) => directive::config("", ConfigTask::KeyStore(backend)),
+                            Err(e) => send_information(vec![format!("{}", e)]),
+                        }
+                    }
+                }
+
+                // EncryptCollision
+                Some(ConfigCommand::EncryptCollision { policy }) => {
+                    if policy.is_empty() {
+                        send_information(vec![format!(
+                            "Current encrypt_collision: {}",
+                            config::get_config().get_encrypt_collision()
+                        )]);
+                    } else {
+                        match policy.parse::<EncryptCollision>() {
+                            Ok(policy) => directive::config("", ConfigTask::EncryptCollision(policy)),
+                            Err(e) => send_information(vec![format!("{}", e)]),
+                        }
+                    }
+                }
+
+                // VerifyOnEncrypt
+                Some(ConfigCommand::VerifyOnEncrypt { enabled }) => {
+                    if enabled.is_empty() {
+                        send_information(vec![format!(
+                            "Current verify_on_encrypt: {}",
+                            config::get_config().get_verify_on_encrypt()
+                        )]);
+                    } else {
+                        let enabled = match enabled.to_lowercase().as_str() {
+                            "on" | "true" | "1" => true,
+                            "off" | "false" | "0" => false,
+                            _ => panic!("invalid input, expected on/off"),
+                        };
+                        directive::config("", ConfigTask::VerifyOnEncrypt(enabled));
+                    }
+                }
+
+                // GenerateThumbnails
+                Some(ConfigCommand::GenerateThumbnails { enabled }) => {
+                    if enabled.is_empty() {
+                        send_information(vec![format!(
+                            "Current generate_thumbnails: {}",
+                            config::get_config().get_generate_thumbnails()
+                        )]);
+                    } else {
+                        let enabled = match enabled.to_lowercase().as_str() {
+                            "on" | "true" | "1" => true,
+                            "off" | "false" | "0" => false,
+                            _ => panic!("invalid input, expected on/off"),
+                        };
+                        directive::config("", ConfigTask::GenerateThumbnails(enabled));
+                    }
                 }
 
                 //Hwid
@@ -370,6 +880,38 @@ pub fn load_cli() {
                     send_information(vec![format!("machine name: {}", get_machine_name())]);
                 }
 
+                // TreeCharset
+                Some(ConfigCommand::TreeCharset { charset }) => {
+                    if charset.is_empty() {
+                        send_information(vec![format!(
+                            "Current tree_charset: {}",
+                            config::get_config().get_tree_charset()
+                        )]);
+                    } else {
+                        match charset.parse::<TreeCharset>() {
+                            Ok(charset) => directive::config("", ConfigTask::TreeCharset(charset)),
+                            Err(e) => send_information(vec![format!("{}", e)]),
+                        }
+                    }
+                }
+
+                // CloudWalkConcurrency
+                Some(ConfigCommand::CloudWalkConcurrency { limit }) => {
+                    if limit.is_empty() {
+                        send_information(vec![format!(
+                            "Current cloud_walk_concurrency: {}",
+                            config::get_config().get_cloud_walk_concurrency()
+                        )]);
+                    } else {
+                        match limit.parse::<usize>() {
+                            Ok(limit) => {
+                                directive::config("", ConfigTask::CloudWalkConcurrency(limit))
+                            }
+                            Err(e) => send_information(vec![format!("{}", e)]),
+                        }
+                    }
+                }
+
                 // LoadDefault
                 Some(ConfigCommand::LoadDefault) => {
                     directive::config("", ConfigTask::LoadDefault);
@@ -380,6 +922,132 @@ pub fn load_cli() {
             // let config = config::get_config();
             // println!("{}", config);
         }
+
+        // ExportBundle
+        Some(Commands::ExportBundle {
+            out_path,
+            passphrase,
+            password_stdin,
+        }) => {
+            let passphrase = passphrase
+                .clone()
+                .or_else(|| read_passphrase_non_interactive(*password_stdin));
+            if let Err(e) = directive::export_bundle(out_path, passphrase.as_deref()) {
+                println!("error: {}", e);
+            }
+        }
+
+        // ExportManifest
+        Some(Commands::ExportManifest { out_path, with_keys }) => {
+            if let Err(e) = directive::export_manifest(out_path, *with_keys) {
+                println!("error: {}", e);
+            }
+        }
+
+        // ImportBundle
+        Some(Commands::ImportBundle {
+            path,
+            target_dir,
+            passphrase,
+            password_stdin,
+        }) => {
+            let passphrase = passphrase
+                .clone()
+                .or_else(|| read_passphrase_non_interactive(*password_stdin));
+            if let Err(e) = directive::import_bundle(path, target_dir, passphrase.as_deref()) {
+                println!("error: {}", e);
+            }
+        }
+
+        // Selftest
+        Some(Commands::Selftest) => match self_test() {
+            Ok(report) => {
+                println!("{}", report);
+                if !report.all_passed() {
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                println!("error: {}", e);
+                std::process::exit(1);
+            }
+        },
+
+        // Compare
+        Some(Commands::Compare {
+            plaintext,
+            uuid_or_crypt,
+        }) => {
+            directive::compare(plaintext, uuid_or_crypt);
+        }
+
+        // Scan
+        Some(Commands::Scan { cloud, json }) => match directive::scan(*cloud) {
+            Ok(report) => {
+                if *json {
+                    match serde_json::to_string_pretty(&report) {
+                        Ok(rendered) => println!("{}", rendered),
+                        Err(e) => println!("error: {}", e),
+                    }
+                } else {
+                    println!("{}", report);
+                }
+                if report.corrupt_count() > 0 {
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                println!("error: {}", e);
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+/// Prints a per-file summary table after a `crypt cloud -g upload` run.
+fn print_upload_summary(results: &[UploadResult]) {
+    for result in results {
+        let status = match &result.outcome {
+            UploadOutcome::Uploaded(id) => format!("uploaded (drive id: {})", id),
+            UploadOutcome::Updated(id) => format!("updated (drive id: {})", id),
+            UploadOutcome::Skipped => "skipped".to_string(),
+            UploadOutcome::Failed(e) => format!("failed: {}", e),
+        };
+        println!("{}: {}", result.path.display(), status);
+    }
+}
+
+/// Renders a [`Quota`] as human-readable used/total, for the `cloud -g quota` command.
+fn print_quota(quota: &Quota) {
+    match quota.limit {
+        Some(limit) => println!(
+            "Drive storage: {} used of {} ({} used by Drive, {} free)",
+            human_bytes(quota.usage),
+            human_bytes(limit),
+            human_bytes(quota.usage_in_drive),
+            human_bytes(limit.saturating_sub(quota.usage)),
+        ),
+        None => println!(
+            "Drive storage: {} used ({} used by Drive) -- unlimited storage",
+            human_bytes(quota.usage),
+            human_bytes(quota.usage_in_drive),
+        ),
+    }
+}
+
+/// Formats a byte count as a human-readable size (e.g. `1.50 GB`).
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.2} {}", size, UNITS[unit])
     }
 }
 
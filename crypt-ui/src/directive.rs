@@ -8,28 +8,68 @@ use crate::{
 };
 use crypt_cloud::{
     crypt_core::{
+        bundle::{export_bundle as bundle_export, import_bundle as bundle_import},
         common::{
-            build_tree, chooser, get_crypt_folder, get_filenames_from_subdirectories,
+            self, build_tree, chooser, get_crypt_folder, get_filenames_from_subdirectories,
             get_full_file_path, send_information, verify_path, walk_crypt_folder, walk_directory,
         },
         config::{self, Config, ConfigTask, ItemsTask},
-        db::{self, delete_keeper, export_keeper, query_crypt, query_keeper_crypt},
-        filecrypt::{decrypt_contents, decrypt_file, encrypt_file, get_uuid_from_file},
+        db::{
+            self, delete_keeper, export_keeper, export_manifest as export_manifest_db,
+            migrate_crypt_path, query_crypt, query_keeper_crypt, query_keeper_token,
+        },
+        encryption::{compute_hash, derive_key_from_passphrase, suggest_zstd_level},
+        filecrypt::{
+            compare_to_stored_hash, decrypt_contents, decrypt_file, encrypt_file,
+            find_reused_nonces, get_file_info, get_uuid, get_uuid_from_file,
+            verify_encrypted_write, EncryptOutcome, FileCrypt,
+        },
         filetree::{
             tree::{dir_walk, is_not_hidden, sort_by_name, Directory},
             treeprint::print_tree,
         },
-        token::{purge_tokens, UserToken},
+        token::{purge_tokens, rotate_token_keys, UserToken},
     },
     drive,
 };
 use std::{
+    collections::{HashMap, HashSet},
     fs,
+    io::IsTerminal,
     path::{Path, PathBuf},
 };
 use tokio::runtime::Runtime;
 // #############################################################################################################################################
 
+/// Flags accepted by [`encrypt`] beyond `path`/`output`. Bundled into a struct
+/// (rather than more positional bools) so a new flag doesn't push the
+/// function past clippy's `too_many_arguments` threshold, and so a caller
+/// can't silently transpose e.g. `force` and `verify`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EncryptOptions {
+    /// Bypass the configured `max_file_size` guard.
+    pub allow_large: bool,
+    /// Allow `-o` to point outside the crypt folder via an absolute path.
+    pub allow_absolute_output: bool,
+    /// Suppress the byte-count progress reported while encrypting a directory.
+    pub quiet: bool,
+    /// Upload the resulting .crypt to Google Drive in the same command.
+    pub upload: bool,
+    /// Re-encrypt even if the tracked crypt's content hash is unchanged.
+    pub force: bool,
+    /// When encrypting a directory, encrypt every hardlinked path
+    /// independently instead of encrypting one and recording the rest to be
+    /// relinked on decrypt.
+    pub dereference: bool,
+    /// Decrypt the freshly-written .crypt back in memory and confirm the hash
+    /// matches before reporting success.
+    pub verify: bool,
+    /// Ignore any batch-resume state left by a previous interrupted run of
+    /// this directory and start fresh instead of skipping files already
+    /// marked done.
+    pub no_resume: bool,
+}
+
 ///Process the encryption directive
 ///
 /// # Example
@@ -42,13 +82,41 @@ use tokio::runtime::Runtime;
 /// directive.encrypt(in_place, output);
 ///```
 ///TODO: implement output
-pub fn encrypt(path: &str, output: Option<String>) -> Result<()> {
+pub fn encrypt(path: &str, output: Option<String>, opts: EncryptOptions) -> Result<()> {
+    let EncryptOptions {
+        allow_large,
+        allow_absolute_output,
+        quiet,
+        upload,
+        force,
+        dereference,
+        verify,
+        no_resume,
+    } = opts;
+
     // verify our path is pointing to a actual dir/file
     if !verify_path(&path) {
         send_information(vec![format!("could not find path: {}", path)]);
         return Ok(());
     }
 
+    // share a single Drive session across every file encrypted in this run, so a
+    // directory encrypt with --upload doesn't re-authenticate per file.
+    let google = if upload {
+        match Google::new(None) {
+            Ok(google) => Some(google),
+            Err(e) => {
+                send_information(vec![format!(
+                    "no cloud token configured ({}) -- encryption will continue, upload skipped",
+                    e
+                )]);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     // get the difference between the user's current working directory, and the path they passed in.
 
     let mut root = PathBuf::new();
@@ -58,22 +126,314 @@ pub fn encrypt(path: &str, output: Option<String>) -> Result<()> {
     match user_path.is_dir() {
         true => {
             if let Ok(directory) = walk_directory(path, false) {
+                // pre-pass: sum up the bytes we're about to encrypt so progress can be
+                // reported as a running total rather than just a file count.
+                let total_bytes: u64 = directory
+                    .iter()
+                    .filter(|p| p.is_file())
+                    .filter_map(|p| std::fs::metadata(p).ok())
+                    .map(|m| m.len())
+                    .sum();
+                let show_progress = !quiet && std::io::stdout().is_terminal();
+                let mut bytes_done: u64 = 0;
+
+                // group files that share an inode so a hardlinked file only gets
+                // encrypted once; the rest are recorded on the primary's `hardlinks`
+                // field so decrypt can recreate them. --dereference disables this and
+                // encrypts every path independently, as before.
+                let hardlinks_for = if dereference {
+                    HashMap::new()
+                } else {
+                    group_hardlinked_files(&directory)
+                };
+                let duplicate_paths: HashSet<&PathBuf> =
+                    hardlinks_for.values().flatten().collect();
+
+                let batch_root = Path::new(path);
+                let mut resume_state = if no_resume {
+                    clear_resume_state(batch_root);
+                    EncryptResumeState::default()
+                } else {
+                    load_resume_state(batch_root)
+                };
+                if !resume_state.completed.is_empty() {
+                    let total_files = directory.iter().filter(|p| p.is_file()).count();
+                    send_information(vec![format!(
+                        "resuming, {}/{} already done",
+                        resume_state.completed.len(),
+                        total_files
+                    )]);
+                }
+
                 for path in directory {
                     if path.is_dir() {
                         root.push(path.file_name().unwrap());
                     } else if path.is_file() {
-                        encrypt_file(path.to_str().unwrap(), &Some(root.display().to_string()));
+                        if duplicate_paths.contains(&path) {
+                            if show_progress {
+                                send_information(vec![format!(
+                                    "skipping {} (hardlinked, recorded alongside its primary)",
+                                    path.display()
+                                )]);
+                            }
+                            continue;
+                        }
+                        let file_size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                        let path_key = path.display().to_string();
+                        let content_hash = fs::read(&path).ok().map(|c| compute_hash(&c));
+                        if let Some(hash) = content_hash {
+                            if resume_state.completed.get(&path_key) == Some(&hash) {
+                                bytes_done += file_size;
+                                if show_progress {
+                                    send_information(vec![format!(
+                                        "resuming: already done {}",
+                                        path.display()
+                                    )]);
+                                }
+                                continue;
+                            }
+                        }
+                        let file_output = Some(root.display().to_string());
+                        match encrypt_file(
+                            path.to_str().unwrap(),
+                            &file_output,
+                            allow_large,
+                            allow_absolute_output,
+                            force,
+                            verify,
+                        ) {
+                            Ok(EncryptOutcome::Unchanged) => {
+                                bytes_done += file_size;
+                                if show_progress {
+                                    send_information(vec![format!(
+                                        "unchanged, skipping {}",
+                                        path.display()
+                                    )]);
+                                }
+                                if let Some(hash) = content_hash {
+                                    resume_state.completed.insert(path_key, hash);
+                                    save_resume_state(batch_root, &resume_state);
+                                }
+                            }
+                            Ok(EncryptOutcome::Skipped) => {
+                                bytes_done += file_size;
+                                send_information(vec![format!(
+                                    "skipping {}: a different file already occupies its .crypt slot (encrypt_collision = skip)",
+                                    path.display()
+                                )]);
+                            }
+                            Ok(_) => {
+                                bytes_done += file_size;
+                                if show_progress {
+                                    send_information(vec![format!(
+                                        "encrypted {} / {} bytes",
+                                        bytes_done, total_bytes
+                                    )]);
+                                }
+                                if let Some(links) = hardlinks_for.get(&path) {
+                                    record_hardlinks(
+                                        path.to_str().unwrap(),
+                                        &file_output,
+                                        allow_absolute_output,
+                                        links,
+                                    );
+                                }
+                                if let Some(google) = &google {
+                                    upload_freshly_encrypted(
+                                        google,
+                                        path.to_str().unwrap(),
+                                        &file_output,
+                                        allow_absolute_output,
+                                    );
+                                }
+                                if let Some(hash) = content_hash {
+                                    resume_state.completed.insert(path_key, hash);
+                                    save_resume_state(batch_root, &resume_state);
+                                }
+                            }
+                            Err(e) => send_information(vec![format!(
+                                "skipping {}: {}",
+                                path.display(),
+                                e
+                            )]),
+                        }
+                    }
+                }
+                clear_resume_state(batch_root);
+            }
+        }
+        false => {
+            let outcome =
+                encrypt_file(path, &output, allow_large, allow_absolute_output, force, verify)?;
+            match outcome {
+                EncryptOutcome::Unchanged => {
+                    send_information(vec![format!("unchanged, skipping {}", path)]);
+                }
+                EncryptOutcome::Skipped => {
+                    send_information(vec![format!(
+                        "skipping {}: a different file already occupies its .crypt slot (encrypt_collision = skip)",
+                        path
+                    )]);
+                }
+                _ => {
+                    if let Some(google) = &google {
+                        upload_freshly_encrypted(google, path, &output, allow_absolute_output);
                     }
                 }
             }
         }
-        false => encrypt_file(path, &output),
     }
     Ok(())
 }
 
+/// Computes the `.crypt` path `encrypt_file` writes for `path`/`output`, mirroring
+/// its own path-construction logic so callers don't have to re-derive it.
+fn crypt_output_path(path: &str, output: &Option<String>, allow_absolute_output: bool) -> Result<PathBuf> {
+    let (full_path, _, filename, _) = get_file_info(path);
+    let crypt_folder = get_crypt_folder();
+    let dir = match output {
+        Some(o) => common::resolve_within_root(&crypt_folder, o, allow_absolute_output)?,
+        None => crypt_folder,
+    };
+    // a collision may have put this file's crypt at `<filename>(n).crypt`, so
+    // walk the candidates until the one carrying this file's uuid (or a free slot)
+    let uuid = db::query_keeper_for_existing_file(full_path)?.uuid;
+    let mut candidate = dir.join(format!("{}.crypt", filename));
+    let mut counter = 1;
+    while candidate.exists() && get_uuid_from_file(&candidate).ok().as_deref() != Some(uuid.as_str()) {
+        candidate = dir.join(format!("{}({}).crypt", filename, counter));
+        counter += 1;
+    }
+    Ok(candidate)
+}
+
+/// Uploads the `.crypt` that `encrypt_file` just wrote for `path`/`output`,
+/// reusing the already-authenticated `google` session. Reports failures via
+/// [`send_information`] rather than aborting the encrypt that already succeeded.
+fn upload_freshly_encrypted(
+    google: &Google,
+    path: &str,
+    output: &Option<String>,
+    allow_absolute_output: bool,
+) {
+    let result = (|| -> Result<String> {
+        let crypt_path = crypt_output_path(path, output, allow_absolute_output)?;
+
+        let mut fc = get_uuid_from_file(&crypt_path).and_then(db::query_crypt)?;
+        fc.drive_id = google.runtime.block_on(drive::g_upload(
+            &google.token,
+            &crypt_path.display().to_string(),
+            &google.cloud_root_folder,
+        ))?;
+        db::insert_crypt(&fc)?;
+        Ok(fc.drive_id)
+    })();
+
+    match result {
+        Ok(drive_id) => send_information(vec![format!("uploaded {} (drive id: {})", path, drive_id)]),
+        Err(e) => send_information(vec![format!("encrypted {} but upload failed: {}", path, e)]),
+    }
+}
+
+/// Name of the batch-resume state file [`encrypt`] leaves inside a directory
+/// it's encrypting, so a run interrupted partway through can pick up where it
+/// left off instead of re-processing files it already finished. Lives inside
+/// the directory being encrypted, like `.cryptignore`, so it's implicitly
+/// scoped to that batch and excluded from the walk by
+/// [`common::walk_directory`]'s dotfile filtering.
+const ENCRYPT_RESUME_FILENAME: &str = ".crypt-resume.json";
+
+/// Tracks which source files a directory encrypt has already finished,
+/// keyed by path (as walked) to the content hash it was encrypted with, so a
+/// file modified since it was marked done still gets re-encrypted on resume.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct EncryptResumeState {
+    completed: HashMap<String, [u8; 32]>,
+}
+
+fn resume_state_path(root: &Path) -> PathBuf {
+    root.join(ENCRYPT_RESUME_FILENAME)
+}
+
+/// Loads the resume state left in `root`, if any. A missing or unreadable
+/// file is treated the same as "nothing done yet" rather than an error.
+fn load_resume_state(root: &Path) -> EncryptResumeState {
+    fs::read_to_string(resume_state_path(root))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Persists `state` after every completed file, so a run killed at any point
+/// leaves an up-to-date record rather than losing progress since the last save.
+fn save_resume_state(root: &Path, state: &EncryptResumeState) {
+    if let Ok(json) = serde_json::to_string(state) {
+        _ = fs::write(resume_state_path(root), json);
+    }
+}
+
+fn clear_resume_state(root: &Path) {
+    _ = fs::remove_file(resume_state_path(root));
+}
+
+/// Returns `(dev, ino)` for `path`, or `None` on platforms without Unix inodes
+/// (or if the file can't be stat'd).
+#[cfg(unix)]
+fn inode_key(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().map(|m| (m.dev(), m.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode_key(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+/// Groups `paths` (a directory walk's entries) by shared inode. For each group
+/// larger than one, the first path encountered is the "primary" (the one that
+/// will actually be encrypted); the map's value lists the rest, to be recorded
+/// on the primary's `FileCrypt::hardlinks` and skipped during encryption.
+fn group_hardlinked_files(paths: &[PathBuf]) -> HashMap<PathBuf, Vec<PathBuf>> {
+    let mut primary_of: HashMap<(u64, u64), PathBuf> = HashMap::new();
+    let mut hardlinks_for: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+
+    for path in paths.iter().filter(|p| p.is_file()) {
+        let Some(key) = inode_key(path) else { continue };
+        match primary_of.get(&key) {
+            Some(primary) => hardlinks_for.entry(primary.clone()).or_default().push(path.clone()),
+            None => {
+                primary_of.insert(key, path.clone());
+            }
+        }
+    }
+
+    hardlinks_for
+}
+
+/// Records `links` on the `.crypt` that `encrypt_file` just wrote for `path`/`output`,
+/// so decrypt can recreate them as hardlinks. Failures are reported via
+/// [`send_information`] rather than aborting the encrypt that already succeeded.
+fn record_hardlinks(path: &str, output: &Option<String>, allow_absolute_output: bool, links: &[PathBuf]) {
+    let result = (|| -> Result<()> {
+        let crypt_path = crypt_output_path(path, output, allow_absolute_output)?;
+        let mut fc = get_uuid_from_file(&crypt_path).and_then(db::query_crypt)?;
+        fc.set_hardlinks(links);
+        db::insert_crypt(&fc)?;
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        send_information(vec![format!("encrypted {} but failed to record hardlinks: {}", path, e)]);
+    }
+}
+
 ///Process the decryption directive
-pub fn decrypt(path: &str, output: Option<String>) {
+pub fn decrypt(
+    path: &str,
+    output: Option<String>,
+    preserve_permissions: bool,
+    allow_absolute_output: bool,
+) {
     let mut crypt_folder = get_crypt_folder();
     crypt_folder.push(path);
 
@@ -93,7 +453,12 @@ pub fn decrypt(path: &str, output: Option<String>) {
                         root.push(p.file_name().unwrap());
                     } else if p.is_file() {
                         send_information(vec![format!("Decrypting file: {}", p.display())]);
-                        let _res = decrypt_file(p, root.display().to_string());
+                        let _res = decrypt_file(
+                            p,
+                            root.display().to_string(),
+                            preserve_permissions,
+                            allow_absolute_output,
+                        );
                     }
                 }
             }
@@ -102,9 +467,14 @@ pub fn decrypt(path: &str, output: Option<String>) {
         false => {
             let res;
             if let Some(o) = output {
-                res = decrypt_file(path, o);
+                res = decrypt_file(path, o, preserve_permissions, allow_absolute_output);
             } else {
-                res = decrypt_file(path, "".to_string());
+                res = decrypt_file(
+                    path,
+                    "".to_string(),
+                    preserve_permissions,
+                    allow_absolute_output,
+                );
             }
             println!("decrypt result: {:?}", res);
         }
@@ -121,16 +491,27 @@ pub struct Google {
 }
 
 impl Google {
-    /// Creates a new [`Google`].
-    fn new() -> Result<Self> {
+    /// Creates a new [`Google`]. If `remote_folder` is given, `cloud_root_folder`
+    /// resolves (creating as needed) to that nested path under the root `Crypt`
+    /// folder instead of the root itself, so every operation built on top of
+    /// `cloud_root_folder` is automatically scoped to it.
+    fn new(remote_folder: Option<&str>) -> Result<Self> {
         let runtime = Runtime::new()?;
 
         let token = UserToken::new_google();
 
         // Access google drive and ensure a crypt folder exists, create if doesn't
-        let cloud_root_folder: String =
+        let mut cloud_root_folder: String =
             runtime.block_on(drive::g_create_folder(&token, None, ""))?;
 
+        if let Some(remote_folder) = remote_folder {
+            cloud_root_folder = runtime.block_on(drive::g_create_folder_path(
+                &token,
+                &cloud_root_folder,
+                Path::new(remote_folder),
+            ))?;
+        }
+
         return Ok(Self {
             runtime,
             token,
@@ -141,7 +522,87 @@ impl Google {
 
 // ############################################ Cloud Upload ############################################
 
-pub fn google_upload() -> Result<()> {
+/// What happened to a single file during a [`google_upload`] run.
+#[derive(Debug)]
+pub enum UploadOutcome {
+    /// File had no drive id on record; uploaded fresh, new drive id returned.
+    Uploaded(String),
+    /// File already existed on the drive; contents were updated in place.
+    Updated(String),
+    /// File was not eligible for upload (e.g. no "crypt" folder in its path).
+    Skipped,
+    /// Upload/update failed for this file specifically.
+    Failed(Error),
+}
+
+/// Per-file result of a [`google_upload`] run, so callers can report precisely
+/// what happened instead of inferring it from side effects.
+#[derive(Debug)]
+pub struct UploadResult {
+    pub path: PathBuf,
+    pub outcome: UploadOutcome,
+}
+
+/// Precheck for [`google_upload`]: aborts with [`error::UploadError::QuotaExceeded`]
+/// if the upload's total size (`path`'s size when it's a file; otherwise every
+/// file in the crypt folder, which is what [`google_upload`] uploads in that
+/// case) wouldn't fit in the account's remaining Drive quota. Silently proceeds if the quota can't be
+/// fetched or the account has unlimited storage, so a flaky `about` call never
+/// blocks an upload that would otherwise succeed.
+fn check_quota_for_upload(google: &Google, path: &Path) -> Result<()> {
+    let total_size: u64 = if path.is_file() {
+        std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+    } else {
+        walk_crypt_folder()
+            .map(|(files, _)| {
+                files
+                    .iter()
+                    .filter_map(|f| std::fs::metadata(f).ok())
+                    .map(|m| m.len())
+                    .sum()
+            })
+            .unwrap_or(0)
+    };
+
+    let Ok(quota) = google.runtime.block_on(drive::g_quota(&google.token)) else {
+        return Ok(());
+    };
+    let Some(limit) = quota.limit else {
+        return Ok(());
+    };
+
+    let available = limit.saturating_sub(quota.usage);
+    if total_size > available {
+        send_information(vec![format!(
+            "WARNING: this upload needs {} bytes but only {} bytes are free in Drive -- aborting.",
+            total_size, available
+        )]);
+        return Err(Error::UploadError(error::UploadError::QuotaExceeded(
+            total_size, available,
+        )));
+    }
+
+    Ok(())
+}
+
+/// Fetches the account's Drive storage quota, for the `cloud -g quota` command.
+pub fn google_quota() -> Result<drive::Quota> {
+    let google = Google::new(None)?;
+    Ok(google.runtime.block_on(drive::g_quota(&google.token))?)
+}
+
+/// Uploads to the root `Crypt` folder, or -- if `remote_folder` is given --
+/// to that nested path under it (created if it doesn't already exist).
+pub fn google_upload(remote_folder: Option<&str>) -> Result<Vec<UploadResult>> {
+    if !config::get_config().get_cloud_scope().supports_upload() {
+        send_information(vec![format!(
+            "WARNING: cloud_scope is set to \"{}\", which is read-only -- this upload will \
+             likely fail. Run `crypt config -s full` or `crypt config -s file` and generate \
+             a new token to allow uploads.",
+            config::get_config().get_cloud_scope()
+        )]);
+    }
+
     let user_result = chooser("").unwrap_or_default();
 
     // user aborted | no files in crypt
@@ -149,38 +610,51 @@ pub fn google_upload() -> Result<()> {
         return Err(Error::UploadError(error::UploadError::UserAbortedError));
     }
 
-    let google = Google::new()?;
+    let google = Google::new(remote_folder)?;
+    let mut results: Vec<UploadResult> = Vec::new();
+
+    check_quota_for_upload(&google, &user_result)?;
 
     // determine if path picked is a file or path
     if user_result.is_file() {
-        // 1. get crypt info from pathbuf
-        let mut fc = get_uuid_from_file(user_result.clone()).and_then(db::query_crypt)?;
-
-        // 2. upload file to cloud, saving drive id to crypt
-        fc.drive_id = google.runtime.block_on(drive::g_upload(
-            &google.token,
-            &user_result.display().to_string(),
-            &google.cloud_root_folder,
-        ))?;
-
-        // 3. update database.
-        db::insert_crypt(&fc)?;
+        // 1. get crypt info from pathbuf, 2. upload file to cloud, 3. update database.
+        let outcome = (|| -> Result<UploadOutcome> {
+            let mut fc = get_uuid_from_file(user_result.clone()).and_then(db::query_crypt)?;
+            fc.drive_id = google.runtime.block_on(drive::g_upload(
+                &google.token,
+                &user_result.display().to_string(),
+                &google.cloud_root_folder,
+            ))?;
+            db::insert_crypt(&fc)?;
+            Ok(UploadOutcome::Uploaded(fc.drive_id))
+        })()
+        .unwrap_or_else(UploadOutcome::Failed);
+        results.push(UploadResult {
+            path: user_result,
+            outcome,
+        });
 
         // 4. show cloud directory
         let cloud_directory = google
             .runtime
-            .block_on(drive::g_walk(&google.token, "Crypt"))
+            .block_on(drive::g_walk_by_id(&google.token, &google.cloud_root_folder))
             .expect("Could not view directory information");
         send_information(build_tree(&cloud_directory));
     } else {
         // get all our file paths from folder
         let (files, _) = walk_crypt_folder()?;
 
-        for file in files {
+        'files: for file in files {
             // get FileCrypt information from keeper
-            let mut fc = match get_uuid_from_file(file.as_path()) {
-                Ok(uuid) => db::query_crypt(uuid).unwrap(),
-                Err(_) => continue,
+            let mut fc = match get_uuid_from_file(file.as_path()).and_then(db::query_crypt) {
+                Ok(fc) => fc,
+                Err(e) => {
+                    results.push(UploadResult {
+                        path: file,
+                        outcome: UploadOutcome::Failed(e.into()),
+                    });
+                    continue;
+                }
             };
 
             // check if we have a drive id in the filecrypt & if it exists in google drive
@@ -202,60 +676,189 @@ pub fn google_upload() -> Result<()> {
                     .unwrap_or_else(|_| "".to_string());
 
                 if !fc.drive_id.is_empty() {
+                    results.push(UploadResult {
+                        path: file,
+                        outcome: UploadOutcome::Updated(fc.drive_id),
+                    });
                     continue;
                 }
             }
 
             // Find the position of "crypt" in the path
-            if let Some(index) = file.iter().position(|component| component == "crypt") {
-                // Collect the components after "crypt"
-                let remaining_components: Vec<_> = file.iter().skip(index + 1).collect();
+            let Some(index) = file.iter().position(|component| component == "crypt") else {
+                results.push(UploadResult {
+                    path: file,
+                    outcome: UploadOutcome::Skipped,
+                });
+                continue;
+            };
 
-                // Check if there are remaining components
-                if remaining_components.is_empty() {
-                    continue;
-                }
+            // Collect the components after "crypt"
+            let remaining_components: Vec<_> = file.iter().skip(index + 1).collect();
 
-                // our parent directory ID
-                let mut parent: String = google.cloud_root_folder.clone();
+            // Check if there are remaining components
+            if remaining_components.is_empty() {
+                results.push(UploadResult {
+                    path: file,
+                    outcome: UploadOutcome::Skipped,
+                });
+                continue;
+            }
 
-                // our current directory ID
-                let mut current: String = String::new();
+            // our parent directory ID
+            let mut parent: String = google.cloud_root_folder.clone();
 
-                // length of remaining components
-                let len = remaining_components.len() - 1;
+            // our current directory ID
+            let mut current: String = String::new();
 
-                // Iterate over each remaining component
-                for (num, component) in remaining_components.iter().enumerate() {
-                    if num != len {
-                        current = google.runtime.block_on(drive::g_create_folder(
-                            &google.token,
-                            Some(&PathBuf::from(component)),
-                            &parent,
-                        ))?;
-                        parent = current.clone();
-                    } else {
-                        current = google.runtime.block_on(drive::g_upload(
-                            &google.token,
-                            file.to_str().unwrap(),
-                            &current,
-                        ))?;
-                        fc.drive_id = current.clone();
-                    }
+            // length of remaining components
+            let len = remaining_components.len() - 1;
+
+            // Iterate over each remaining component
+            for (num, component) in remaining_components.iter().enumerate() {
+                if num != len {
+                    current = match google.runtime.block_on(drive::g_create_folder(
+                        &google.token,
+                        Some(&PathBuf::from(component)),
+                        &parent,
+                    )) {
+                        Ok(id) => id,
+                        Err(e) => {
+                            results.push(UploadResult {
+                                path: file,
+                                outcome: UploadOutcome::Failed(e.into()),
+                            });
+                            continue 'files;
+                        }
+                    };
+                    parent = current.clone();
+                } else {
+                    current = match google.runtime.block_on(drive::g_upload(
+                        &google.token,
+                        file.to_str().unwrap(),
+                        &current,
+                    )) {
+                        Ok(id) => id,
+                        Err(e) => {
+                            results.push(UploadResult {
+                                path: file,
+                                outcome: UploadOutcome::Failed(e.into()),
+                            });
+                            continue 'files;
+                        }
+                    };
+                    fc.drive_id = current.clone();
                 }
-                // 3. update database.
-                db::insert_crypt(&fc)?;
             }
+            // 3. update database.
+            let outcome = match db::insert_crypt(&fc) {
+                Ok(_) => UploadOutcome::Uploaded(fc.drive_id),
+                Err(e) => UploadOutcome::Failed(e.into()),
+            };
+            results.push(UploadResult {
+                path: file,
+                outcome,
+            });
         }
     }
 
+    Ok(results)
+}
+
+/// Uploads `path` to the cloud Crypt folder as-is, with no encryption, no
+/// `FileCrypt`, and no keeper DB row -- for backing up already-encrypted or
+/// non-sensitive files without running them through ByteCrypt's encryption.
+pub fn google_upload_raw(path: &str) -> Result<()> {
+    if !verify_path(&path) {
+        send_information(vec![format!("could not find path: {}", path)]);
+        return Ok(());
+    }
+
+    let google = Google::new(None)?;
+    let drive_id = google.runtime.block_on(drive::g_upload_raw(
+        &google.token,
+        path,
+        &google.cloud_root_folder,
+    ))?;
+
+    send_information(vec![format!(
+        "uploaded {} as a raw file (drive id: {}) -- not tracked in the keeper database",
+        path, drive_id
+    )]);
+    Ok(())
+}
+
+/// Re-associates a local crypt with an existing cloud file, for when the
+/// database has lost a `drive_id` for a file that's still sitting in Drive.
+///
+/// Verifies via `g_id_matches_name` that `drive_id` actually points to
+/// a file matching this crypt before writing it, so we don't accidentally
+/// link to an unrelated file. On success, later `google_upload` runs will
+/// `g_update` this file instead of uploading a duplicate.
+pub fn google_link(file: &str, drive_id: &str) -> Result<()> {
+    let fc = get_uuid_from_file(PathBuf::from(file)).and_then(db::query_crypt)?;
+
+    let google = Google::new(None)?;
+    let expected_name = format!("{}{}", fc.filename, fc.ext);
+    let matches = google.runtime.block_on(drive::g_id_matches_name(
+        &google.token,
+        drive_id,
+        &expected_name,
+    ))?;
+
+    if !matches {
+        return Err(Error::UploadError(error::UploadError::LinkMismatch(
+            drive_id.to_string(),
+            expected_name,
+        )));
+    }
+
+    db::update_drive_id(&fc.uuid, drive_id)?;
+    send_information(vec![format!(
+        "linked {} to existing drive file {}",
+        file, drive_id
+    )]);
+
     Ok(())
 }
 
 // ############################################ Cloud Download ############################################
 
-pub fn google_download(path: &str) -> Result<()> {
-    let google = Google::new()?;
+/// Fails with a typed error rather than letting a stale/trashed `drive_id`
+/// silently produce a zero-byte "decrypted" file.
+fn ensure_non_empty(bytes: Vec<u8>, drive_id: &str) -> Result<Vec<u8>> {
+    if bytes.is_empty() {
+        return Err(Error::DownloadError(error::DownloadError::EmptyResponse(
+            drive_id.to_string(),
+        )));
+    }
+    Ok(bytes)
+}
+
+/// Checks a freshly downloaded `.crypt.partial` before handing it to
+/// [`decrypt_contents`]: non-empty, and its leading uuid matches the
+/// `FileCrypt` we expected -- catching a corrupt/mismatched download before
+/// wasting CPU on a decrypt attempt that would only fail the hash check anyway.
+fn verify_downloaded_crypt(bytes: Vec<u8>, fc: &FileCrypt) -> Result<Vec<u8>> {
+    let bytes = ensure_non_empty(bytes, &fc.drive_id)?;
+    let (uuid, _) = get_uuid(&bytes)?;
+    if uuid != fc.uuid {
+        return Err(Error::DownloadError(error::DownloadError::CorruptDownload(
+            fc.drive_id.clone(),
+            fc.uuid.clone(),
+            uuid,
+        )));
+    }
+    Ok(bytes)
+}
+
+/// Downloads a file previously uploaded to Drive. `remote_folder` is passed
+/// through to [`Google::new`] so the folder is resolved/created the same way
+/// `google_upload` would, but the download itself still locates the file by
+/// the `drive_id` already recorded in the local keeper database rather than
+/// by walking `remote_folder` -- so it has no effect beyond that.
+pub fn google_download(path: &str, remote_folder: Option<&str>) -> Result<()> {
+    let google = Google::new(remote_folder)?;
 
     let crypt_folder = get_crypt_folder();
     let (_files, _) = get_filenames_from_subdirectories(crypt_folder)?;
@@ -275,23 +878,28 @@ pub fn google_download(path: &str) -> Result<()> {
         // if we download the file, and check uuid.
         // thought about having user select, but based off what? filename, the "fullpath" we have in the db?
 
-        // step 2: get drive id and query file, retreve contents
+        // Step 2: stream the drive file to a `.crypt.partial` temp in the
+        // configured temp directory (see `config::Config::temp_path`) --
+        // resumable (see `drive::download_to_file`), so an interrupted
+        // download resumes from the last byte received on the next attempt
+        // instead of restarting. `TempFileGuard` removes it once it goes out
+        // of scope, even if we bail out early with `?`.
+        let tmp_download = common::TempFileGuard::new(&format!("{}.crypt.partial", fc.uuid))?;
 
-        let bytes = google
-            .runtime
-            .block_on(drive::google_query_file(&google.token, &fc.drive_id))
-            .unwrap_or(vec![]);
+        google.runtime.block_on(drive::download_to_file(
+            &google.token,
+            &fc.drive_id,
+            tmp_download.path(),
+        ))?;
 
-        // TODO: if something went wrong, what do?
-        if bytes.is_empty() {
-            send_information(vec![format!(
-                "Failed to get contents of cloud file. Please try again."
-            )]);
-            std::process::exit(2);
-        }
+        // Step 3: verify the download before decrypting, so a truncated/corrupt
+        // temp is caught here rather than surfacing as a confusing hash mismatch
+        // after decompression.
+        let bytes = std::fs::read(tmp_download.path())?;
+        let bytes = verify_downloaded_crypt(bytes, &fc)?;
 
-        // Step 2.5: unzip / decrypt contents / write to file.
-        decrypt_contents(fc, bytes)?;
+        // Step 4: unzip / decrypt contents / write to file.
+        decrypt_contents(fc, bytes, cfg!(unix))?;
     }
     // otherwise we assume it is a folder
     else {
@@ -308,7 +916,7 @@ pub fn google_download(path: &str) -> Result<()> {
 // ############################################ Cloud View ############################################
 
 pub fn google_view(path: &str) -> Result<()> {
-    let google = Google::new()?;
+    let google = Google::new(None)?;
 
     let cloud_directory = google
         .runtime
@@ -372,7 +980,7 @@ pub fn config(path: &str, config_task: ConfigTask) {
             }
         },
 
-        ConfigTask::CryptPath => {
+        ConfigTask::CryptPath(migrate) => {
             match path.to_lowercase().as_str() {
                 "" => {
                     let path = get_full_file_path(&config.crypt_path);
@@ -395,7 +1003,26 @@ pub fn config(path: &str, config_task: ConfigTask) {
 
                     if s.as_str() == "y" {
                         if PathBuf::from(path).exists() {
-                            config.set_crypt_path(path);
+                            if migrate {
+                                let old_path = get_full_file_path(&config.crypt_path);
+                                let new_path = get_full_file_path(path);
+                                match migrate_crypt_path(&old_path, &new_path) {
+                                    Ok(count) => {
+                                        send_information(vec![format!(
+                                            "moved {} crypt file(s) to {}",
+                                            count,
+                                            new_path.display()
+                                        )]);
+                                        config.set_crypt_path(path);
+                                    }
+                                    Err(e) => send_information(vec![format!(
+                                        "migration failed, crypt path left unchanged: {}",
+                                        e
+                                    )]),
+                                }
+                            } else {
+                                config.set_crypt_path(path);
+                            }
                         } else {
                             //TODO: create path
                         }
@@ -418,6 +1045,158 @@ pub fn config(path: &str, config_task: ConfigTask) {
             false => send_information(vec![format!("Error occured, please verify parameters")]),
         },
 
+        ConfigTask::AutoZstdLevel => {
+            let sample = get_filenames_from_subdirectories(get_crypt_folder())
+                .ok()
+                .and_then(|(files, _)| files.into_iter().find_map(|f| fs::read(f).ok()));
+
+            match sample {
+                Some(sample) => {
+                    let level = suggest_zstd_level(&sample, std::time::Duration::from_secs(2));
+                    config.set_zstd_level(level);
+                    send_information(vec![format!(
+                        "benchmarked sample, zstd_level value changed to: {}",
+                        level
+                    )]);
+                }
+                None => send_information(vec![format!(
+                    "no files found in crypt folder to benchmark -- add files with `crypt config -c` \
+                     or set the level manually with `crypt config -z <level>`"
+                )]),
+            }
+        }
+
+        ConfigTask::MaxFileSize(bytes) => {
+            config.set_max_file_size(bytes);
+            send_information(vec![format!("max_file_size value changed to: {}", bytes)]);
+        }
+
+        ConfigTask::PrivateMetadata(enabled, password_stdin) => {
+            if enabled == config.get_private_metadata() {
+                send_information(vec![format!("private_metadata is already {}", enabled)]);
+            } else if enabled {
+                let passphrase = match db::read_passphrase_non_interactive(password_stdin) {
+                    Some(passphrase) => passphrase,
+                    None => {
+                        send_information(vec![format!(
+                            "enabling private_metadata -- enter a passphrase to encrypt filenames/paths with:"
+                        )]);
+                        let mut passphrase = String::new();
+                        std::io::stdin()
+                            .read_line(&mut passphrase)
+                            .expect("Did not enter a correct string");
+                        passphrase.trim().to_string()
+                    }
+                };
+                let key = derive_key_from_passphrase(&passphrase);
+
+                match db::migrate_metadata_encryption(Some(key)) {
+                    Ok(count) => {
+                        db::set_metadata_key(&passphrase);
+                        config.set_private_metadata(true);
+                        send_information(vec![format!(
+                            "encrypted metadata for {} existing crypt(s)",
+                            count
+                        )]);
+                    }
+                    Err(e) => {
+                        send_information(vec![format!("failed to migrate metadata: {}", e)])
+                    }
+                }
+            } else {
+                match db::migrate_metadata_encryption(None) {
+                    Ok(count) => {
+                        db::clear_metadata_key();
+                        config.set_private_metadata(false);
+                        send_information(vec![format!(
+                            "decrypted metadata for {} existing crypt(s)",
+                            count
+                        )]);
+                    }
+                    Err(e) => {
+                        send_information(vec![format!("failed to migrate metadata: {}", e)])
+                    }
+                }
+            }
+        }
+
+        ConfigTask::CloudScope(scope) => {
+            config.set_cloud_scope(scope);
+            send_information(vec![format!(
+                "cloud_scope value changed to: {} (a new token will need to be generated for this to take effect)",
+                scope
+            )]);
+        }
+
+        ConfigTask::DecryptNaming(naming) => {
+            config.set_decrypt_naming(naming);
+            send_information(vec![format!("decrypt_naming value changed to: {}", naming)]);
+        }
+
+        ConfigTask::KeyStore(backend) => {
+            config.set_key_store(backend);
+            send_information(vec![format!("key_store value changed to: {}", backend)]);
+        }
+
+        ConfigTask::HashParallelThreshold(bytes) => {
+            config.set_hash_parallel_threshold(bytes);
+            send_information(vec![format!(
+                "hash_parallel_threshold value changed to: {}",
+                bytes
+            )]);
+        }
+
+        ConfigTask::TempPath => match path.to_lowercase().as_str() {
+            "" => {
+                send_information(vec![format!(
+                    "Current temp Path:\n  {}",
+                    common::get_temp_dir().display()
+                )]);
+            }
+            "default" => {
+                config.set_temp_path(None);
+                send_information(vec![format!("temp_path reset to system default")]);
+            }
+            _ => {
+                config.set_temp_path(Some(path.to_string()));
+                send_information(vec![format!("temp_path value changed to: {}", path)]);
+            }
+        },
+
+        ConfigTask::EncryptCollision(policy) => {
+            config.set_encrypt_collision(policy);
+            send_information(vec![format!(
+                "encrypt_collision value changed to: {}",
+                policy
+            )]);
+        }
+
+        ConfigTask::VerifyOnEncrypt(enabled) => {
+            config.set_verify_on_encrypt(enabled);
+            send_information(vec![format!("verify_on_encrypt value changed to: {}", enabled)]);
+        }
+
+        ConfigTask::GenerateThumbnails(enabled) => {
+            config.set_generate_thumbnails(enabled);
+            send_information(vec![format!(
+                "generate_thumbnails value changed to: {}",
+                enabled
+            )]);
+        }
+
+        ConfigTask::TreeCharset(charset) => {
+            config.set_tree_charset(charset);
+            send_information(vec![format!("tree_charset value changed to: {}", charset)]);
+        }
+
+        ConfigTask::CloudWalkConcurrency(limit) => {
+            config.set_cloud_walk_concurrency(limit);
+            send_information(vec![format!(
+                "cloud_walk_concurrency value changed to: {}",
+                config.get_cloud_walk_concurrency()
+            )]);
+        }
+
         ConfigTask::LoadDefault => match config.restore_default() {
             true => send_information(vec![format!("Default configuration has been restored")]),
             false => send_information(vec![format!(
@@ -436,6 +1215,28 @@ pub fn config(path: &str, config_task: ConfigTask) {
     };
 }
 
+/// Reports how many crypts and tokens currently live in the database, and
+/// which files a database purge would orphan, without deleting anything.
+fn preview_purge_database() -> (Vec<FileCrypt>, Vec<UserToken>) {
+    let crypts = query_keeper_crypt().unwrap_or_default();
+    let tokens = query_keeper_token().unwrap_or_default();
+
+    let mut lines = vec![format!(
+        "purging the database would remove {} crypt(s) and {} token(s):",
+        crypts.len(),
+        tokens.len()
+    )];
+    lines.extend(
+        crypts
+            .iter()
+            .map(|fc| format!("  {}", fc.full_path.display())),
+    );
+    lines.extend(tokens.iter().map(|t| format!("  {} token", t.service)));
+    send_information(lines);
+
+    (crypts, tokens)
+}
+
 pub fn keeper(kc: &KeeperCommand) {
     match kc {
         KeeperCommand::Import { path } => {
@@ -450,8 +1251,23 @@ pub fn keeper(kc: &KeeperCommand) {
             };
         }
         KeeperCommand::Purge { category } => match category {
-            Some(Token {}) => purge_tokens(),
-            Some(Database {}) => {
+            Some(Token { dry_run }) => {
+                let tokens = query_keeper_token().unwrap_or_default();
+                send_information(vec![format!(
+                    "purging tokens would remove {} token(s)",
+                    tokens.len()
+                )]);
+                if *dry_run {
+                    return;
+                }
+                purge_tokens();
+            }
+            Some(Database { dry_run }) => {
+                preview_purge_database();
+                if *dry_run {
+                    return;
+                }
+
                 send_information(vec![
                     format!("==================== WARNING ===================="),
                     format!("DOING THIS WILL IRREVERSIBLY DELETE YOUR DATABASE\n"),
@@ -479,22 +1295,149 @@ pub fn keeper(kc: &KeeperCommand) {
             }
             None => send_information(vec![format!("invalid entry entered.")]),
         },
+        KeeperCommand::Compact {} => {
+            let path = config::get_config().database_path;
+            let before = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            match db::vacuum() {
+                Ok(_) => {
+                    let after = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                    send_information(vec![format!(
+                        "database compacted: {} bytes -> {} bytes ({} bytes reclaimed)",
+                        before,
+                        after,
+                        before.saturating_sub(after)
+                    )]);
+                }
+                Err(e) => send_information(vec![format!("compact failed: {}", e)]),
+            }
+        }
+        KeeperCommand::AuditNonces {} => match query_keeper_crypt() {
+            Ok(crypts) => {
+                let reused = find_reused_nonces(&crypts);
+                if reused.is_empty() {
+                    send_information(vec![format!(
+                        "audit-nonces: no reused (key, nonce) pairs found across {} crypts.",
+                        crypts.len()
+                    )]);
+                } else {
+                    send_information(vec![
+                        format!("==================== WARNING ===================="),
+                        format!(
+                            "audit-nonces: found {} reused (key, nonce) pair(s) -- this indicates an RNG failure!",
+                            reused.len()
+                        ),
+                    ]);
+                    for group in reused {
+                        let uuids: Vec<String> = group.into_iter().map(|fc| fc.uuid).collect();
+                        send_information(vec![format!("  shared by: {}", uuids.join(", "))]);
+                    }
+                }
+            }
+            Err(e) => send_information(vec![format!("audit-nonces failed: {}", e)]),
+        },
+        KeeperCommand::SetPath { uuid, path } => {
+            let new_path = Path::new(path);
+            if !new_path.exists() {
+                send_information(vec![format!("path does not exist: {}", path)]);
+                return;
+            }
+            // accept a uuid prefix (like a git short hash) instead of the full 36 chars
+            let fc = match db::query_crypt_by_prefix(uuid) {
+                Ok(fc) => fc,
+                Err(e) => {
+                    send_information(vec![format!("set-path failed: {}", e)]);
+                    return;
+                }
+            };
+            match db::update_full_path(&fc.uuid, new_path) {
+                Ok(_) => send_information(vec![format!(
+                    "updated full_path for {} to {}",
+                    fc.uuid, path
+                )]),
+                Err(e) => send_information(vec![format!("set-path failed: {}", e)]),
+            }
+        }
+        KeeperCommand::Orphans {} => match db::find_orphaned_crypts() {
+            Ok(orphans) if orphans.is_empty() => {
+                send_information(vec![format!("orphans: no unrecognized .crypt files found.")]);
+            }
+            Ok(orphans) => {
+                send_information(vec![format!(
+                    "orphans: found {} .crypt file(s) with no matching keeper row -- import a keeper export (`keeper import`) containing these uuids to recover their keys:",
+                    orphans.len()
+                )]);
+                for orphan in orphans {
+                    send_information(vec![format!(
+                        "  {} ({})",
+                        orphan.path.display(),
+                        orphan.uuid
+                    )]);
+                }
+            }
+            Err(e) => send_information(vec![format!("orphans failed: {}", e)]),
+        },
+        KeeperCommand::RotateTokenKeys {} => match rotate_token_keys() {
+            Ok(rotated) => send_information(vec![format!(
+                "rotate-token-keys: rotated {} token(s).",
+                rotated
+            )]),
+            Err(e) => send_information(vec![format!("rotate-token-keys failed: {}", e)]),
+        },
         //List
         KeeperCommand::List {} => {
             let fc = query_keeper_crypt().unwrap();
             for crypt in fc {
                 println!(
-                    "file: {}{} \nfull file path: {}\ncloud location: {}\n",
+                    "file: {}{} \nfull file path: {}\ncloud location: {}\ndetected type: {}\n",
                     crypt.filename,
                     crypt.ext,
                     crypt.full_path.display(),
                     crypt.drive_id,
+                    crypt.file_type.as_deref().unwrap_or("unknown"),
                 );
             }
         }
     }
 }
 
+/// Tars the crypt folder and keeper database into a single portable
+/// `.cryptbundle` file at `out_path`, optionally encrypting it with a passphrase.
+pub fn export_bundle(out_path: &str, passphrase: Option<&str>) -> Result<()> {
+    let config = config::get_config();
+    bundle_export(
+        &get_crypt_folder(),
+        Path::new(config.get_database_path()),
+        Path::new(out_path),
+        passphrase,
+    )?;
+    send_information(vec![format!("bundle exported to: {}", out_path)]);
+    Ok(())
+}
+
+/// Restores a bundle produced by `export_bundle` into `target_dir`.
+pub fn import_bundle(path: &str, target_dir: &str, passphrase: Option<&str>) -> Result<()> {
+    bundle_import(Path::new(path), Path::new(target_dir), passphrase)?;
+    send_information(vec![format!("bundle restored to: {}", target_dir)]);
+    Ok(())
+}
+
+/// Writes a JSON restore index (filename, full path, extension, hash, drive_id,
+/// and size for every tracked crypt) to `out_path`, independent of the keeper
+/// database. `with_keys` additionally includes key/nonce material so the
+/// manifest alone is enough for full recovery -- which also makes it as
+/// sensitive as the keeper database, so it's off by default.
+pub fn export_manifest(out_path: &str, with_keys: bool) -> Result<()> {
+    if with_keys {
+        send_information(vec![format!(
+            "WARNING: --with-keys includes decryption key material -- store {} as securely as the keeper database",
+            out_path
+        )]);
+    }
+    export_manifest_db(Some(out_path), with_keys)?;
+    send_information(vec![format!("manifest exported to: {}", out_path)]);
+    Ok(())
+}
+
 // Function to write the file to the base file path
 pub fn merge_base_with_relative_path(
     base_path: &Path,
@@ -532,6 +1475,178 @@ pub fn merge_base_with_relative_path(
     Ok(target_path)
 }
 
+/// Compares the plaintext at `plaintext_path` against the content hash stored
+/// for the crypt referenced by `uuid_or_crypt` (either a uuid/uuid-prefix, or
+/// the path to the `.crypt` file itself), without touching the `.crypt`'s
+/// encrypted contents at all.
+pub fn compare(plaintext_path: &str, uuid_or_crypt: &str) {
+    let crypt_path = Path::new(uuid_or_crypt);
+    let lookup = if crypt_path.exists() {
+        get_uuid_from_file(crypt_path)
+    } else {
+        Ok(uuid_or_crypt.to_string())
+    };
+
+    let fc = match lookup.and_then(|uuid| db::query_crypt_by_prefix(&uuid)) {
+        Ok(fc) => fc,
+        Err(e) => {
+            send_information(vec![format!("compare failed: {}", e)]);
+            return;
+        }
+    };
+
+    match compare_to_stored_hash(plaintext_path, &fc) {
+        Ok(true) => send_information(vec![format!(
+            "compare: {} matches the stored hash for {}",
+            plaintext_path, fc.uuid
+        )]),
+        Ok(false) => send_information(vec![format!(
+            "compare: {} does NOT match the stored hash for {} -- the file has changed since it was encrypted",
+            plaintext_path, fc.uuid
+        )]),
+        Err(e) => send_information(vec![format!("compare failed: {}", e)]),
+    }
+}
+
+// ############################################ Corruption Scan ############################################
+
+/// Outcome of checking a single tracked crypt during a [`scan`] sweep.
+#[derive(Debug, Clone, serde::Serialize)]
+pub enum ScanStatus {
+    /// Decrypted, decompressed, and rehashed cleanly -- matches the stored hash.
+    Ok,
+    /// The `.crypt` couldn't be located (missing locally, or not found on Drive with `--cloud`).
+    Missing,
+    /// Decrypt/decompress/hash-check failed -- the crypt is corrupt.
+    Corrupt(String),
+}
+
+/// Per-file result of a [`scan`] sweep.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ScanEntry {
+    pub uuid: String,
+    pub filename: String,
+    pub status: ScanStatus,
+}
+
+/// Report produced by [`scan`]: one [`ScanEntry`] per crypt tracked in the
+/// keeper.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ScanReport {
+    pub entries: Vec<ScanEntry>,
+}
+
+impl ScanReport {
+    /// Number of entries whose `.crypt` is missing or failed to reproduce its stored hash.
+    pub fn corrupt_count(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| !matches!(e.status, ScanStatus::Ok))
+            .count()
+    }
+}
+
+impl std::fmt::Display for ScanReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for entry in &self.entries {
+            let status = match &entry.status {
+                ScanStatus::Ok => "ok".to_string(),
+                ScanStatus::Missing => "missing".to_string(),
+                ScanStatus::Corrupt(reason) => format!("corrupt: {}", reason),
+            };
+            writeln!(f, "[{}] {} ({})", status, entry.filename, entry.uuid)?;
+        }
+        write!(
+            f,
+            "scan: {} checked, {} flagged",
+            self.entries.len(),
+            self.corrupt_count()
+        )
+    }
+}
+
+/// Sweeps every crypt tracked in the keeper: locates its `.crypt` (locally,
+/// or downloaded from Drive if `cloud` is set), fully decrypts and
+/// decompresses it, and confirms the recomputed hash still matches the one on
+/// record. This is [`verify_encrypted_write`] -- the same round-trip
+/// `encrypt_file` runs right after writing when `verify_on_encrypt` is set --
+/// extended to the whole keeper, so corruption a crypt develops long after it
+/// was written (bit rot, a bad sync, a truncated cloud upload) is caught by a
+/// scheduled sweep instead of only at decrypt time.
+pub fn scan(cloud: bool) -> Result<ScanReport> {
+    let crypts = query_keeper_crypt()?;
+    let google = if cloud { Some(Google::new(None)?) } else { None };
+
+    let entries = crypts
+        .into_iter()
+        .map(|fc| {
+            let status = match &google {
+                Some(google) => scan_cloud_crypt(&fc, google),
+                None => scan_local_crypt(&fc),
+            };
+            ScanEntry {
+                uuid: fc.uuid.clone(),
+                filename: format!("{}{}", fc.filename, fc.ext),
+                status,
+            }
+        })
+        .collect();
+
+    Ok(ScanReport { entries })
+}
+
+/// Checks the `.crypt` already sitting in the local crypt folder.
+fn scan_local_crypt(fc: &FileCrypt) -> ScanStatus {
+    let mut path = get_crypt_folder();
+    path.push(format!("{}.crypt", fc.filename));
+
+    if !path.exists() {
+        return ScanStatus::Missing;
+    }
+
+    match verify_encrypted_write(fc, &path) {
+        Ok(()) => ScanStatus::Ok,
+        Err(e) => ScanStatus::Corrupt(e.to_string()),
+    }
+}
+
+/// Downloads the `.crypt` from Drive to a scratch temp file (in the same temp
+/// folder [`google_download`] uses, named `<uuid>.crypt.scan` rather than its
+/// `<uuid>.crypt.partial`) and checks it there, so nothing under `--cloud`
+/// touches the local crypt folder.
+fn scan_cloud_crypt(fc: &FileCrypt, google: &Google) -> ScanStatus {
+    let tmp_download = match common::TempFileGuard::new(&format!("{}.crypt.scan", fc.uuid)) {
+        Ok(guard) => guard,
+        Err(e) => return ScanStatus::Corrupt(e.to_string()),
+    };
+
+    if google
+        .runtime
+        .block_on(drive::download_to_file(
+            &google.token,
+            &fc.drive_id,
+            tmp_download.path(),
+        ))
+        .is_err()
+    {
+        return ScanStatus::Missing;
+    }
+
+    let bytes = match std::fs::read(tmp_download.path()) {
+        Ok(bytes) => bytes,
+        Err(e) => return ScanStatus::Corrupt(e.to_string()),
+    };
+
+    if let Err(e) = verify_downloaded_crypt(bytes, fc) {
+        return ScanStatus::Corrupt(e.to_string());
+    }
+
+    match verify_encrypted_write(fc, tmp_download.path()) {
+        Ok(()) => ScanStatus::Ok,
+        Err(e) => ScanStatus::Corrupt(e.to_string()),
+    }
+}
+
 pub fn ls(local: &bool, cloud: &bool) {
     let crypt_root = get_crypt_folder();
 
@@ -574,3 +1689,163 @@ pub fn test() {
     let res = chooser("");
     println!("{:#?}", res);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ensure_non_empty_errors_on_empty_download() {
+        let err = ensure_non_empty(vec![], "some-drive-id").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::DownloadError(error::DownloadError::EmptyResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_ensure_non_empty_passes_through_non_empty_bytes() {
+        let bytes = ensure_non_empty(vec![1, 2, 3], "some-drive-id").unwrap();
+        assert_eq!(bytes, vec![1, 2, 3]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_group_hardlinked_files_dedupes_shared_inode() {
+        let dir = std::env::temp_dir().join("crypt_directive_hardlink_test");
+        _ = std::fs::create_dir_all(&dir);
+        let primary = dir.join("primary.txt");
+        let linked = dir.join("linked.txt");
+        let unrelated = dir.join("unrelated.txt");
+        std::fs::write(&primary, b"shared content").unwrap();
+        std::fs::hard_link(&primary, &linked).unwrap();
+        std::fs::write(&unrelated, b"different content").unwrap();
+
+        let paths = vec![primary.clone(), linked.clone(), unrelated.clone()];
+        let groups = group_hardlinked_files(&paths);
+
+        assert_eq!(groups.get(&primary), Some(&vec![linked.clone()]));
+        assert!(!groups.contains_key(&unrelated));
+
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_verify_downloaded_crypt_passes_matching_uuid() {
+        use crypt_cloud::crypt_core::filecrypt::prepend_uuid;
+
+        let fc = FileCrypt::new(
+            "f".to_string(),
+            ".txt".to_string(),
+            "drive123".to_string(),
+            PathBuf::from("/tmp/f.txt"),
+            [0u8; 32],
+        );
+        let bytes = prepend_uuid(&fc.uuid, &mut b"ciphertext".to_vec());
+
+        let verified = verify_downloaded_crypt(bytes.clone(), &fc).unwrap();
+        assert_eq!(verified, bytes);
+    }
+
+    #[test]
+    fn test_verify_downloaded_crypt_detects_uuid_mismatch() {
+        use crypt_cloud::crypt_core::filecrypt::prepend_uuid;
+
+        let fc = FileCrypt::new(
+            "f".to_string(),
+            ".txt".to_string(),
+            "drive123".to_string(),
+            PathBuf::from("/tmp/f.txt"),
+            [0u8; 32],
+        );
+        let bytes = prepend_uuid("00000000-0000-0000-0000-000000000000", &mut b"ciphertext".to_vec());
+
+        let err = verify_downloaded_crypt(bytes, &fc).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::DownloadError(error::DownloadError::CorruptDownload(_, _, _))
+        ));
+    }
+
+    #[test]
+    fn test_resume_state_survives_simulated_interruption() {
+        let dir = std::env::temp_dir().join("crypt_directive_resume_test");
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // simulate a batch encrypt interrupted after the first of two files finished
+        let mut state = load_resume_state(&dir);
+        assert!(state.completed.is_empty());
+        state.completed.insert("a.txt".to_string(), [1u8; 32]);
+        save_resume_state(&dir, &state);
+
+        // re-running "resumes" by reloading whatever was on disk when it died
+        let resumed = load_resume_state(&dir);
+        assert_eq!(resumed.completed.get("a.txt"), Some(&[1u8; 32]));
+        assert!(!resumed.completed.contains_key("b.txt"));
+
+        // a completed run clears the state so the next invocation starts fresh
+        clear_resume_state(&dir);
+        assert!(load_resume_state(&dir).completed.is_empty());
+
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_scan_report_corrupt_count_counts_non_ok_entries() {
+        let report = ScanReport {
+            entries: vec![
+                ScanEntry {
+                    uuid: "a".to_string(),
+                    filename: "a.txt".to_string(),
+                    status: ScanStatus::Ok,
+                },
+                ScanEntry {
+                    uuid: "b".to_string(),
+                    filename: "b.txt".to_string(),
+                    status: ScanStatus::Missing,
+                },
+                ScanEntry {
+                    uuid: "c".to_string(),
+                    filename: "c.txt".to_string(),
+                    status: ScanStatus::Corrupt("hash mismatch".to_string()),
+                },
+            ],
+        };
+
+        assert_eq!(report.corrupt_count(), 2);
+    }
+
+    #[test]
+    fn test_scan_flags_a_crypt_with_no_file_on_disk_as_missing() {
+        let fc = FileCrypt {
+            uuid: "scan-missing-test-uuid".to_string(),
+            filename: "scan_missing_test".to_string(),
+            ..Default::default()
+        };
+        db::insert_crypt(&fc).unwrap();
+
+        let report = scan(false).unwrap();
+
+        let entry = report.entries.iter().find(|e| e.uuid == fc.uuid).unwrap();
+        assert!(matches!(entry.status, ScanStatus::Missing));
+
+        db::delete_crypt(fc.uuid).unwrap();
+    }
+
+    #[test]
+    fn test_preview_purge_database_reports_existing_crypts() {
+        let fc = FileCrypt {
+            uuid: "preview-purge-test-uuid".to_string(),
+            full_path: PathBuf::from("/preview/purge/file.txt"),
+            ..Default::default()
+        };
+        db::insert_crypt(&fc).unwrap();
+
+        let (crypts, _tokens) = preview_purge_database();
+
+        assert!(crypts.iter().any(|c| c.uuid == fc.uuid));
+
+        db::delete_crypt(fc.uuid).unwrap();
+    }
+}
@@ -17,6 +17,9 @@ pub enum Error {
     #[error(transparent)]
     UploadError(#[from] UploadError),
 
+    #[error(transparent)]
+    DownloadError(#[from] DownloadError),
+
     #[error(transparent)]
     IoError(#[from] std::io::Error),
 
@@ -41,7 +44,27 @@ pub enum UploadError {
     /// Generated if no crypt files exist within the directory provided.
     #[error("no files were found in the directory provided")]
     NoCryptFilesFound,
+
+    /// Generated when `google_link` is asked to link to a drive id that either
+    /// doesn't exist, is trashed, or doesn't match the local file's name.
+    #[error("drive id {0} does not point to a file matching '{1}' -- refusing to link")]
+    LinkMismatch(String, String),
+
+    /// Generated when a `google_upload` run's total size wouldn't fit in the
+    /// account's remaining Drive quota.
+    #[error("upload needs {0} bytes but only {1} bytes are free in Drive -- aborting")]
+    QuotaExceeded(u64, u64),
 }
 
 #[derive(Debug, Error)]
-pub enum DownloadError {}
+pub enum DownloadError {
+    /// Generated when the cloud drive returns zero bytes for a file, e.g. a
+    /// stale/trashed `drive_id` or a transient network hiccup.
+    #[error("received an empty response downloading drive id {0} -- the file may have been trashed or moved")]
+    EmptyResponse(String),
+
+    /// Generated when a downloaded `.crypt.partial`'s leading uuid doesn't match
+    /// the `FileCrypt` we expected to decrypt -- the download is truncated/corrupt.
+    #[error("downloaded file for drive id {0} is corrupt: expected uuid {1}, found {2}")]
+    CorruptDownload(String, String, String),
+}
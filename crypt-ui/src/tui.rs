@@ -1,315 +1,248 @@
-// use anyhow::Ok;
-// use crossterm::{
-//     event::{self, KeyCode},
-//     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-//     ExecutableCommand,
-// };
-// use crypt_cloud::crypt_core::common::DirInfo;
-// use ratatui::{prelude::*, widgets::*};
-// use std::io::stdout;
-
-// ///Tracks cursor state
-// pub struct Cursor {
-//     ///Index of selected area per section
-//     pub selected: [usize; 3],
-//     ///Index of current section
-//     pub section: usize,
-// }
-
-// ///Loads the TUI
-// pub fn load_tui() -> anyhow::Result<()> {
-//     //Set up the interface
-//     enable_raw_mode()?;
-//     stdout().execute(EnterAlternateScreen)?;
-//     let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
-//     terminal.clear()?;
-
-//     let mut should_quit = false;
-//     let mut cursor = Cursor {
-//         selected: [0, 0, 0],
-//         section: 0,
-//     };
-
-//     while !should_quit {
-//         //Draw terminal
-//         terminal.draw(|frame| draw_ui(frame, &cursor))?;
-//         should_quit = event_handler(&mut cursor)?;
-//     }
-
-//     //Close out of the interface
-//     stdout().execute(LeaveAlternateScreen)?;
-//     disable_raw_mode()?;
-
-//     Ok(())
-// }
-
-// ///Create the UI
-// fn draw_ui(frame: &mut Frame, cursor: &Cursor) {
-//     //Create a main layout
-//     let main_layout = Layout::default()
-//         .direction(Direction::Vertical)
-//         .constraints([
-//             Constraint::Length(1),
-//             Constraint::Min(6),
-//             Constraint::Percentage(75),
-//             Constraint::Min(1),
-//         ])
-//         .split(frame.size());
-
-//     //Title bar
-//     frame.render_widget(
-//         Block::new().borders(Borders::TOP).title("ByteCrypt").cyan(),
-//         main_layout[0],
-//     );
-
-//     //Primary Section
-//     let interaction_layout = Layout::default()
-//         .direction(Direction::Horizontal)
-//         .constraints([Constraint::Percentage(35), Constraint::Percentage(65)])
-//         .split(main_layout[1]);
-
-//     //Menu layout
-//     let menu_layout = Layout::default()
-//         .direction(Direction::Horizontal)
-//         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-//         .split(interaction_layout[0]);
-
-//     //Sub menu on the left side of the menu layout
-//     let sub_menu_left = Layout::default()
-//         .direction(Direction::Vertical)
-//         .constraints([Constraint::Min(3), Constraint::Min(3)])
-//         .split(menu_layout[0]);
-
-//     //Sub menu on the right side of the menu layout
-//     let sub_menu_right = Layout::default()
-//         .direction(Direction::Vertical)
-//         .constraints([Constraint::Min(3), Constraint::Min(3)])
-//         .split(menu_layout[1]);
-
-//     //Create and implement the buttons
-//     let button_text = ["Option 1", "Option 2", "Option 3", "Option 4"];
-//     let sub_menu = [
-//         sub_menu_left[0],
-//         sub_menu_left[1],
-//         sub_menu_right[0],
-//         sub_menu_right[1],
-//     ];
-
-//     for (button, &button_text) in button_text.iter().enumerate() {
-//         let outer_block = Block::default().borders(Borders::ALL).fg(Color::Magenta);
-
-//         let inner_style = if cursor.selected[0] == button {
-//             Style::default().fg(Color::White).bg(Color::Magenta)
-//         } else {
-//             Style::default().fg(Color::White)
-//         };
-
-//         let inner_paragraph = Paragraph::new(button_text)
-//             .alignment(Alignment::Center)
-//             .style(inner_style);
-
-//         frame.render_widget(outer_block, sub_menu[button]);
-
-//         let inner_area = {
-//             let mut area = sub_menu[button];
-//             area.height = area.height.saturating_sub(2);
-//             area.width = area.width.saturating_sub(2);
-//             area.x += 1;
-//             area.y += 1;
-//             area
-//         };
-
-//         frame.render_widget(inner_paragraph, inner_area);
-//     }
-
-//     //Information Display
-//     let button_info = [
-//         "Menu Option 1 Info",
-//         "Menu Option 2 Info",
-//         "Menu Option 3 Info",
-//         "Menu Option 4 Info",
-//     ];
-
-//     let info_window = Paragraph::new(button_info[cursor.selected[0]])
-//         .block(
-//             Block::default()
-//                 .borders(Borders::ALL)
-//                 .border_style(Style::default().fg(Color::Magenta))
-//                 .title(" Information ")
-//                 .title_style(Style::default().fg(Color::Blue)),
-//         )
-//         .white()
-//         .alignment(Alignment::Left)
-//         .wrap(Wrap { trim: true });
-
-//     frame.render_widget(info_window, interaction_layout[1]);
-
-//     //Directory Layout
-//     let directory_layout = Layout::default()
-//         .direction(Direction::Horizontal)
-//         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-//         .split(main_layout[2]);
-
-//     //Left Directory
-//     let _current_directory = std::env::current_dir().expect("Failed to get current directory");
-//     let _directory_tree = DirInfo::default(); //generate_directory(&current_directory).unwrap(); // TODO: FIX
-//     let formatted_tree = Text::default(); // format_directory(&directory_tree, 0, cursor); // TODO: FIX
-//                                           //let left_directory = Paragraph::new(formatted_tree);
-
-//     let left_directory = Paragraph::new(formatted_tree)
-//         .block(
-//             Block::default()
-//                 .borders(Borders::ALL)
-//                 .border_style(Style::default().fg(Color::Magenta))
-//                 .title(" Left Directory ")
-//                 .title_style(Style::default().fg(Color::Blue))
-//                 .white(),
-//         )
-//         .alignment(Alignment::Left)
-//         .wrap(Wrap { trim: false })
-//         .scroll((0, 0));
-
-//     frame.render_widget(left_directory, directory_layout[0]);
-
-//     //Right Directory
-//     frame.render_widget(
-//         Block::default()
-//             .borders(Borders::ALL)
-//             .title(" Right Directory ")
-//             .magenta(),
-//         directory_layout[1],
-//     );
-
-//     //Add the status bar at the bottom of the main_layout
-//     frame.render_widget(
-//         Block::new()
-//             .borders(Borders::TOP)
-//             .title("Footer Bar ")
-//             .cyan(),
-//         main_layout[3],
-//     );
-// }
-
-// ///Handles input events for the TUI
-// fn event_handler(cursor: &mut Cursor) -> anyhow::Result<bool> {
-//     //16ms ~60fps
-//     if event::poll(std::time::Duration::from_millis(16))? {
-//         if let event::Event::Key(key) = event::read()? {
-//             match key.code {
-//                 KeyCode::Tab => {
-//                     cursor.section = (cursor.section + 1) % 3;
-//                 }
-//                 KeyCode::Up => {
-//                     if cursor.section == 0 && cursor.selected[0] % 2 > 0 {
-//                         cursor.selected[0] -= 1;
-//                     }
-//                     if cursor.section == 1 && cursor.selected[1] > 0 {
-//                         cursor.selected[1] -= 1;
-//                     }
-//                 }
-//                 KeyCode::Left => {
-//                     if cursor.selected[0] > 1 {
-//                         cursor.selected[0] -= 2;
-//                     }
-//                 }
-//                 KeyCode::Down => {
-//                     if cursor.section == 0 && cursor.selected[0] % 2 == 0 {
-//                         cursor.selected[0] += 1;
-//                     }
-//                     if cursor.section == 1 {
-//                         cursor.selected[1] += 1;
-//                     }
-//                 }
-//                 KeyCode::Right => {
-//                     if cursor.selected[0] < 2 {
-//                         cursor.selected[0] += 2;
-//                     }
-//                 }
-//                 KeyCode::Enter => {
-//                     //Key action for enter here
-//                     if cursor.section == 1 {
-//                         //expand/collapse directories
-//                     }
-//                 }
-//                 KeyCode::Char('q') => return Ok(true),
-//                 _ => {}
-//             }
-//         }
-//     }
-
-//     Ok(false)
-// }
-
-// // TODO: Fix, BROKEN
-
-// //Takes in the current directory and formats it into a string
-// // pub fn format_directory<'a>(directory: &DirInfo, depth: usize, cursor: &Cursor) -> Text<'a> {
-// //     let char_set = CharacterSet::U8_SLINE;
-// //     let mut lines: Vec<Line> = Vec::new();
-// //     let mut line_span: Vec<Span> = Vec::new();
-
-// //     let mut result = String::new();
-// //     //Root directory
-// //     if depth == 0 {
-// //         result.push_str( {"{}\n",
-// //             directory.path.full_path.file_name().unwrap().to_str().unwrap()
-// //         });
-// //     }
-// //     line_span.push(Span::raw(result));
-// //     lines.push(Line::from(line_span));
-
-// //     //Traverse through the directory and build the string to display
-// //     for (index, entity) in directory.contents.iter().enumerate() {
-// //         let is_selected = index == cursor.selected[1];
-// //         let mut line_spans: Vec<Span> = Vec::new();
-
-// //         //set up for last entity
-// //         let last_entity = index == directory.contents.len() - 1;
-// //         let connector = if last_entity {
-// //             char_set.node
-// //         } else {
-// //             char_set.joint
-// //         };
-
-// //         let mut prefix = String::new();
-// //         if depth == 0 {
-// //             //for item that immediately follows root contents
-// //             prefix.push_str(("{}", connector));
-// //         }
-// //         if depth > 0 {
-// //             //Non-root
-// //             prefix.push_str(&" ".repeat(depth * 4));
-// //             prefix.push_str( {"{}", connector});
-// //         }
-
-// //         let text = match entity {
-// //             FileSystemEntity::File(path) => path
-// //                 .full_path
-// //                 .file_name()
-// //                 .unwrap()
-// //                 .to_str()
-// //                 .unwrap()
-// //                 .to_string(),
-// //             FileSystemEntity::Directory(dir) => dir
-// //                 .path
-// //                 .parent
-// //                 .file_name()
-// //                 .unwrap()
-// //                 .to_str()
-// //                 .unwrap()
-// //                 .to_string(),
-// //         };
-
-// //         //Styles for selected items
-// //         let selected_text = if is_selected {
-// //             Span::styled(text, Style::new().bg(Color::Magenta).fg(Color::White))
-// //         } else {
-// //             Span::raw(text)
-// //         };
-
-// //         line_spans.push(Span::raw(prefix));
-// //         line_spans.push(selected_text);
-// //         lines.push(Line::from(line_spans));
-// //     }
-// //     Text::from(lines)
-// // }
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crypt_cloud::crypt_core::{
+    common::{get_crypt_folder, walk_crypt_folder},
+    db::query_crypt,
+    filecrypt::{decrypt_file, get_uuid_from_file},
+};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    Frame,
+};
+
+use crate::prelude::*;
+
+/// A single visible row in the browse tree.
+struct Row {
+    path: PathBuf,
+    is_dir: bool,
+    depth: usize,
+}
+
+/// State for the browse-and-decrypt TUI.
+struct App {
+    crypt_folder: PathBuf,
+    expanded: HashSet<PathBuf>,
+    rows: Vec<Row>,
+    list_state: ListState,
+    status: String,
+    should_quit: bool,
+}
+
+impl App {
+    fn new(crypt_folder: PathBuf) -> Self {
+        let mut app = Self {
+            crypt_folder,
+            expanded: HashSet::new(),
+            rows: Vec::new(),
+            list_state: ListState::default(),
+            status: String::from("↑/↓ move · enter expand/decrypt · q quit"),
+            should_quit: false,
+        };
+        app.refresh();
+        app.list_state.select(Some(0));
+        app
+    }
+
+    /// Re-walks the real crypt folder and rebuilds the flattened, visible row
+    /// list from the current `expanded` set.
+    fn refresh(&mut self) {
+        let (files, folders) = walk_crypt_folder().unwrap_or_default();
+        let folder_set: HashSet<PathBuf> = folders.into_iter().collect();
+
+        let mut children: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+        for path in files.iter().chain(folder_set.iter()) {
+            if let Some(parent) = path.parent() {
+                children.entry(parent.to_path_buf()).or_default().push(path.clone());
+            }
+        }
+        for kids in children.values_mut() {
+            kids.sort();
+        }
+
+        let mut rows = Vec::new();
+        Self::flatten(&self.crypt_folder, &children, &folder_set, &self.expanded, 0, &mut rows);
+        self.rows = rows;
+
+        if let Some(selected) = self.list_state.selected() {
+            if selected >= self.rows.len() {
+                self.list_state.select(self.rows.len().checked_sub(1));
+            }
+        }
+    }
+
+    fn flatten(
+        dir: &Path,
+        children: &HashMap<PathBuf, Vec<PathBuf>>,
+        folders: &HashSet<PathBuf>,
+        expanded: &HashSet<PathBuf>,
+        depth: usize,
+        out: &mut Vec<Row>,
+    ) {
+        let Some(kids) = children.get(dir) else {
+            return;
+        };
+        for child in kids {
+            let is_dir = folders.contains(child);
+            out.push(Row {
+                path: child.clone(),
+                is_dir,
+                depth,
+            });
+            if is_dir && expanded.contains(child) {
+                Self::flatten(child, children, folders, expanded, depth + 1, out);
+            }
+        }
+    }
+
+    fn selected_row(&self) -> Option<&Row> {
+        self.list_state.selected().and_then(|i| self.rows.get(i))
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, self.rows.len() as isize - 1);
+        self.list_state.select(Some(next as usize));
+    }
+
+    /// Enter: expand/collapse a directory, or decrypt a `.crypt` file.
+    fn activate_selected(&mut self) {
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+
+        if row.is_dir {
+            let path = row.path.clone();
+            if !self.expanded.remove(&path) {
+                self.expanded.insert(path);
+            }
+            self.refresh();
+            return;
+        }
+
+        if row.path.extension().and_then(|e| e.to_str()) != Some("crypt") {
+            self.status = format!("{} is not a .crypt file", row.path.display());
+            return;
+        }
+
+        let path = row.path.clone();
+        let friendly_name = get_uuid_from_file(&path)
+            .and_then(query_crypt)
+            .map(|fc| fc.full_path.display().to_string())
+            .unwrap_or_else(|_| path.display().to_string());
+
+        match decrypt_file(path.to_str().unwrap_or_default(), String::new(), true, false) {
+            Ok(()) => self.status = format!("decrypted {}", friendly_name),
+            Err(e) => self.status = format!("failed to decrypt {}: {}", friendly_name, e),
+        }
+    }
+
+    fn on_key(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => self.should_quit = true,
+            KeyCode::Up => self.move_selection(-1),
+            KeyCode::Down => self.move_selection(1),
+            KeyCode::Enter => self.activate_selected(),
+            _ => {}
+        }
+    }
+}
+
+/// Launches the interactive browse-and-decrypt TUI, rooted at the real crypt
+/// folder. Arrow keys move the selection, Enter expands/collapses a
+/// directory or decrypts a selected `.crypt` file, and `q`/Esc quits.
+pub fn load_tui() -> Result<()> {
+    let mut terminal = ratatui::try_init()?;
+    let mut app = App::new(get_crypt_folder());
+
+    while !app.should_quit {
+        terminal.draw(|frame| draw_ui(frame, &mut app))?;
+
+        if event::poll(std::time::Duration::from_millis(100))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    app.on_key(key.code);
+                }
+            }
+        }
+    }
+
+    ratatui::try_restore()?;
+    Ok(())
+}
+
+fn draw_ui(frame: &mut Frame, app: &mut App) {
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(3), Constraint::Length(1)])
+        .split(frame.area());
+
+    frame.render_widget(title_bar(), layout[0]);
+    render_tree(frame, app, layout[1]);
+    frame.render_widget(status_bar(&app.status), layout[2]);
+}
+
+fn title_bar() -> Paragraph<'static> {
+    Paragraph::new("ByteCrypt").style(Style::default().fg(Color::Cyan))
+}
+
+fn status_bar(status: &str) -> Paragraph<'_> {
+    Paragraph::new(status)
+        .style(Style::default().fg(Color::DarkGray))
+        .wrap(Wrap { trim: true })
+}
+
+fn render_tree(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .rows
+        .iter()
+        .map(|row| {
+            let name = row
+                .path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            let marker = if row.is_dir {
+                if app.expanded.contains(&row.path) {
+                    "▾ "
+                } else {
+                    "▸ "
+                }
+            } else {
+                "  "
+            };
+
+            let indent = "  ".repeat(row.depth);
+            let style = if row.is_dir {
+                Style::default().fg(Color::Magenta)
+            } else {
+                Style::default()
+            };
+
+            ListItem::new(Line::from(vec![
+                Span::raw(indent),
+                Span::raw(marker),
+                Span::styled(name, style),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" {} ", app.crypt_folder.display())),
+        )
+        .highlight_style(Style::default().bg(Color::Magenta).add_modifier(Modifier::BOLD));
+
+    frame.render_stateful_widget(list, area, &mut app.list_state);
+}